@@ -0,0 +1,213 @@
+// Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+const CHECKPOINTS_DIR: &str = "checkpoints";
+const CHECKPOINTS_INDEX_PATH: &str = "index.json";
+
+/// Content of a file before it was modified by a tool call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Index of the tool call message in the session history
+    pub message_idx: usize,
+    pub path: PathBuf,
+    /// Name of the snapshot file, None if the file didn't exist before the tool call
+    pub snapshot: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Stores file snapshots of a session in `<session_dir>/checkpoints`
+pub struct CheckpointStore {
+    dir: PathBuf,
+}
+
+impl CheckpointStore {
+    pub fn new(session_dir: &Path) -> Self {
+        Self {
+            dir: session_dir.join(CHECKPOINTS_DIR),
+        }
+    }
+
+    pub fn list(&self) -> Result<Vec<Checkpoint>> {
+        let path = self.dir.join(CHECKPOINTS_INDEX_PATH);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    fn save_index(&self, checkpoints: &[Checkpoint]) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(
+            self.dir.join(CHECKPOINTS_INDEX_PATH),
+            serde_json::to_string_pretty(checkpoints)?,
+        )?;
+        Ok(())
+    }
+
+    /// Saves the current content of the file before it is modified by the tool call message
+    pub fn snapshot(&self, message_idx: usize, path: &Path) -> Result<()> {
+        let mut checkpoints = self.list()?;
+        if checkpoints
+            .iter()
+            .any(|checkpoint| checkpoint.message_idx == message_idx && checkpoint.path == path)
+        {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.dir)?;
+        let snapshot = if path.is_file() {
+            // indexes are shifted by compaction, so the name may be already taken
+            let name = (checkpoints.len()..)
+                .map(|n| format!("{}-{}", message_idx, n))
+                .find(|name| !self.dir.join(name).exists())
+                .unwrap();
+            fs::copy(path, self.dir.join(&name))?;
+            Some(name)
+        } else {
+            None
+        };
+        tracing::debug!("Checkpoint for message {}: {}", message_idx, path.display());
+        checkpoints.push(Checkpoint {
+            message_idx,
+            path: path.to_path_buf(),
+            snapshot,
+            created_at: Utc::now(),
+        });
+        self.save_index(&checkpoints)
+    }
+
+    /// Moves checkpoints after the first `removed` messages of the history were replaced
+    /// by a summary message, checkpoints of the removed messages belong to the summary
+    pub fn shift(&self, removed: usize) -> Result<()> {
+        let mut checkpoints = self.list()?;
+        if removed == 0 || checkpoints.is_empty() {
+            return Ok(());
+        }
+        for checkpoint in checkpoints.iter_mut() {
+            checkpoint.message_idx = checkpoint.message_idx.saturating_sub(removed);
+        }
+        self.save_index(&checkpoints)
+    }
+
+    /// Paths of the files restored by the rollback to the message with the given index
+    pub fn rollback_paths(&self, message_idx: usize) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for checkpoint in self.list()? {
+            if checkpoint.message_idx >= message_idx && !paths.contains(&checkpoint.path) {
+                paths.push(checkpoint.path);
+            }
+        }
+        Ok(paths)
+    }
+
+    /// Restores files to the state before the message with the given index,
+    /// returns paths of the restored files
+    pub fn rollback(&self, message_idx: usize) -> Result<Vec<PathBuf>> {
+        let checkpoints = self.list()?;
+        let (kept, rolled_back): (Vec<_>, Vec<_>) = checkpoints
+            .into_iter()
+            .partition(|checkpoint| checkpoint.message_idx < message_idx);
+        let mut restored = HashSet::new();
+        let mut restored_paths = Vec::new();
+        // the earliest snapshot of the file has its content before the message
+        for checkpoint in rolled_back.iter() {
+            if !restored.insert(checkpoint.path.clone()) {
+                continue;
+            }
+            match &checkpoint.snapshot {
+                Some(snapshot) => {
+                    if let Some(parent) = checkpoint.path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    fs::copy(self.dir.join(snapshot), &checkpoint.path)?;
+                }
+                None => {
+                    if checkpoint.path.exists() {
+                        fs::remove_file(&checkpoint.path)?;
+                    }
+                }
+            }
+            tracing::info!("Restored file {}", checkpoint.path.display());
+            restored_paths.push(checkpoint.path.clone());
+        }
+        for checkpoint in rolled_back.iter() {
+            if let Some(snapshot) = &checkpoint.snapshot {
+                fs::remove_file(self.dir.join(snapshot)).ok();
+            }
+        }
+        self.save_index(&kept)?;
+        Ok(restored_paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::test_dir;
+
+    #[test]
+    fn test_rollback() {
+        let dir = test_dir("checkpoints");
+        let store = CheckpointStore::new(&dir.join("session"));
+        let existing = dir.join("existing.txt");
+        let created = dir.join("new/created.txt");
+        fs::write(&existing, "v1").unwrap();
+
+        store.snapshot(1, &existing).unwrap();
+        fs::write(&existing, "v2").unwrap();
+        store.snapshot(3, &created).unwrap();
+        fs::create_dir_all(created.parent().unwrap()).unwrap();
+        fs::write(&created, "new").unwrap();
+        store.snapshot(5, &existing).unwrap();
+        fs::write(&existing, "v3").unwrap();
+
+        assert_eq!(
+            store.rollback_paths(0).unwrap(),
+            vec![existing.clone(), created.clone()]
+        );
+        assert_eq!(store.rollback_paths(4).unwrap(), vec![existing.clone()]);
+        assert_eq!(store.rollback(4).unwrap(), vec![existing.clone()]);
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "v2");
+        assert_eq!(store.list().unwrap().len(), 2);
+
+        assert_eq!(store.rollback(0).unwrap().len(), 2);
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "v1");
+        assert!(!created.exists());
+        assert!(store.list().unwrap().is_empty());
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_rollback_after_compaction() {
+        let dir = test_dir("checkpoints-shift");
+        let store = CheckpointStore::new(&dir.join("session"));
+        let path = dir.join("file.txt");
+        fs::write(&path, "v1").unwrap();
+        store.snapshot(3, &path).unwrap();
+        fs::write(&path, "v2").unwrap();
+        store.snapshot(9, &path).unwrap();
+        fs::write(&path, "v3").unwrap();
+
+        // messages 0..=5 are replaced by the summary, message 9 becomes message 4
+        store.shift(5).unwrap();
+        assert_eq!(
+            store
+                .list()
+                .unwrap()
+                .iter()
+                .map(|checkpoint| checkpoint.message_idx)
+                .collect::<Vec<_>>(),
+            vec![0, 4]
+        );
+        assert_eq!(store.rollback(4).unwrap(), vec![path.clone()]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "v2");
+        assert_eq!(store.rollback(0).unwrap(), vec![path.clone()]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "v1");
+        fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,407 @@
+// Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+use std::collections::HashMap;
+
+use futures::StreamExt;
+use rig::completion::CompletionError;
+use rig::message::{AssistantContent, Message, ToolResultContent, UserContent};
+use rig::OneOrMany;
+
+use crate::config::CompactionConfig;
+use crate::providers::HulyAgent;
+use crate::templates::{COMPACTION_PROMPT, COMPACTION_SUMMARY};
+
+pub const SUMMARY_SYSTEM_PROMPT: &str =
+    "You are a helpful assistant that summarizes conversations of an AI coding agent with the user.";
+
+/// Tool results are truncated only if they are larger than the limit by this margin,
+/// so already truncated results are not truncated again
+const TRUNCATION_MARGIN: usize = 100;
+const TASK_START_TAG: &str = "<task>\n";
+const TASK_END_TAG: &str = "\n</task>";
+
+/// Error messages of providers when the request doesn't fit the context window
+const CONTEXT_OVERFLOW_ERRORS: [&str; 7] = [
+    "context length",
+    "context_length",
+    "context window",
+    "maximum context",
+    "prompt is too long",
+    "too many tokens",
+    "token limit",
+];
+
+/// Keeps conversation in the model context window by truncating large tool results
+/// and summarizing older messages
+pub struct Compactor {
+    config: CompactionConfig,
+    /// Agent used for summarization, the main agent is used if not set
+    summary_agent: Option<Box<dyn HulyAgent>>,
+}
+
+pub fn is_context_overflow_error(error: &str) -> bool {
+    let error = error.to_lowercase();
+    CONTEXT_OVERFLOW_ERRORS
+        .iter()
+        .any(|pattern| error.contains(pattern))
+}
+
+fn truncate_text(text: &str, max_tokens: u32) -> Option<String> {
+    // approximately 4 characters per token, exact count is not needed for truncation
+    let mut end = (max_tokens * 4) as usize;
+    if text.len() <= end + TRUNCATION_MARGIN {
+        return None;
+    }
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    Some(format!(
+        "{}\n... [truncated {} characters] ...",
+        &text[..end],
+        text.len() - end
+    ))
+}
+
+/// Original task of the conversation, if the history was already compacted
+/// the task is restored from the summary message
+pub(crate) fn original_task(messages: &[Message]) -> String {
+    let Some(Message::User { content }) = messages.first() else {
+        return String::new();
+    };
+    let UserContent::Text(text) = content.first() else {
+        return String::new();
+    };
+    if let Some(start) = text.text.find(TASK_START_TAG) {
+        if let Some(end) = text.text[start..].find(TASK_END_TAG) {
+            return text.text[start + TASK_START_TAG.len()..start + end].to_string();
+        }
+    }
+    text.text
+}
+
+/// Renders messages as plain text entries, so they can be summarized by a model without tools
+fn transcript(messages: &[Message], max_tool_result_tokens: u32) -> Vec<String> {
+    let mut result = Vec::new();
+    for message in messages {
+        match message {
+            Message::User { content } => {
+                for item in content.iter() {
+                    match item {
+                        UserContent::Text(text) => {
+                            if !text.text.starts_with("<environment_details>") {
+                                result.push(format!("USER:\n{}", text.text));
+                            }
+                        }
+                        UserContent::ToolResult(tool_result) => {
+                            for item in tool_result.content.iter() {
+                                if let ToolResultContent::Text(text) = item {
+                                    let text = truncate_text(&text.text, max_tool_result_tokens)
+                                        .unwrap_or_else(|| text.text.clone());
+                                    result.push(format!("TOOL RESULT:\n{text}"));
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Message::Assistant { content } => {
+                for item in content.iter() {
+                    match item {
+                        AssistantContent::Text(text) => {
+                            result.push(format!("ASSISTANT:\n{}", text.text));
+                        }
+                        AssistantContent::ToolCall(tool_call) => {
+                            result.push(format!(
+                                "ASSISTANT TOOL CALL [{}]:\n{}",
+                                tool_call.function.name, tool_call.function.arguments
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    result
+}
+
+/// Groups transcript entries into chunks of at most `max_chars` characters, entries are
+/// truncated to 40% of the chunk, so every chunk holds at least two of them
+fn chunk_transcript(entries: Vec<String>, max_chars: usize) -> Vec<String> {
+    let max_entry_tokens = (max_chars / 10).min(u32::MAX as usize / 4) as u32;
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    for entry in entries {
+        let entry = truncate_text(&entry, max_entry_tokens).unwrap_or(entry);
+        if !chunk.is_empty() && chunk.len() + entry.len() + 2 > max_chars {
+            chunks.push(std::mem::take(&mut chunk));
+        }
+        if !chunk.is_empty() {
+            chunk.push_str("\n\n");
+        }
+        chunk.push_str(&entry);
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+/// Finds index of the first kept message. Kept part always starts with an assistant message,
+/// so every tool result stays next to its tool call and user/assistant messages keep alternating
+/// after the summary message.
+fn find_split(messages: &[Message], keep_last: usize) -> Option<usize> {
+    let len = messages.len();
+    let is_assistant = |idx: &usize| matches!(messages[*idx], Message::Assistant { .. });
+    let target = len.saturating_sub(keep_last).min(len.saturating_sub(1));
+    (2..=target)
+        .rev()
+        .find(is_assistant)
+        .or_else(|| (target.max(2)..len.saturating_sub(1)).find(is_assistant))
+}
+
+impl Compactor {
+    pub fn new(config: CompactionConfig, summary_agent: Option<Box<dyn HulyAgent>>) -> Self {
+        Self {
+            config,
+            summary_agent,
+        }
+    }
+
+    pub fn need_compaction(&self, tokens: u32, max_tokens: u32) -> bool {
+        self.config.enabled
+            && max_tokens > 0
+            && tokens as f32 > max_tokens as f32 * self.config.threshold
+    }
+
+    /// Truncates large tool results and removes images of older messages,
+    /// returns true if any message was changed
+    pub fn truncate_tool_results(&self, messages: &mut [Message]) -> bool {
+        let end = messages
+            .len()
+            .saturating_sub(self.config.keep_last_messages);
+        let mut changed = false;
+        for message in messages[..end].iter_mut() {
+            let Message::User { content } = message else {
+                continue;
+            };
+            let mut items = Vec::new();
+            for item in content.iter() {
+                let UserContent::ToolResult(tool_result) = item else {
+                    items.push(item.clone());
+                    continue;
+                };
+                let mut tool_result = tool_result.clone();
+                let results = tool_result
+                    .content
+                    .iter()
+                    .map(|result| match result {
+                        ToolResultContent::Text(text) => {
+                            match truncate_text(&text.text, self.config.max_tool_result_tokens) {
+                                Some(text) => {
+                                    changed = true;
+                                    ToolResultContent::text(text)
+                                }
+                                None => result.clone(),
+                            }
+                        }
+                        ToolResultContent::Image(_) => {
+                            changed = true;
+                            ToolResultContent::text("[image removed from the context]")
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                tool_result.content = OneOrMany::many(results).unwrap();
+                items.push(UserContent::ToolResult(tool_result));
+            }
+            *content = OneOrMany::many(items).unwrap();
+        }
+        changed
+    }
+
+    /// Replaces older messages with a summary, returns None if there is nothing to summarize.
+    /// The summarized part is split into chunks fitting half of the `max_tokens` context window,
+    /// summaries of the chunks are merged until they fit a single request.
+    pub async fn summarize(
+        &self,
+        agent: &dyn HulyAgent,
+        messages: &[Message],
+        max_tokens: u32,
+    ) -> Result<Option<Vec<Message>>, CompletionError> {
+        let Some(split) = find_split(messages, self.config.keep_last_messages) else {
+            return Ok(None);
+        };
+        tracing::info!("Summarize {} of {} messages", split, messages.len());
+        let agent = self.summary_agent.as_deref().unwrap_or(agent);
+        // approximately 4 characters per token, the rest of the window is left for the prompt and the summary
+        let max_chars = if max_tokens > 0 {
+            max_tokens as usize * 2
+        } else {
+            usize::MAX
+        };
+        let mut entries = transcript(&messages[..split], self.config.max_tool_result_tokens);
+        let summary = loop {
+            let chunks = chunk_transcript(entries, max_chars);
+            if chunks.len() <= 1 {
+                break summarize_text(agent, chunks.first().map_or("", String::as_str)).await?;
+            }
+            tracing::info!("Summarize {} chunks of the conversation", chunks.len());
+            entries = Vec::new();
+            for (idx, chunk) in chunks.iter().enumerate() {
+                let summary = summarize_text(agent, chunk).await?;
+                entries.push(format!("SUMMARY OF PART {}:\n{}", idx + 1, summary));
+            }
+        };
+        let summary_message = subst::substitute(
+            COMPACTION_SUMMARY,
+            &HashMap::from([
+                ("TASK", original_task(messages).as_str()),
+                ("SUMMARY", summary.as_str()),
+            ]),
+        )
+        .unwrap();
+        let mut result = vec![Message::user(summary_message)];
+        result.extend_from_slice(&messages[split..]);
+        Ok(Some(result))
+    }
+}
+
+/// Summarizes the conversation transcript in a single request
+async fn summarize_text(
+    agent: &dyn HulyAgent,
+    conversation: &str,
+) -> Result<String, CompletionError> {
+    let prompt = subst::substitute(
+        COMPACTION_PROMPT,
+        &HashMap::from([("CONVERSATION", conversation)]),
+    )
+    .unwrap();
+    let mut stream = agent
+        .send_messages(Message::user(prompt), Vec::new())
+        .await?;
+    let mut summary = String::new();
+    while let Some(result) = stream.next().await {
+        if let AssistantContent::Text(text) = result? {
+            summary.push_str(&text.text);
+        }
+    }
+    if summary.trim().is_empty() {
+        return Err(CompletionError::ResponseError(
+            "Model returned empty summary".to_string(),
+        ));
+    }
+    Ok(summary.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool_call(id: &str) -> Message {
+        Message::Assistant {
+            content: OneOrMany::one(AssistantContent::tool_call(
+                id,
+                "read_file",
+                serde_json::json!({ "path": "a.txt" }),
+            )),
+        }
+    }
+
+    fn compactor() -> Compactor {
+        Compactor::new(
+            CompactionConfig {
+                keep_last_messages: 2,
+                max_tool_result_tokens: 10,
+                ..Default::default()
+            },
+            None,
+        )
+    }
+
+    #[test]
+    fn test_find_split() {
+        let messages = vec![
+            Message::user("task"),
+            tool_call("1"),
+            Message::tool_result("1", "content"),
+            tool_call("2"),
+            Message::tool_result("2", "content"),
+            tool_call("3"),
+            Message::tool_result("3", "content"),
+        ];
+        assert_eq!(find_split(&messages, 2), Some(5));
+        assert_eq!(find_split(&messages, 3), Some(3));
+        // at least something is summarized even if history is shorter than keep_last_messages
+        assert_eq!(find_split(&messages, 10), Some(3));
+        assert_eq!(find_split(&messages[..3], 1), None);
+    }
+
+    #[test]
+    fn test_chunk_transcript() {
+        let entries = vec![
+            "a".repeat(80),
+            "b".repeat(80),
+            "c".repeat(300),
+            "d".repeat(10),
+        ];
+        let chunks = chunk_transcript(entries.clone(), 200);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0], format!("{}\n\n{}", entries[0], entries[1]));
+        // too large entry is truncated to fit the chunk with other entries
+        assert!(chunks[1].starts_with(&format!("{}\n... [truncated", "c".repeat(80))));
+        assert!(chunks[1].ends_with(&format!("\n\n{}", entries[3])));
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 200));
+        assert_eq!(chunk_transcript(entries, usize::MAX).len(), 1);
+    }
+
+    #[test]
+    fn test_truncate_tool_results() {
+        let long_result = "a".repeat(200);
+        let mut messages = vec![
+            Message::user("task"),
+            tool_call("1"),
+            Message::tool_result("1", &long_result),
+            tool_call("2"),
+            Message::tool_result("2", &long_result),
+        ];
+        assert!(compactor().truncate_tool_results(&mut messages));
+        let Message::User { content } = &messages[2] else {
+            panic!("unexpected message");
+        };
+        let UserContent::ToolResult(tool_result) = content.first() else {
+            panic!("unexpected content");
+        };
+        let ToolResultContent::Text(text) = tool_result.content.first() else {
+            panic!("unexpected tool result");
+        };
+        assert!(text.text.starts_with(&"a".repeat(40)));
+        assert!(text.text.ends_with("[truncated 160 characters] ..."));
+        // last messages are kept as is
+        assert_eq!(messages[4], Message::tool_result("2", &long_result));
+        assert!(!compactor().truncate_tool_results(&mut messages));
+    }
+
+    #[test]
+    fn test_original_task() {
+        let summary = subst::substitute(
+            COMPACTION_SUMMARY,
+            &HashMap::from([("TASK", "Build app\nin rust"), ("SUMMARY", "done")]),
+        )
+        .unwrap();
+        assert_eq!(
+            original_task(&[Message::user(summary)]),
+            "Build app\nin rust"
+        );
+        assert_eq!(original_task(&[Message::user("Build app")]), "Build app");
+    }
+
+    #[test]
+    fn test_context_overflow_error() {
+        assert!(is_context_overflow_error(
+            "ProviderError: This model's maximum context length is 128000 tokens"
+        ));
+        assert!(is_context_overflow_error(
+            "prompt is too long: 210000 tokens"
+        ));
+        assert!(!is_context_overflow_error("429 Too Many Requests"));
+    }
+}
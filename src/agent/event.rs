@@ -64,6 +64,8 @@ pub struct AgentCommandStatus {
     pub command_id: usize,
     pub command: Option<String>,
     pub output: String,
+    /// Terminal screen of the command run in a pseudo-terminal
+    pub screen: Option<Box<vt100::Screen>>,
     pub is_active: bool,
 }
 
@@ -76,6 +78,10 @@ pub enum AgentOutputEvent {
     CommandStatus(Vec<AgentCommandStatus>),
     AgentStatus(u32, u32, AgentState),
     HighlightFile(String, bool),
+    /// Whole message history is replaced (e.g. another session is opened)
+    ReplaceMessages(Vec<Message>),
+    /// Agent switched to the session with the given id
+    SessionChanged(String),
 }
 
 #[derive(Clone, Debug)]
@@ -83,6 +89,8 @@ pub enum ConfirmToolResponse {
     Approve,
     Deny,
     AlwaysApprove,
+    /// Approves the tool call with arguments edited by the user
+    Edit(serde_json::Value),
 }
 
 /// Controls events that are sent to the agent
@@ -94,4 +102,9 @@ pub enum AgentControlEvent {
     ConfirmTool(ConfirmToolResponse),
     CancelTask,
     NewTask,
+    /// Opens the session by id and continues it
+    OpenSession(String),
+    /// Restores workspace files to the state before the message with the given index,
+    /// the message history is truncated to the message if the flag is set
+    Rollback(usize, bool),
 }
@@ -1,6 +1,10 @@
+use std::collections::HashMap;
 use std::collections::HashSet;
 use std::fmt::Display;
 use std::fs;
+use std::hash::DefaultHasher;
+use std::hash::Hash;
+use std::hash::Hasher;
 use std::path::Path;
 use std::path::PathBuf;
 // Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
@@ -8,21 +12,37 @@ use std::sync::Arc;
 
 use crate::config::McpClientTransport;
 use crate::config::McpConfig;
+use crate::config::PermissionAction;
 use crate::config::PermissionMode;
 use crate::config::ProviderKind;
+use crate::providers::model_info::ModelInfo;
+use crate::providers::replay;
+use crate::providers::replay::RecordingAgent;
+use crate::providers::replay::ReplayFixture;
+use crate::providers::tokenizer::create_tokenizer;
+use crate::providers::tokenizer::Tokenizer;
 use crate::providers::HulyAgent;
+use crate::tools::apply_patch;
+use crate::tools::apply_patch::ApplyPatchTool;
 use crate::tools::ask_followup_question::AskFollowupQuestionTool;
 use crate::tools::attempt_completion::AttemptCompletionTool;
+use crate::tools::code_nav::tools::{CodeOutlineTool, FindReferencesTool, FindSymbolTool};
+use crate::tools::codebase_search::index::CodebaseIndex;
+use crate::tools::codebase_search::CodebaseSearchTool;
+use crate::tools::execute_command::executor::create_executor;
 use crate::tools::execute_command::tools::ExecuteCommandTool;
 use crate::tools::execute_command::tools::GetCommandResultTool;
+use crate::tools::execute_command::tools::ResetShellTool;
 use crate::tools::execute_command::tools::TerminateCommandTool;
-use crate::tools::execute_command::ProcessRegistry;
+use crate::tools::execute_command::{command_output_dir, ProcessRegistry};
 use crate::tools::list_files::ListFilesTool;
 use crate::tools::memory;
 use crate::tools::memory::indexer::MemoryIndexer;
 use crate::tools::memory::MemoryManager;
 use crate::tools::read_file::ReadFileTool;
+use crate::tools::read_image::{ReadImageTool, ReadImageToolOutput};
 use crate::tools::replace_in_file::ReplaceInFileTool;
+use crate::tools::resolve_write_path;
 use crate::tools::search_files::SearchFilesTool;
 use crate::tools::web_fetch::WebFetchTool;
 use crate::tools::web_search::WebSearchTool;
@@ -41,7 +61,6 @@ use rig::completion::CompletionError;
 use rig::completion::CompletionModel;
 use rig::completion::CompletionResponse;
 use rig::message::AssistantContent;
-use rig::message::ImageMediaType;
 use rig::message::Message;
 use rig::message::ToolCall;
 use rig::message::ToolResultContent;
@@ -54,34 +73,60 @@ use serde::Deserialize;
 use serde::Serialize;
 use tokio::sync::mpsc;
 
+pub mod checkpoint;
+pub mod compaction;
 pub mod event;
+pub mod permissions;
+pub mod repo_map;
+pub mod session;
 pub mod utils;
 pub use event::AgentControlEvent;
 pub use event::AgentOutputEvent;
 use tokio::sync::RwLock;
 use tokio::sync::RwLockReadGuard;
 
+use self::checkpoint::CheckpointStore;
+use self::compaction::is_context_overflow_error;
+use self::compaction::original_task;
+use self::compaction::Compactor;
+use self::compaction::SUMMARY_SYSTEM_PROMPT;
 use self::event::AgentState;
+use self::repo_map::RepoMap;
+use self::session::SessionInfo;
+use self::session::SessionStore;
 use self::utils::*;
 
 pub struct Agent {
     config: Config,
+    model_info: ModelInfo,
     sender: mpsc::UnboundedSender<AgentOutputEvent>,
     memory: Arc<RwLock<MemoryManager>>,
     process_registry: Arc<RwLock<ProcessRegistry>>,
+    codebase_index: Arc<RwLock<CodebaseIndex>>,
 }
 
 struct BuildAgentContext<'a> {
     config: &'a Config,
+    model: &'a str,
+    /// Agent without tools is used for auxiliary requests like history summarization
+    with_tools: bool,
     memory: Arc<RwLock<MemoryManager>>,
     process_registry: Arc<RwLock<ProcessRegistry>>,
+    codebase_index: Arc<RwLock<CodebaseIndex>>,
+    data_dir: &'a Path,
     system_prompt: String,
     sender: mpsc::UnboundedSender<AgentOutputEvent>,
+    tokenizer: Arc<dyn Tokenizer>,
+    /// Model accepts images, so tools returning images are available
+    supports_vision: bool,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 struct AgentConfigState {
     approved_tools: HashSet<String>,
+    /// Command prefixes always approved for `execute_command`
+    #[serde(default)]
+    approved_commands: HashSet<String>,
 }
 
 impl AgentConfigState {
@@ -91,9 +136,7 @@ impl AgentConfigState {
             serde_yaml::from_str(&std::fs::read_to_string(path).unwrap_or_default())
                 .unwrap_or_default()
         } else {
-            Self {
-                approved_tools: HashSet::default(),
-            }
+            Self::default()
         }
     }
 }
@@ -113,8 +156,25 @@ impl Display for AgentError {
     }
 }
 
-fn count_tokens(text: &str) -> u32 {
-    text.len() as u32 / 4
+/// Approximate token count of an image, images are billed by resolution rather than by data size
+const IMAGE_TOKENS: u32 = 1500;
+
+/// Restores agent state from the message history
+fn restore_state(messages: &[Message]) -> AgentState {
+    match messages.last() {
+        None => AgentState::WaitingUserPrompt,
+        Some(Message::User { .. }) => AgentState::Paused,
+        Some(Message::Assistant { content }) => match content.first() {
+            AssistantContent::Text(_) => AgentState::WaitingUserPrompt,
+            AssistantContent::ToolCall(tool_call) => {
+                if tool_call.function.name == AttemptCompletionTool::NAME {
+                    AgentState::Completed
+                } else {
+                    AgentState::WaitingUserPrompt
+                }
+            }
+        },
+    }
 }
 
 fn pending_tool_id<'a>(messages: RwLockReadGuard<'a, Vec<Message>>) -> Option<String> {
@@ -136,6 +196,13 @@ struct AgentContext {
     sender: mpsc::UnboundedSender<AgentOutputEvent>,
     process_registry: Arc<RwLock<ProcessRegistry>>,
     memory_index: Arc<RwLock<MemoryIndexer>>,
+    repo_map: Option<Arc<RwLock<RepoMap>>>,
+    model_info: ModelInfo,
+    sessions: SessionStore,
+    session_id: Arc<RwLock<String>>,
+    tokenizer: Arc<dyn Tokenizer>,
+    /// Token counts of messages by the hash of their content
+    token_counts: Arc<std::sync::Mutex<HashMap<u64, u32>>>,
     system_prompt_token_count: u32,
     current_input_tokens: u32,
     current_completion_tokens: u32,
@@ -143,16 +210,29 @@ struct AgentContext {
 
 impl Agent {
     pub fn new(
-        _data_dir: &str,
+        data_dir: &str,
         config: Config,
+        model_info: ModelInfo,
         memory: Arc<RwLock<MemoryManager>>,
         sender: mpsc::UnboundedSender<AgentOutputEvent>,
     ) -> Self {
+        let codebase_index = Arc::new(RwLock::new(CodebaseIndex::new(
+            Path::new(data_dir),
+            &config.workspace,
+            config.memory_embedding.clone(),
+        )));
+        let process_registry = Arc::new(RwLock::new(ProcessRegistry::new(
+            config.terminal.clone(),
+            command_output_dir(Path::new(data_dir)),
+            create_executor(&config),
+        )));
         Self {
             config,
+            model_info,
             sender,
             memory,
-            process_registry: Arc::new(RwLock::new(ProcessRegistry::default())),
+            process_registry,
+            codebase_index,
         }
     }
 
@@ -163,9 +243,18 @@ impl Agent {
     where
         M: CompletionModel,
     {
+        // full outputs of commands are readable to page through truncated outputs
+        let mut read_roots = context.config.read_only_roots.clone();
+        read_roots.push(command_output_dir(context.data_dir));
         let mut agent_builder = agent_builder
-            .tool(ReadFileTool::new(context.config.workspace.to_path_buf()))
-            .tool(ListFilesTool::new(context.config.workspace.to_path_buf()))
+            .tool(ReadFileTool::new(
+                context.config.workspace.to_path_buf(),
+                read_roots,
+            ))
+            .tool(ListFilesTool::new(
+                context.config.workspace.to_path_buf(),
+                context.config.read_only_roots.clone(),
+            ))
             .tool(WriteToFileTool::new(context.config.workspace.to_path_buf()))
             .tool(ExecuteCommandTool::new(
                 context.config.workspace.to_path_buf(),
@@ -177,9 +266,36 @@ impl Agent {
             .tool(ReplaceInFileTool::new(
                 context.config.workspace.to_path_buf(),
             ))
-            .tool(SearchFilesTool::new(context.config.workspace.to_path_buf()))
+            .tool(ApplyPatchTool::new(context.config.workspace.to_path_buf()))
+            .tool(SearchFilesTool::new(
+                context.config.workspace.to_path_buf(),
+                context.config.read_only_roots.clone(),
+            ))
+            .tool(CodeOutlineTool::new(
+                context.config.workspace.to_path_buf(),
+                context.config.read_only_roots.clone(),
+            ))
+            .tool(FindSymbolTool::new(
+                context.config.workspace.to_path_buf(),
+                context.config.read_only_roots.clone(),
+            ))
+            .tool(FindReferencesTool::new(
+                context.config.workspace.to_path_buf(),
+                context.config.read_only_roots.clone(),
+            ))
+            .tool(CodebaseSearchTool::new(context.codebase_index.clone()))
             .tool(AskFollowupQuestionTool)
             .tool(AttemptCompletionTool);
+        if context.config.terminal.persistent_shell {
+            agent_builder =
+                agent_builder.tool(ResetShellTool::new(context.process_registry.clone()));
+        }
+        if context.supports_vision {
+            agent_builder = agent_builder.tool(ReadImageTool::new(
+                context.config.workspace.to_path_buf(),
+                context.config.read_only_roots.clone(),
+            ));
+        }
         if let Some(web_search) = context.config.web_search.as_ref() {
             agent_builder = agent_builder.tool(WebSearchTool::new(web_search.clone()));
         }
@@ -314,13 +430,17 @@ impl Agent {
             agent_builder = agent_builder.additional_params(config.clone());
         }
         let mut system_prompt = context.system_prompt.clone();
-        let mcp_config = context.config.mcp.as_ref();
-        agent_builder = Self::add_static_tools(agent_builder, context);
-        let (agent_builder, system_prompt_addons) =
-            Self::add_mcp_tools(agent_builder, mcp_config).await?;
-        system_prompt.push_str(&system_prompt_addons);
+        let tokenizer = context.tokenizer.clone();
+        if context.with_tools {
+            let mcp_config = context.config.mcp.as_ref();
+            agent_builder = Self::add_static_tools(agent_builder, context);
+            let (builder, system_prompt_addons) =
+                Self::add_mcp_tools(agent_builder, mcp_config).await?;
+            agent_builder = builder;
+            system_prompt.push_str(&system_prompt_addons);
+        }
         let agent = agent_builder.preamble(&system_prompt).build();
-        *tools_tokens = count_tokens(
+        *tools_tokens = tokenizer.count_tokens(
             &agent
                 .tools
                 .documents()
@@ -346,7 +466,7 @@ impl Agent {
                         .clone()
                         .expect("provider_api_key is required for OpenAI"),
                 )
-                .agent(&context.config.model);
+                .agent(context.model);
                 Ok(Box::new(
                     Self::configure_agent(agent_builder, context, tools_tokens).await?,
                 ))
@@ -360,7 +480,7 @@ impl Agent {
                         .expect("provider_api_key is required for Anthropic"),
                 )
                 .build()
-                .agent(&context.config.model)
+                .agent(context.model)
                 .max_tokens(20000);
                 Ok(Box::new(
                     Self::configure_agent(agent_builder, context, tools_tokens).await?,
@@ -374,7 +494,7 @@ impl Agent {
                         .clone()
                         .expect("provider_api_key is required for OpenRouter"),
                 )
-                .agent(&context.config.model);
+                .agent(context.model);
                 Ok(Box::new(
                     Self::configure_agent(agent_builder, context, tools_tokens).await?,
                 ))
@@ -388,7 +508,21 @@ impl Agent {
                         .clone()
                         .unwrap_or("http://127.0.0.1:1234/v1".to_string()),
                 )
-                .agent(&context.config.model);
+                .agent(context.model);
+                Ok(Box::new(
+                    Self::configure_agent(agent_builder, context, tools_tokens).await?,
+                ))
+            }
+            ProviderKind::Replay => {
+                let replay_config = context
+                    .config
+                    .replay
+                    .as_ref()
+                    .context("replay configuration is required for Replay provider")?;
+                let fixture = ReplayFixture::load(&replay_config.fixture).with_context(|| {
+                    format!("Failed to load replay fixture {:?}", replay_config.fixture)
+                })?;
+                let agent_builder = AgentBuilder::new(replay::CompletionModel::new(fixture));
                 Ok(Box::new(
                     Self::configure_agent(agent_builder, context, tools_tokens).await?,
                 ))
@@ -400,6 +534,8 @@ impl Agent {
         &mut self,
         data_dir: &str,
         receiver: mpsc::UnboundedReceiver<AgentControlEvent>,
+        sessions: SessionStore,
+        session: SessionInfo,
         messages: Vec<Message>,
         memory_index: Arc<RwLock<MemoryIndexer>>,
     ) {
@@ -409,43 +545,74 @@ impl Agent {
             self.config.model
         );
         let system_prompt = prepare_system_prompt(&self.config).await;
-        let system_prompt_token_count = count_tokens(&system_prompt);
+        let tokenizer = create_tokenizer(&self.config);
+        let supports_vision = self
+            .config
+            .vision
+            .unwrap_or(self.model_info.supports_vision);
+        let system_prompt_token_count = tokenizer.count_tokens(&system_prompt);
         let mut tools_tokens = 0;
 
         let agent = Self::build_agent(
             BuildAgentContext {
                 config: &self.config,
+                model: &self.config.model,
+                with_tools: true,
                 system_prompt,
                 memory: self.memory.clone(),
                 process_registry: self.process_registry.clone(),
+                codebase_index: self.codebase_index.clone(),
+                data_dir: Path::new(data_dir),
                 sender: self.sender.clone(),
+                tokenizer: tokenizer.clone(),
+                supports_vision,
             },
             &mut tools_tokens,
         )
         .await
         .unwrap();
-
-        // This is workaround to calculate tokens from system prompt and tools for providers like LMStudio
-        let system_prompt_token_count = system_prompt_token_count + tools_tokens / 2;
-        // restore state from messages
-        let state = if messages.is_empty() {
-            AgentState::WaitingUserPrompt
-        } else {
-            match messages.last().unwrap() {
-                Message::User { .. } => AgentState::Paused,
-                Message::Assistant { content } => match content.first() {
-                    AssistantContent::Text(_) => AgentState::WaitingUserPrompt,
-                    AssistantContent::ToolCall(tool_call) => {
-                        if tool_call.function.name == AttemptCompletionTool::NAME {
-                            AgentState::Completed
-                        } else {
-                            AgentState::WaitingUserPrompt
-                        }
-                    }
-                },
+        let agent: Box<dyn HulyAgent> = match &self.config.replay {
+            Some(replay_config)
+                if replay_config.record
+                    && !matches!(self.config.provider, ProviderKind::Replay) =>
+            {
+                Box::new(RecordingAgent::new(agent, &replay_config.fixture))
             }
+            _ => agent,
         };
+
+        let summary_agent = match &self.config.compaction.model {
+            Some(model) if self.config.compaction.enabled => Some(
+                Self::build_agent(
+                    BuildAgentContext {
+                        config: &self.config,
+                        model,
+                        with_tools: false,
+                        system_prompt: SUMMARY_SYSTEM_PROMPT.to_string(),
+                        memory: self.memory.clone(),
+                        process_registry: self.process_registry.clone(),
+                        codebase_index: self.codebase_index.clone(),
+                        data_dir: Path::new(data_dir),
+                        sender: self.sender.clone(),
+                        tokenizer: tokenizer.clone(),
+                        supports_vision,
+                    },
+                    &mut 0,
+                )
+                .await
+                .unwrap(),
+            ),
+            _ => None,
+        };
+        let compactor = Compactor::new(self.config.compaction.clone(), summary_agent);
+
+        let system_prompt_token_count = system_prompt_token_count + tools_tokens;
+        let token_counts = Arc::new(std::sync::Mutex::new(HashMap::new()));
+        let state = restore_state(&messages);
         tracing::info!("initial state: {:?}", state);
+        self.sender
+            .send(AgentOutputEvent::SessionChanged(session.id.clone()))
+            .unwrap();
         self.sender
             .send(AgentOutputEvent::AgentStatus(0, 0, state.clone()))
             .unwrap();
@@ -454,6 +621,14 @@ impl Agent {
         let sender = self.sender.clone();
         let state = Arc::new(RwLock::new(state));
         let config_state = Arc::new(RwLock::new(AgentConfigState::new(data_dir)));
+        let session_id = Arc::new(RwLock::new(session.id));
+        let repo_map = self.config.repo_map.enabled.then(|| {
+            Arc::new(RwLock::new(RepoMap::new(
+                &self.config.workspace,
+                self.config.repo_map.max_tokens,
+                tokenizer.clone(),
+            )))
+        });
 
         let events_context = AgentContext {
             config: self.config.clone(),
@@ -464,6 +639,12 @@ impl Agent {
             sender: self.sender.clone(),
             process_registry: self.process_registry.clone(),
             memory_index: memory_index.clone(),
+            repo_map: repo_map.clone(),
+            model_info: self.model_info.clone(),
+            sessions: sessions.clone(),
+            session_id: session_id.clone(),
+            tokenizer: tokenizer.clone(),
+            token_counts: token_counts.clone(),
             current_completion_tokens: 0,
             current_input_tokens: 0,
             system_prompt_token_count,
@@ -478,6 +659,12 @@ impl Agent {
             sender: self.sender.clone(),
             process_registry: self.process_registry.clone(),
             memory_index: memory_index.clone(),
+            repo_map: repo_map.clone(),
+            model_info: self.model_info.clone(),
+            sessions: sessions.clone(),
+            session_id: session_id.clone(),
+            tokenizer: tokenizer.clone(),
+            token_counts: token_counts.clone(),
             current_completion_tokens: 0,
             current_input_tokens: 0,
             system_prompt_token_count,
@@ -485,7 +672,7 @@ impl Agent {
 
         tokio::select! {
            _ = handle_control_events(events_context, receiver) => {}
-           _ = process_messages(process_context, agent) => {}
+           _ = process_messages(process_context, agent, compactor) => {}
            _ = handle_process_registry(self.process_registry.clone(), self.sender.clone()) => {}
            _ = sender.closed() => {}
         }
@@ -530,9 +717,12 @@ impl AgentContext {
     }
 
     async fn add_env_message(&self, mut message: Message) -> Message {
+        let task = original_task(&self.messages.read().await);
         add_env_message(
             &mut message,
             self.memory_index.clone(),
+            self.repo_map.clone(),
+            &task,
             &self.data_dir,
             &self.config.workspace,
             self.process_registry.clone(),
@@ -577,7 +767,198 @@ impl AgentContext {
     async fn persist_history(&self) {
         tracing::debug!("persist_history");
         let messages = self.messages.read().await;
-        persist_history(&self.data_dir, &messages);
+        let session_id = self.session_id.read().await;
+        if let Err(e) = self.sessions.save_messages(&session_id, &messages) {
+            tracing::error!("Failed to save session {}: {}", session_id, e);
+        }
+    }
+
+    async fn add_session_usage(&self) {
+        let session_id = self.session_id.read().await;
+        if let Err(e) = self.sessions.add_usage(
+            &session_id,
+            self.current_input_tokens,
+            self.current_completion_tokens,
+            &self.model_info,
+        ) {
+            tracing::error!("Failed to update session {} usage: {}", session_id, e);
+        }
+    }
+
+    /// Switches the agent to another session with the given message history
+    async fn switch_session(&mut self, session_id: String, messages: Vec<Message>, reason: &str) {
+        let state = restore_state(&messages);
+        *self.session_id.write().await = session_id.clone();
+        self.replace_messages(messages).await;
+        self.current_input_tokens = 0;
+        self.current_completion_tokens = 0;
+        self.sender
+            .send(AgentOutputEvent::SessionChanged(session_id))
+            .ok();
+        self.set_state(state, reason).await;
+    }
+
+    async fn replace_messages(&mut self, messages: Vec<Message>) {
+        // counts of the replaced messages are not needed anymore
+        self.token_counts.lock().unwrap().clear();
+        *self.messages.write().await = messages.clone();
+        self.sender
+            .send(AgentOutputEvent::ReplaceMessages(messages))
+            .ok();
+    }
+
+    fn checkpoints(&self, session_id: &str) -> CheckpointStore {
+        CheckpointStore::new(&self.sessions.session_dir(session_id))
+    }
+
+    /// Snapshots the files modified by the tool call, so they can be restored by rollback
+    async fn checkpoint_tool_call(&self, tool_call: &ToolCall) {
+        let arg = |name: &str| {
+            tool_call
+                .function
+                .arguments
+                .get(name)
+                .and_then(|value| value.as_str())
+        };
+        let paths = match tool_call.function.name.as_str() {
+            WriteToFileTool::NAME | ReplaceInFileTool::NAME => {
+                arg("path").map(|path| vec![path.to_string()])
+            }
+            ApplyPatchTool::NAME => arg("patch").map(apply_patch::patch_paths),
+            _ => None,
+        };
+        let message_idx = self.messages.read().await.len().saturating_sub(1);
+        let session_id = self.session_id.read().await;
+        let checkpoints = self.checkpoints(&session_id);
+        for path in paths.unwrap_or_default() {
+            // the tool itself reports the error for invalid paths
+            let Ok(path) = resolve_write_path(&self.config.workspace, &path) else {
+                continue;
+            };
+            if let Err(e) = checkpoints.snapshot(message_idx, &path) {
+                tracing::error!("Failed to create checkpoint of {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// Restores workspace files to the state before the message with the given index
+    async fn rollback(&mut self, message_idx: usize, truncate_history: bool) -> Result<()> {
+        let session_id = self.session_id.read().await.clone();
+        let restored = self.checkpoints(&session_id).rollback(message_idx)?;
+        tracing::info!(
+            "Rollback to message {}: {} files restored",
+            message_idx,
+            restored.len()
+        );
+        let workspace = self
+            .config
+            .workspace
+            .canonicalize()
+            .unwrap_or_else(|_| self.config.workspace.clone());
+        for path in restored {
+            if let Ok(path) = path.strip_prefix(&workspace) {
+                self.sender
+                    .send(AgentOutputEvent::HighlightFile(
+                        path.to_string_lossy().to_string(),
+                        true,
+                    ))
+                    .ok();
+            }
+        }
+        if truncate_history {
+            let mut messages = self.messages.read().await.clone();
+            messages.truncate(message_idx);
+            let state = restore_state(&messages);
+            self.replace_messages(messages).await;
+            self.persist_history().await;
+            self.set_state(state, "rollback").await;
+        }
+        Ok(())
+    }
+
+    /// Compacts message history if it doesn't fit the model context window,
+    /// returns true if the history was changed
+    async fn compact_history(
+        &mut self,
+        agent: &dyn HulyAgent,
+        compactor: &Compactor,
+        force: bool,
+    ) -> bool {
+        let max_tokens = self.model_info.max_tokens;
+        let tokens = self
+            .count_aproximate_tokens()
+            .await
+            .max(self.current_input_tokens + self.current_completion_tokens);
+        if !force && !compactor.need_compaction(tokens, max_tokens) {
+            return false;
+        }
+        tracing::info!("Compact history: {} of {} tokens", tokens, max_tokens);
+        let original = self.messages.read().await.clone();
+        let mut messages = original.clone();
+        let mut compacted = false;
+        if compactor.truncate_tool_results(&mut messages) {
+            self.replace_messages(messages.clone()).await;
+            compacted = true;
+        }
+        if force || compactor.need_compaction(self.count_aproximate_tokens().await, max_tokens) {
+            match compactor.summarize(agent, &messages, max_tokens).await {
+                Ok(Some(summarized)) => {
+                    // older messages are replaced by a single summary message
+                    let removed = messages.len() - summarized.len();
+                    let session_id = self.session_id.read().await.clone();
+                    if let Err(e) = self.checkpoints(&session_id).shift(removed) {
+                        tracing::error!("Failed to shift checkpoints: {}", e);
+                    }
+                    self.replace_messages(summarized).await;
+                    compacted = true;
+                }
+                Ok(None) => tracing::warn!("History is too short to summarize"),
+                Err(e) => tracing::error!("Failed to summarize history: {}", e),
+            }
+        }
+        if compacted {
+            let session_id = self.session_id.read().await.clone();
+            if let Err(e) = self.sessions.archive_messages(&session_id, &original) {
+                tracing::error!("Failed to archive history of session {}: {}", session_id, e);
+            }
+            self.current_input_tokens = self.count_aproximate_tokens().await;
+            self.current_completion_tokens = 0;
+            self.persist_history().await;
+        }
+        compacted
+    }
+
+    /// Permission of the tool call by the permission mode and previously approved tools
+    async fn permission_mode_action(&self, tool_call: &ToolCall) -> PermissionAction {
+        match self.config.permission_mode {
+            PermissionMode::FullAutonomous => PermissionAction::Allow,
+            PermissionMode::DenyAll => PermissionAction::Deny,
+            PermissionMode::ManualApproval => {
+                let config_state = self.config_state.read().await;
+                let approved = if tool_call.function.name == ExecuteCommandTool::NAME {
+                    tool_call
+                        .function
+                        .arguments
+                        .get("command")
+                        .and_then(|command| command.as_str())
+                        .is_some_and(|command| {
+                            permissions::is_command_approved(
+                                &config_state.approved_commands,
+                                command,
+                            )
+                        })
+                } else {
+                    config_state
+                        .approved_tools
+                        .contains(&tool_call.function.name)
+                };
+                if approved {
+                    PermissionAction::Allow
+                } else {
+                    PermissionAction::Ask
+                }
+            }
+        }
     }
 
     async fn persist_config_state(&self) {
@@ -601,36 +982,56 @@ impl AgentContext {
 
     async fn count_aproximate_tokens(&self) -> u32 {
         let messages = self.messages.read().await;
-        self.system_prompt_token_count
-            + messages
+        self.system_prompt_token_count + self.count_messages_tokens(&messages)
+    }
+
+    fn count_messages_tokens(&self, messages: &[Message]) -> u32 {
+        messages
+            .iter()
+            .map(|message| self.count_message_tokens(message))
+            .sum::<u32>()
+    }
+
+    /// Tokens of the message, counts are cached by the message content,
+    /// so the history isn't tokenized again before every request
+    fn count_message_tokens(&self, message: &Message) -> u32 {
+        let mut hasher = DefaultHasher::new();
+        serde_json::to_string(message)
+            .unwrap_or_default()
+            .hash(&mut hasher);
+        let key = hasher.finish();
+        if let Some(tokens) = self.token_counts.lock().unwrap().get(&key) {
+            return *tokens;
+        }
+        let count_tokens = |text: &str| self.tokenizer.count_tokens(text);
+        let tokens = match message {
+            Message::User { content } => content
                 .iter()
-                .map(|m| match m {
-                    Message::User { content } => content
+                .map(|c| match c {
+                    UserContent::Text(text) => count_tokens(&text.text),
+                    UserContent::ToolResult(tool_result) => tool_result
+                        .content
                         .iter()
-                        .map(|c| match c {
-                            UserContent::Text(text) => count_tokens(&text.text),
-                            UserContent::ToolResult(tool_result) => tool_result
-                                .content
-                                .iter()
-                                .map(|t| match t {
-                                    ToolResultContent::Text(text) => count_tokens(&text.text),
-                                    ToolResultContent::Image(img) => count_tokens(&img.data),
-                                })
-                                .sum::<u32>(),
-                            _ => 0,
-                        })
-                        .sum::<u32>(),
-                    Message::Assistant { content } => content
-                        .iter()
-                        .map(|c| match c {
-                            AssistantContent::Text(text) => count_tokens(&text.text),
-                            AssistantContent::ToolCall(tool_call) => {
-                                count_tokens(&serde_json::to_string(tool_call).unwrap())
-                            }
+                        .map(|t| match t {
+                            ToolResultContent::Text(text) => count_tokens(&text.text),
+                            ToolResultContent::Image(_) => IMAGE_TOKENS,
                         })
                         .sum::<u32>(),
+                    _ => 0,
                 })
-                .sum::<u32>()
+                .sum::<u32>(),
+            Message::Assistant { content } => content
+                .iter()
+                .map(|c| match c {
+                    AssistantContent::Text(text) => count_tokens(&text.text),
+                    AssistantContent::ToolCall(tool_call) => {
+                        count_tokens(&serde_json::to_string(tool_call).unwrap())
+                    }
+                })
+                .sum::<u32>(),
+        };
+        self.token_counts.lock().unwrap().insert(key, tokens);
+        tokens
     }
 }
 
@@ -657,11 +1058,49 @@ async fn handle_control_events(
             }
             AgentControlEvent::NewTask => {
                 tracing::info!("New task");
+                let session = ctx.sessions.create();
                 ctx.messages.write().await.clear();
+                ctx.process_registry.write().await.reset_shell();
+                *ctx.session_id.write().await = session.id.clone();
                 ctx.set_state(AgentState::WaitingUserPrompt, "new_task")
                     .await;
                 ctx.sender.send(AgentOutputEvent::NewTask).ok();
-                ctx.persist_history().await;
+                ctx.sender
+                    .send(AgentOutputEvent::SessionChanged(session.id))
+                    .ok();
+            }
+            AgentControlEvent::OpenSession(session_id) => {
+                tracing::info!("Open session: {}", session_id);
+                if !ctx.state.read().await.is_paused() {
+                    ctx.set_state(AgentState::Paused, "open_session").await;
+                }
+                match ctx.sessions.messages(&session_id) {
+                    Ok(messages) => {
+                        ctx.process_registry.write().await.reset_shell();
+                        ctx.switch_session(session_id, messages, "open_session")
+                            .await
+                    }
+                    Err(e) => {
+                        ctx.set_state(
+                            AgentState::Error(format!("Failed to open session: {e}")),
+                            "open_session",
+                        )
+                        .await
+                    }
+                }
+            }
+            AgentControlEvent::Rollback(message_idx, truncate_history) => {
+                tracing::info!("Rollback to message: {}", message_idx);
+                if !ctx.state.read().await.is_paused() {
+                    ctx.set_state(AgentState::Paused, "rollback").await;
+                }
+                if let Err(e) = ctx.rollback(message_idx, truncate_history).await {
+                    ctx.set_state(
+                        AgentState::Error(format!("Failed to rollback: {e}")),
+                        "rollback",
+                    )
+                    .await;
+                }
             }
             AgentControlEvent::TerminalData(idx, data) => {
                 tracing::info!("Terminal input data");
@@ -681,6 +1120,34 @@ async fn handle_control_events(
                         )
                         .await;
                     }
+                    ConfirmToolResponse::Edit(arguments) => {
+                        let mut tool_call = tool_call.clone();
+                        tool_call.function.arguments = arguments;
+                        // the edited call is kept in the history instead of the original one
+                        ctx.update_last_message(Message::Assistant {
+                            content: OneOrMany::one(AssistantContent::ToolCall(tool_call.clone())),
+                        })
+                        .await;
+                        ctx.persist_history().await;
+                        // the edited arguments may point to files or commands denied by the rules
+                        if permissions::evaluate_rules(
+                            &ctx.config.permissions,
+                            &ctx.config.workspace,
+                            &tool_call,
+                        ) == Some(PermissionAction::Deny)
+                        {
+                            ctx.add_message(Message::tool_result(
+                                tool_call.id,
+                                "Tool execution denied by permission rules",
+                            ))
+                            .await;
+                            ctx.set_state(AgentState::WaitingResponse, "permission_rule_deny")
+                                .await;
+                            continue;
+                        }
+                        ctx.set_state(AgentState::ToolCall(tool_call, false), "tool_edit")
+                            .await;
+                    }
                     ConfirmToolResponse::Deny => {
                         ctx.add_message(Message::tool_result(
                             tool_call.id,
@@ -695,11 +1162,25 @@ async fn handle_control_events(
                         let AgentState::ToolCall(tool_call, _) = state else {
                             unreachable!()
                         };
-                        ctx.config_state
-                            .write()
-                            .await
-                            .approved_tools
-                            .insert(tool_call.function.name.clone());
+                        {
+                            let mut config_state = ctx.config_state.write().await;
+                            if tool_call.function.name == ExecuteCommandTool::NAME {
+                                // approve only the command prefix, not any command
+                                if let Some(prefix) = tool_call
+                                    .function
+                                    .arguments
+                                    .get("command")
+                                    .and_then(|command| command.as_str())
+                                    .and_then(permissions::command_prefix)
+                                {
+                                    config_state.approved_commands.insert(prefix);
+                                }
+                            } else {
+                                config_state
+                                    .approved_tools
+                                    .insert(tool_call.function.name.clone());
+                            }
+                        }
                         ctx.persist_config_state().await;
                         ctx.set_state(
                             AgentState::ToolCall(tool_call.clone(), false),
@@ -713,7 +1194,11 @@ async fn handle_control_events(
     }
 }
 
-async fn process_messages(mut ctx: AgentContext, mut agent: Box<dyn HulyAgent>) {
+async fn process_messages(
+    mut ctx: AgentContext,
+    mut agent: Box<dyn HulyAgent>,
+    compactor: Compactor,
+) {
     loop {
         if ctx.state.read().await.is_paused() {
             tokio::time::sleep(std::time::Duration::from_millis(10)).await;
@@ -741,7 +1226,14 @@ async fn process_messages(mut ctx: AgentContext, mut agent: Box<dyn HulyAgent>)
             drop(state);
         }
 
+        ctx.compact_history(agent.as_ref(), &compactor, false).await;
         if let Err(e) = send_messages(&mut ctx, &mut agent).await {
+            if is_context_overflow_error(&e.to_string())
+                && ctx.compact_history(agent.as_ref(), &compactor, true).await
+            {
+                tracing::warn!("Retry after history compaction: {}", e);
+                continue;
+            }
             ctx.persist_history().await;
             tracing::error!("Error processing messages: {}", e);
             ctx.set_state(AgentState::Error(format!("{e}")), "process_messages")
@@ -754,6 +1246,7 @@ async fn process_messages(mut ctx: AgentContext, mut agent: Box<dyn HulyAgent>)
         agent: &mut Box<dyn HulyAgent>,
         tool_call: ToolCall,
     ) {
+        ctx.checkpoint_tool_call(&tool_call).await;
         let (mut tool_result, is_error) = match agent
             .tools()
             .call(
@@ -810,7 +1303,35 @@ async fn process_messages(mut ctx: AgentContext, mut agent: Box<dyn HulyAgent>)
                 _ => {}
             }
         }
-        let tool_result_content: OneOrMany<ToolResultContent> = {
+        // source files may be modified, so the repository map parses them again
+        if let Some(repo_map) = &ctx.repo_map {
+            if matches!(
+                tool_call.function.name.as_str(),
+                WriteToFileTool::NAME
+                    | ReplaceInFileTool::NAME
+                    | ApplyPatchTool::NAME
+                    | ExecuteCommandTool::NAME
+            ) {
+                repo_map.write().await.mark_stale();
+            }
+        }
+        let image = if !is_error && tool_call.function.name == ReadImageTool::NAME {
+            serde_json::from_str::<ReadImageToolOutput>(&tool_result).ok()
+        } else {
+            None
+        };
+        let tool_result_content: OneOrMany<ToolResultContent> = if let Some(image) = image {
+            OneOrMany::many([
+                ToolResultContent::text(image.description()),
+                ToolResultContent::image(
+                    image.data,
+                    None,
+                    Some(image_media_type(&image.media_type)),
+                    None,
+                ),
+            ])
+            .unwrap()
+        } else {
             // due incomplete rig_mcp implementation we try detect image data in response and split message
             if tool_result.contains("|image-data:") {
                 let mut parts = tool_result.split("|image-data:");
@@ -825,16 +1346,7 @@ async fn process_messages(mut ctx: AgentContext, mut agent: Box<dyn HulyAgent>)
                     ToolResultContent::image(
                         image_data,
                         None, //.Some(rig::message::ContentFormat::Base64),
-                        match image_type {
-                            "image/png" => Some(ImageMediaType::PNG),
-                            "image/jpeg" => Some(ImageMediaType::JPEG),
-                            "image/gif" => Some(ImageMediaType::GIF),
-                            "image/webp" => Some(ImageMediaType::WEBP),
-                            "image/heic" => Some(ImageMediaType::HEIC),
-                            "image/heif" => Some(ImageMediaType::HEIF),
-                            "image/svg+xml" => Some(ImageMediaType::SVG),
-                            _ => Some(ImageMediaType::PNG),
-                        },
+                        Some(image_media_type(image_type)),
                         None,
                     ),
                 ])
@@ -860,6 +1372,7 @@ async fn process_messages(mut ctx: AgentContext, mut agent: Box<dyn HulyAgent>)
         agent: &mut Box<dyn HulyAgent>,
     ) -> Result<(), AgentError> {
         let last_message = ctx.messages.read().await.last().unwrap().clone();
+        let sent_messages_count = ctx.messages.read().await.len();
         let mut stream = agent
             .send_messages(last_message.clone(), ctx.chat_histoty().await)
             .await?;
@@ -912,45 +1425,48 @@ async fn process_messages(mut ctx: AgentContext, mut agent: Box<dyn HulyAgent>)
                         tracing::info!("Ask followup question");
                         ctx.persist_history().await;
                     } else {
-                        match ctx.config.permission_mode {
-                            PermissionMode::ManualApproval => {
-                                if ctx
-                                    .config_state
-                                    .read()
-                                    .await
-                                    .approved_tools
-                                    .contains(&tool_call.function.name)
-                                {
-                                    ctx.set_state(
-                                        AgentState::ToolCall(tool_call.clone(), false),
-                                        "manual_auto_approve",
-                                    )
-                                    .await;
-                                    invoke_tool(ctx, agent, tool_call).await;
-                                } else {
-                                    ctx.set_state(
-                                        AgentState::ToolCall(tool_call.clone(), true),
-                                        "manual_approve",
-                                    )
-                                    .await;
-                                }
-                            }
-                            PermissionMode::DenyAll => {
+                        let action = match permissions::evaluate_rules(
+                            &ctx.config.permissions,
+                            &ctx.config.workspace,
+                            &tool_call,
+                        ) {
+                            Some(PermissionAction::Deny) => {
                                 ctx.add_message(Message::tool_result(
                                     tool_call.id,
-                                    "Tool execution denied",
+                                    "Tool execution denied by permission rules",
                                 ))
                                 .await;
-                                ctx.set_state(AgentState::Paused, "permission_deny").await;
+                                ctx.set_state(AgentState::WaitingResponse, "permission_rule_deny")
+                                    .await;
+                                continue;
                             }
-                            PermissionMode::FullAutonomous => {
+                            Some(action) => action,
+                            None => ctx.permission_mode_action(&tool_call).await,
+                        };
+                        match action {
+                            PermissionAction::Allow => {
                                 ctx.set_state(
                                     AgentState::ToolCall(tool_call.clone(), false),
-                                    "full_autonomous",
+                                    "permission_allow",
                                 )
                                 .await;
                                 invoke_tool(ctx, agent, tool_call).await;
                             }
+                            PermissionAction::Ask => {
+                                ctx.set_state(
+                                    AgentState::ToolCall(tool_call.clone(), true),
+                                    "permission_ask",
+                                )
+                                .await;
+                            }
+                            PermissionAction::Deny => {
+                                ctx.add_message(Message::tool_result(
+                                    tool_call.id,
+                                    "Tool execution denied",
+                                ))
+                                .await;
+                                ctx.set_state(AgentState::Paused, "permission_deny").await;
+                            }
                         }
                     }
                 }
@@ -967,10 +1483,15 @@ async fn process_messages(mut ctx: AgentContext, mut agent: Box<dyn HulyAgent>)
                 ctx.current_input_tokens = usage.prompt_tokens as u32;
                 ctx.current_completion_tokens = (usage.total_tokens - usage.prompt_tokens) as u32;
             } else {
-                // try to calculate aproximate tokens
-                ctx.current_input_tokens = ctx.count_aproximate_tokens().await;
-                ctx.current_completion_tokens = 0;
+                // provider doesn't report usage, count tokens of the request and the response
+                let messages = ctx.messages.read().await.clone();
+                let sent_messages_count = sent_messages_count.min(messages.len());
+                ctx.current_input_tokens = ctx.system_prompt_token_count
+                    + ctx.count_messages_tokens(&messages[..sent_messages_count]);
+                ctx.current_completion_tokens =
+                    ctx.count_messages_tokens(&messages[sent_messages_count..]);
             }
+            ctx.add_session_usage().await;
         }
         // if !ctx.is_last_user_message().await && !ctx.state.read().await.is_completed() {
         //     ctx.set_state(AgentState::WaitingUserPrompt).await;
@@ -995,3 +1516,199 @@ async fn handle_process_registry(
         tokio::time::sleep(std::time::Duration::from_millis(10)).await;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::tools::memory::indexer::MemoryEmbeddingModel;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = crate::tools::test_dir(name);
+        fs::create_dir_all(dir.join("workspace")).unwrap();
+        dir
+    }
+
+    fn test_config(dir: &Path, permission_mode: &str, fixture: &str) -> Config {
+        let fixture_path = dir.join("fixture.yaml");
+        fs::write(&fixture_path, fixture).unwrap();
+        serde_yaml::from_str(&format!(
+            "
+provider: Replay
+model: replay
+appearance:
+  theme: dark
+permission_mode: {permission_mode}
+workspace: {workspace}
+user_instructions: test
+memory_embedding:
+  type: fastembed
+replay:
+  fixture: {fixture}
+",
+            workspace = dir.join("workspace").display(),
+            fixture = fixture_path.display(),
+        ))
+        .unwrap()
+    }
+
+    async fn start_agent(
+        dir: &Path,
+        config: Config,
+    ) -> (
+        mpsc::UnboundedSender<AgentControlEvent>,
+        mpsc::UnboundedReceiver<AgentOutputEvent>,
+    ) {
+        let data_dir = dir.to_str().unwrap().to_string();
+        let (output_sender, output_receiver) = mpsc::unbounded_channel();
+        let (control_sender, control_receiver) = mpsc::unbounded_channel();
+        let memory_index = Arc::new(RwLock::new(
+            MemoryIndexer::new(dir, &config).with_embedding_model(MemoryEmbeddingModel::stub()),
+        ));
+        let memory = Arc::new(RwLock::new(MemoryManager::new(
+            &data_dir,
+            memory_index.clone(),
+            true,
+        )));
+        // the stub embedding model works offline
+        memory_index
+            .write()
+            .await
+            .init(memory.clone())
+            .await
+            .unwrap();
+        let model_info = ModelInfo {
+            input_price: 0.0,
+            completion_price: 0.0,
+            max_tokens: 200000,
+            supports_vision: false,
+        };
+        let sessions = SessionStore::new(dir).unwrap();
+        let session = sessions.create();
+        let mut agent = Agent::new(&data_dir, config, model_info, memory, output_sender);
+        tokio::spawn(async move {
+            agent
+                .run(
+                    &data_dir,
+                    control_receiver,
+                    sessions,
+                    session,
+                    Vec::new(),
+                    memory_index,
+                )
+                .await;
+        });
+        (control_sender, output_receiver)
+    }
+
+    /// Waits for the next paused state of the agent after it started processing
+    async fn wait_paused_state(
+        receiver: &mut mpsc::UnboundedReceiver<AgentOutputEvent>,
+    ) -> AgentState {
+        let mut started = false;
+        tokio::time::timeout(Duration::from_secs(30), async {
+            while let Some(event) = receiver.recv().await {
+                if let AgentOutputEvent::AgentStatus(_, _, state) = event {
+                    if !state.is_paused() {
+                        started = true;
+                    } else if started {
+                        return state;
+                    }
+                }
+            }
+            panic!("Agent stopped unexpectedly");
+        })
+        .await
+        .expect("Timeout waiting for agent state")
+    }
+
+    const WRITE_FILE_FIXTURE: &str = "
+turns:
+  - text: I will create the file
+    tool_calls:
+      - name: write_to_file
+        arguments: { path: hello.txt, content: Hello }
+  - tool_calls:
+      - name: attempt_completion
+        arguments: { result: Done }
+";
+
+    #[tokio::test]
+    async fn test_replay_full_autonomous() {
+        let dir = test_dir("replay-autonomous");
+        let config = test_config(&dir, "full_autonomous", WRITE_FILE_FIXTURE);
+        let (sender, mut receiver) = start_agent(&dir, config).await;
+
+        sender
+            .send(AgentControlEvent::SendMessage(
+                "Create hello.txt".to_string(),
+            ))
+            .unwrap();
+        let state = wait_paused_state(&mut receiver).await;
+        assert!(matches!(state, AgentState::Completed), "{state:?}");
+        assert_eq!(
+            fs::read_to_string(dir.join("workspace").join("hello.txt")).unwrap(),
+            "Hello"
+        );
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_replay_manual_approval() {
+        let dir = test_dir("replay-manual");
+        let config = test_config(&dir, "manual_approval", WRITE_FILE_FIXTURE);
+        let (sender, mut receiver) = start_agent(&dir, config).await;
+
+        sender
+            .send(AgentControlEvent::SendMessage(
+                "Create hello.txt".to_string(),
+            ))
+            .unwrap();
+        let state = wait_paused_state(&mut receiver).await;
+        assert!(
+            matches!(&state, AgentState::ToolCall(tool_call, true) if tool_call.function.name == WriteToFileTool::NAME),
+            "{state:?}"
+        );
+        assert!(!dir.join("workspace").join("hello.txt").exists());
+
+        sender
+            .send(AgentControlEvent::ConfirmTool(ConfirmToolResponse::Approve))
+            .unwrap();
+        let state = wait_paused_state(&mut receiver).await;
+        assert!(matches!(state, AgentState::Completed), "{state:?}");
+        assert_eq!(
+            fs::read_to_string(dir.join("workspace").join("hello.txt")).unwrap(),
+            "Hello"
+        );
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_edited_tool_call_permission_rules() {
+        let dir = test_dir("replay-edit");
+        let mut config = test_config(&dir, "manual_approval", WRITE_FILE_FIXTURE);
+        config.permissions =
+            serde_yaml::from_str("- { tool: '*', path: 'secret/**', action: deny }").unwrap();
+        let (sender, mut receiver) = start_agent(&dir, config).await;
+
+        sender
+            .send(AgentControlEvent::SendMessage(
+                "Create hello.txt".to_string(),
+            ))
+            .unwrap();
+        let state = wait_paused_state(&mut receiver).await;
+        assert!(matches!(state, AgentState::ToolCall(_, true)), "{state:?}");
+
+        // the edited path is checked by the rules again
+        sender
+            .send(AgentControlEvent::ConfirmTool(ConfirmToolResponse::Edit(
+                serde_json::json!({ "path": "secret/hello.txt", "content": "Hello" }),
+            )))
+            .unwrap();
+        let state = wait_paused_state(&mut receiver).await;
+        assert!(matches!(state, AgentState::Completed), "{state:?}");
+        assert!(!dir.join("workspace").join("secret").exists());
+        fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,403 @@
+// Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+
+use globset::Glob;
+use regex::Regex;
+use rig::message::ToolCall;
+use rig::tool::Tool;
+
+use crate::config::{PermissionAction, PermissionRule};
+use crate::tools::apply_patch::{self, ApplyPatchTool};
+
+/// Shell metacharacters which allow to chain commands, substitute processes, expand
+/// variables or redirect output, commands with them are never approved by prefix
+const SHELL_METACHARACTERS: [char; 9] = ['$', '`', '<', '>', '|', ';', '&', '\n', '\r'];
+
+/// Returns action of the first rule matching the tool call, tool calls modifying several
+/// files (e.g. `apply_patch`) are evaluated for every file and the most restrictive action wins
+pub fn evaluate_rules(
+    rules: &[PermissionRule],
+    workspace: &Path,
+    tool_call: &ToolCall,
+) -> Option<PermissionAction> {
+    let evaluate = |path: Option<&str>| {
+        rules
+            .iter()
+            .find(|rule| rule_matches(rule, workspace, tool_call, path))
+            .map(|rule| {
+                tracing::info!(
+                    "Permission rule {:?} matched tool call {}",
+                    rule,
+                    tool_call.function.name
+                );
+                rule.action
+            })
+    };
+    let paths = tool_call_paths(tool_call);
+    if paths.is_empty() {
+        return evaluate(None);
+    }
+    let actions = paths
+        .iter()
+        .map(|path| evaluate(Some(path)))
+        .collect::<Vec<_>>();
+    if actions.contains(&Some(PermissionAction::Deny)) {
+        Some(PermissionAction::Deny)
+    } else if actions.contains(&Some(PermissionAction::Ask)) {
+        Some(PermissionAction::Ask)
+    } else if actions.contains(&None) {
+        // files without a matching rule are handled by the permission mode
+        None
+    } else {
+        Some(PermissionAction::Allow)
+    }
+}
+
+/// Paths of files the tool call reads or modifies
+fn tool_call_paths(tool_call: &ToolCall) -> Vec<String> {
+    let arg = |name: &str| {
+        tool_call
+            .function
+            .arguments
+            .get(name)
+            .and_then(|value| value.as_str())
+    };
+    if tool_call.function.name == ApplyPatchTool::NAME {
+        return arg("patch")
+            .map(apply_patch::patch_paths)
+            .unwrap_or_default();
+    }
+    arg("path")
+        .map(|path| vec![path.to_string()])
+        .unwrap_or_default()
+}
+
+fn rule_matches(
+    rule: &PermissionRule,
+    workspace: &Path,
+    tool_call: &ToolCall,
+    path: Option<&str>,
+) -> bool {
+    if rule.tool != "*" && rule.tool != tool_call.function.name {
+        return false;
+    }
+    let arg = |name: &str| {
+        tool_call
+            .function
+            .arguments
+            .get(name)
+            .and_then(|value| value.as_str())
+    };
+    if let Some(pattern) = &rule.path {
+        let Some(path) = path else {
+            return false;
+        };
+        if !path_matches(pattern, workspace, path) {
+            return false;
+        }
+    }
+    if let Some(pattern) = &rule.command {
+        let Some(command) = arg("command") else {
+            return false;
+        };
+        let regex = match Regex::new(pattern) {
+            Ok(regex) => regex,
+            Err(e) => {
+                tracing::warn!("Invalid command pattern '{}': {}", pattern, e);
+                return false;
+            }
+        };
+        if !command_matches(rule, command, |command| regex.is_match(command)) {
+            return false;
+        }
+    }
+    if let Some(prefix) = &rule.command_prefix {
+        let Some(command) = arg("command") else {
+            return false;
+        };
+        if !command_matches(rule, command, |command| command_has_prefix(command, prefix)) {
+            return false;
+        }
+    }
+    if let Some(hosts) = &rule.hosts {
+        let Some(host) = arg("url").and_then(url_host) else {
+            return false;
+        };
+        if !hosts.iter().any(|pattern| host_matches(pattern, &host)) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Path relative to the workspace with resolved `.` and `..` components
+fn workspace_relative_path(workspace: &Path, path: &str) -> PathBuf {
+    let path = Path::new(path);
+    let path = path.strip_prefix(workspace).unwrap_or(path);
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !result.pop() {
+                    result.push("..");
+                }
+            }
+            component => result.push(component),
+        }
+    }
+    result
+}
+
+fn path_matches(pattern: &str, workspace: &Path, path: &str) -> bool {
+    match Glob::new(pattern) {
+        Ok(glob) => glob
+            .compile_matcher()
+            .is_match(workspace_relative_path(workspace, path)),
+        Err(e) => {
+            tracing::warn!("Invalid path pattern '{}': {}", pattern, e);
+            false
+        }
+    }
+}
+
+fn url_host(url: &str) -> Option<String> {
+    url::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(|host| host.to_lowercase()))
+}
+
+fn host_matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host == domain || host.ends_with(&format!(".{domain}")),
+        None => host == pattern,
+    }
+}
+
+/// Returns true if the command may run something else than its program, so its prefix can't be trusted
+fn is_compound_command(command: &str) -> bool {
+    command.contains(SHELL_METACHARACTERS)
+}
+
+/// Checks the command condition of the rule: a compound command never matches allow rules,
+/// other rules match it if they match the whole command or any of the chained commands
+fn command_matches(rule: &PermissionRule, command: &str, matches: impl Fn(&str) -> bool) -> bool {
+    if !is_compound_command(command) {
+        return matches(command);
+    }
+    rule.action != PermissionAction::Allow
+        && (matches(command)
+            || command
+                .split(SHELL_METACHARACTERS)
+                .map(str::trim)
+                .any(&matches))
+}
+
+fn command_has_prefix(command: &str, prefix: &str) -> bool {
+    let command = command.trim();
+    let prefix = prefix.trim();
+    !is_compound_command(command)
+        && command.starts_with(prefix)
+        && command[prefix.len()..]
+            .chars()
+            .next()
+            .is_none_or(char::is_whitespace)
+}
+
+/// Prefix of the command stored when the user always approves it: the program and its
+/// subcommand, e.g. `cargo test` for `cargo test --all`. Options change what the program
+/// does, so a command with an option after the program is stored whole, e.g. `rm -rf build`.
+pub fn command_prefix(command: &str) -> Option<String> {
+    if is_compound_command(command) {
+        return None;
+    }
+    let words = command.split_whitespace().collect::<Vec<_>>();
+    match words.as_slice() {
+        [] => None,
+        [program] => Some(program.to_string()),
+        [program, subcommand, ..] if !subcommand.starts_with('-') => {
+            Some(format!("{program} {subcommand}"))
+        }
+        _ => Some(words.join(" ")),
+    }
+}
+
+pub fn is_command_approved(approved_commands: &HashSet<String>, command: &str) -> bool {
+    approved_commands
+        .iter()
+        .any(|prefix| command_has_prefix(command, prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use rig::message::AssistantContent;
+
+    use super::*;
+
+    fn tool_call(name: &str, args: serde_json::Value) -> ToolCall {
+        let AssistantContent::ToolCall(tool_call) = AssistantContent::tool_call("1", name, args)
+        else {
+            unreachable!()
+        };
+        tool_call
+    }
+
+    fn rules() -> Vec<PermissionRule> {
+        serde_yaml::from_str(
+            r#"
+- tool: execute_command
+  command_prefix: cargo test
+  action: allow
+- tool: execute_command
+  command: "^rm\\s"
+  action: deny
+- tool: execute_command
+  command: "^ls(\\s|$)"
+  action: allow
+- tool: execute_command
+  command_prefix: git push
+  action: deny
+- tool: "*"
+  path: ".git/**"
+  action: deny
+- tool: write_to_file
+  path: "src/**"
+  action: allow
+- tool: fetch
+  hosts: [docs.rs, "*.github.com"]
+  action: allow
+- tool: fetch
+  action: ask
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_evaluate_rules() {
+        let rules = rules();
+        let workspace = Path::new("/workspace");
+        let evaluate = |name: &str, args: serde_json::Value| {
+            evaluate_rules(&rules, workspace, &tool_call(name, args))
+        };
+        let command = |command: &str| serde_json::json!({ "command": command });
+        let path = |path: &str| serde_json::json!({ "path": path });
+        let url = |url: &str| serde_json::json!({ "url": url });
+
+        assert_eq!(
+            evaluate("execute_command", command("cargo test --all")),
+            Some(PermissionAction::Allow)
+        );
+        assert_eq!(
+            evaluate("execute_command", command("cargo test && rm -rf /")),
+            Some(PermissionAction::Deny)
+        );
+        assert_eq!(evaluate("execute_command", command("cargo tests")), None);
+        assert_eq!(
+            evaluate("execute_command", command("rm -rf /")),
+            Some(PermissionAction::Deny)
+        );
+        // chained commands never match allow rules and can't bypass deny rules
+        assert_eq!(
+            evaluate("execute_command", command("ls")),
+            Some(PermissionAction::Allow)
+        );
+        assert_eq!(evaluate("execute_command", command("ls; cat .env")), None);
+        assert_eq!(
+            evaluate("execute_command", command("ls; rm -rf ~")),
+            Some(PermissionAction::Deny)
+        );
+        assert_eq!(
+            evaluate("execute_command", command("cargo test && git push -f")),
+            Some(PermissionAction::Deny)
+        );
+        assert_eq!(
+            evaluate("write_to_file", path("/workspace/src/main.rs")),
+            Some(PermissionAction::Allow)
+        );
+        assert_eq!(
+            evaluate("write_to_file", path("src/../.git/config")),
+            Some(PermissionAction::Deny)
+        );
+        assert_eq!(
+            evaluate("read_file", path(".git/HEAD")),
+            Some(PermissionAction::Deny)
+        );
+        assert_eq!(evaluate("write_to_file", path("../src/main.rs")), None);
+        assert_eq!(
+            evaluate("fetch", url("https://api.github.com/repos")),
+            Some(PermissionAction::Allow)
+        );
+        assert_eq!(
+            evaluate("fetch", url("https://docs.rs.evil.com/")),
+            Some(PermissionAction::Ask)
+        );
+        assert_eq!(evaluate("list_files", path("src")), None);
+
+        // every file of the patch is checked
+        let patch = |paths: &[&str]| {
+            let files = paths
+                .iter()
+                .map(|path| format!("*** Add File: {path}\n+content\n"))
+                .collect::<String>();
+            serde_json::json!({ "patch": format!("*** Begin Patch\n{files}*** End Patch") })
+        };
+        assert_eq!(
+            evaluate("apply_patch", patch(&[".git/config"])),
+            Some(PermissionAction::Deny)
+        );
+        assert_eq!(
+            evaluate(
+                "apply_patch",
+                patch(&["README.md", ".git/hooks/pre-commit"])
+            ),
+            Some(PermissionAction::Deny)
+        );
+        assert_eq!(evaluate("apply_patch", patch(&["src/main.rs"])), None);
+    }
+
+    #[test]
+    fn test_approved_commands() {
+        assert_eq!(
+            command_prefix("cargo test --all"),
+            Some("cargo test".to_string())
+        );
+        assert_eq!(command_prefix("ls -la"), Some("ls -la".to_string()));
+        assert_eq!(
+            command_prefix("rm -rf build"),
+            Some("rm -rf build".to_string())
+        );
+        assert_eq!(command_prefix("ls"), Some("ls".to_string()));
+        assert_eq!(command_prefix("ls && rm -rf /"), None);
+
+        let approved = HashSet::from(["cargo test".to_string(), "ls".to_string()]);
+        assert!(is_command_approved(&approved, "cargo test -p huly-coder"));
+        assert!(is_command_approved(&approved, "ls src"));
+        assert!(!is_command_approved(&approved, "lsof"));
+        assert!(!is_command_approved(&approved, "ls; rm -rf /"));
+        assert!(!is_command_approved(&approved, "cargo build"));
+        let approved_rm = HashSet::from([command_prefix("rm -rf build").unwrap()]);
+        assert!(is_command_approved(&approved_rm, "rm -rf build"));
+        assert!(!is_command_approved(&approved_rm, "rm -rf src"));
+        assert!(!is_command_approved(&approved_rm, "rm src"));
+        for command in [
+            "ls <(rm -rf ~)",
+            "ls >(rm -rf ~)",
+            "ls $(rm -rf ~)",
+            "ls $HOME",
+            "ls `rm -rf ~`",
+            "ls < /etc/passwd",
+            "ls > ~/.bashrc",
+            "ls | sh",
+            "ls; rm -rf ~",
+            "ls & rm -rf ~",
+            "ls\nrm -rf ~",
+            "ls\rrm -rf ~",
+        ] {
+            assert!(!is_command_approved(&approved, command), "{command}");
+            assert_eq!(command_prefix(command), None, "{command}");
+        }
+    }
+}
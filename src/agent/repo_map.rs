@@ -0,0 +1,279 @@
+// Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+//! Map of the repository for the environment details: the most relevant source files with their
+//! definitions, ranked by references between files and by the current task
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::providers::tokenizer::Tokenizer;
+use crate::tools::code_nav::{source_files, SourceFile, Symbol};
+use crate::tools::workspace_to_string;
+
+/// Maximal number of source files considered for the map
+const MAX_MAP_FILES: usize = 5000;
+const DAMPING_FACTOR: f64 = 0.85;
+const RANK_ITERATIONS: usize = 20;
+/// Boost of files and definitions mentioned in the task
+const MENTION_WEIGHT: f64 = 10.0;
+/// Files are parsed again after the interval or after they were modified by the agent
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+struct MappedFile {
+    modified: SystemTime,
+    /// Top-level definitions and members of top-level types
+    symbols: Vec<Symbol>,
+    identifiers: HashSet<String>,
+}
+
+pub struct RepoMap {
+    workspace: PathBuf,
+    max_tokens: u32,
+    tokenizer: Arc<dyn Tokenizer>,
+    /// Parsed files by the path relative to the workspace
+    files: HashMap<PathBuf, MappedFile>,
+    /// Last rendered map with the task it was rendered for
+    cache: Option<(String, String)>,
+    last_refresh: Option<Instant>,
+}
+
+impl RepoMap {
+    pub fn new(workspace: &Path, max_tokens: u32, tokenizer: Arc<dyn Tokenizer>) -> Self {
+        Self {
+            workspace: workspace.to_path_buf(),
+            max_tokens,
+            tokenizer,
+            files: HashMap::new(),
+            cache: None,
+            last_refresh: None,
+        }
+    }
+
+    /// Files are parsed again on the next render, e.g. after the agent modified them
+    pub fn mark_stale(&mut self) {
+        self.last_refresh = None;
+    }
+
+    /// Parses files changed since the previous refresh and drops removed files
+    fn refresh(&mut self) {
+        let mut seen = HashSet::new();
+        let mut changed = false;
+        for path in source_files(&self.workspace).take(MAX_MAP_FILES) {
+            let Ok(modified) = fs::metadata(&path).and_then(|m| m.modified()) else {
+                continue;
+            };
+            let relative = path
+                .strip_prefix(&self.workspace)
+                .unwrap_or(&path)
+                .to_path_buf();
+            seen.insert(relative.clone());
+            if self
+                .files
+                .get(&relative)
+                .is_some_and(|file| file.modified == modified)
+            {
+                continue;
+            }
+            changed = true;
+            let Some(file) = SourceFile::parse(&path) else {
+                self.files.remove(&relative);
+                continue;
+            };
+            self.files.insert(
+                relative,
+                MappedFile {
+                    modified,
+                    symbols: file
+                        .symbols()
+                        .into_iter()
+                        .filter(|symbol| symbol.depth <= 1)
+                        .collect(),
+                    identifiers: file.identifiers(),
+                },
+            );
+        }
+        let count = self.files.len();
+        self.files.retain(|path, _| seen.contains(path));
+        if changed || count != self.files.len() {
+            self.cache = None;
+        }
+    }
+
+    /// Ranks files by PageRank over references between files, personalized by the files
+    /// and definitions mentioned in the task
+    fn rank(&self, task: &str) -> Vec<&PathBuf> {
+        let mut paths = self.files.keys().collect::<Vec<_>>();
+        paths.sort();
+        let count = paths.len();
+        if count == 0 {
+            return paths;
+        }
+        let mut definers: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (idx, path) in paths.iter().enumerate() {
+            for symbol in &self.files[*path].symbols {
+                let files = definers.entry(symbol.name.as_str()).or_default();
+                if files.last() != Some(&idx) {
+                    files.push(idx);
+                }
+            }
+        }
+        let task_words = task
+            .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .filter(|word| word.len() > 2)
+            .collect::<HashSet<_>>();
+
+        let mut personalization = vec![1.0; count];
+        for (idx, path) in paths.iter().enumerate() {
+            let mentioned = task.contains(workspace_to_string(path).as_str())
+                || path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| task_words.contains(name));
+            if mentioned {
+                personalization[idx] += MENTION_WEIGHT;
+            }
+        }
+        for word in &task_words {
+            for &idx in definers.get(word).into_iter().flatten() {
+                personalization[idx] += MENTION_WEIGHT;
+            }
+        }
+        let total = personalization.iter().sum::<f64>();
+        personalization.iter_mut().for_each(|p| *p /= total);
+
+        // edges from the file using the identifier to the files defining it
+        let mut edges = vec![Vec::new(); count];
+        for (idx, path) in paths.iter().enumerate() {
+            for identifier in &self.files[*path].identifiers {
+                let Some(files) = definers.get(identifier.as_str()) else {
+                    continue;
+                };
+                let weight = if task_words.contains(identifier.as_str()) {
+                    MENTION_WEIGHT
+                } else {
+                    1.0
+                } / files.len() as f64;
+                edges[idx].extend(
+                    files
+                        .iter()
+                        .filter(|&&target| target != idx)
+                        .map(|&target| (target, weight)),
+                );
+            }
+        }
+
+        let mut rank = personalization.clone();
+        for _ in 0..RANK_ITERATIONS {
+            let mut next = personalization
+                .iter()
+                .map(|p| (1.0 - DAMPING_FACTOR) * p)
+                .collect::<Vec<_>>();
+            let mut dangling = 0.0;
+            for (idx, edges) in edges.iter().enumerate() {
+                let total = edges.iter().map(|(_, weight)| weight).sum::<f64>();
+                if total == 0.0 {
+                    dangling += rank[idx];
+                    continue;
+                }
+                for (target, weight) in edges {
+                    next[*target] += DAMPING_FACTOR * rank[idx] * weight / total;
+                }
+            }
+            for (idx, p) in personalization.iter().enumerate() {
+                next[idx] += DAMPING_FACTOR * dangling * p;
+            }
+            rank = next;
+        }
+        let mut ranked = paths.into_iter().zip(rank).collect::<Vec<_>>();
+        ranked.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        ranked.into_iter().map(|(path, _)| path).collect()
+    }
+
+    /// Renders the most relevant files with their definitions fitting into the token budget.
+    /// Parsing is blocking, so it's expected to be called in a blocking task.
+    pub fn render(&mut self, task: &str) -> String {
+        if self
+            .last_refresh
+            .is_none_or(|last_refresh| last_refresh.elapsed() >= REFRESH_INTERVAL)
+        {
+            self.refresh();
+            self.last_refresh = Some(Instant::now());
+        }
+        if let Some((cached_task, map)) = &self.cache {
+            if cached_task == task {
+                return map.clone();
+            }
+        }
+        let mut map = String::new();
+        let mut tokens = 0;
+        for path in self.rank(task) {
+            let file = &self.files[path];
+            if file.symbols.is_empty() {
+                continue;
+            }
+            let mut entry = format!("{}:\n", workspace_to_string(path));
+            for symbol in &file.symbols {
+                entry.push_str(&"  ".repeat(symbol.depth + 1));
+                entry.push_str(&symbol.signature);
+                entry.push('\n');
+            }
+            let entry_tokens = self.tokenizer.count_tokens(&entry);
+            // smaller files of lower rank may still fit
+            if tokens + entry_tokens > self.max_tokens {
+                continue;
+            }
+            tokens += entry_tokens;
+            map.push_str(&entry);
+        }
+        self.cache = Some((task.to_string(), map.clone()));
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::tokenizer::ApproximateTokenizer;
+    use crate::tools::test_dir;
+
+    #[test]
+    fn test_repo_map() {
+        let workspace = test_dir("repo-map");
+        fs::create_dir_all(workspace.join("src")).unwrap();
+        fs::write(
+            workspace.join("src/parser.rs"),
+            "pub struct Parser {}\n\nimpl Parser {\n    pub fn parse(&self) {}\n}\n",
+        )
+        .unwrap();
+        fs::write(
+            workspace.join("src/main.rs"),
+            "fn main() {\n    Parser {}.parse();\n}\n",
+        )
+        .unwrap();
+        fs::write(workspace.join("src/unused.rs"), "fn unused() {}\n").unwrap();
+
+        let mut repo_map = RepoMap::new(&workspace, 1000, Arc::new(ApproximateTokenizer));
+        // the parser is used by main, so it's ranked first
+        let map = repo_map.render("");
+        assert!(map.starts_with(
+            "src/parser.rs:\n  pub struct Parser {}\n  impl Parser\n    pub fn parse(&self)\n"
+        ));
+        assert!(map.contains("src/main.rs:\n  fn main()\n"));
+        // the task mentions the unused function
+        assert!(repo_map
+            .render("Remove the unused function")
+            .starts_with("src/unused.rs:"));
+
+        // the budget fits only the first file
+        let mut repo_map = RepoMap::new(&workspace, 10, Arc::new(ApproximateTokenizer));
+        assert_eq!(repo_map.render("Fix main"), "src/main.rs:\n  fn main()\n");
+
+        // files are parsed again only after they are marked as modified
+        fs::write(workspace.join("src/main.rs"), "fn start() {}\n").unwrap();
+        assert_eq!(repo_map.render("Fix main"), "src/main.rs:\n  fn main()\n");
+        repo_map.mark_stale();
+        assert!(!repo_map.render("Fix main").contains("fn main()"));
+        fs::remove_dir_all(workspace).ok();
+    }
+}
@@ -0,0 +1,353 @@
+// Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use rig::message::{Message, UserContent};
+use serde::{Deserialize, Serialize};
+
+use crate::providers::model_info::ModelInfo;
+use crate::HISTORY_PATH;
+
+const SESSIONS_DIR: &str = "sessions";
+const SESSION_INFO_PATH: &str = "session.json";
+/// Directory of the session with the message history saved before every compaction
+const COMPACTED_HISTORY_DIR: &str = "compacted";
+const MAX_TITLE_LEN: usize = 80;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub id: String,
+    /// Title of the session, by default the first line of the first user message
+    #[serde(default)]
+    pub title: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// Total tokens sent to the model during the session
+    #[serde(default)]
+    pub input_tokens: u64,
+    /// Total tokens received from the model during the session
+    #[serde(default)]
+    pub completion_tokens: u64,
+    /// Total API cost of the session
+    #[serde(default)]
+    pub cost: f64,
+}
+
+impl SessionInfo {
+    fn new(id: String) -> Self {
+        let now = Utc::now();
+        Self {
+            id,
+            title: String::new(),
+            created_at: now,
+            updated_at: now,
+            input_tokens: 0,
+            completion_tokens: 0,
+            cost: 0.0,
+        }
+    }
+}
+
+/// Stores every task in a separate directory `<data_dir>/sessions/<id>`
+/// with the message history and session info
+#[derive(Debug, Clone)]
+pub struct SessionStore {
+    root: PathBuf,
+}
+
+/// Builds session title from the first user message
+pub fn session_title(messages: &[Message]) -> Option<String> {
+    messages.iter().find_map(|message| {
+        let Message::User { content } = message else {
+            return None;
+        };
+        let UserContent::Text(text) = content.first() else {
+            return None;
+        };
+        let line = text
+            .text
+            .lines()
+            .find(|line| !line.trim().is_empty())?
+            .trim();
+        if line.chars().count() > MAX_TITLE_LEN {
+            Some(format!(
+                "{}...",
+                line.chars().take(MAX_TITLE_LEN).collect::<String>()
+            ))
+        } else {
+            Some(line.to_string())
+        }
+    })
+}
+
+impl SessionStore {
+    pub fn new(data_dir: &Path) -> Result<Self> {
+        let store = Self {
+            root: data_dir.join(SESSIONS_DIR),
+        };
+        fs::create_dir_all(&store.root)?;
+        store.migrate_legacy_history(data_dir)?;
+        Ok(store)
+    }
+
+    /// Moves `history.json` of previous versions into a separate session
+    fn migrate_legacy_history(&self, data_dir: &Path) -> Result<()> {
+        let legacy_path = data_dir.join(HISTORY_PATH);
+        if !legacy_path.exists() {
+            return Ok(());
+        }
+        let messages: Vec<Message> = serde_json::from_str(&fs::read_to_string(&legacy_path)?)?;
+        if !messages.is_empty() {
+            let session = self.create();
+            tracing::info!(
+                "Migrate {} to session {}",
+                legacy_path.display(),
+                session.id
+            );
+            self.save_messages(&session.id, &messages)?;
+        }
+        fs::remove_file(legacy_path)?;
+        Ok(())
+    }
+
+    pub fn session_dir(&self, id: &str) -> PathBuf {
+        self.root.join(id)
+    }
+
+    /// Returns all sessions sorted by last update time, most recent first
+    pub fn list(&self) -> Vec<SessionInfo> {
+        let mut sessions = fs::read_dir(&self.root)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .filter_map(|entry| self.info(&entry.file_name().to_string_lossy()).ok())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        sessions.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+        sessions
+    }
+
+    pub fn latest(&self) -> Option<SessionInfo> {
+        self.list().into_iter().next()
+    }
+
+    /// Creates a new session, it is stored on disk only after the first message is saved
+    pub fn create(&self) -> SessionInfo {
+        let now = Utc::now();
+        let base_id = now.format("%Y%m%d-%H%M%S").to_string();
+        let mut id = base_id.clone();
+        let mut idx = 1;
+        while self.session_dir(&id).exists() {
+            idx += 1;
+            id = format!("{base_id}-{idx}");
+        }
+        SessionInfo::new(id)
+    }
+
+    /// Finds session by full id or by unique id prefix
+    pub fn resolve(&self, id: &str) -> Result<SessionInfo> {
+        if let Ok(info) = self.info(id) {
+            return Ok(info);
+        }
+        let mut found = self
+            .list()
+            .into_iter()
+            .filter(|session| session.id.starts_with(id));
+        match (found.next(), found.next()) {
+            (Some(session), None) => Ok(session),
+            (Some(_), Some(_)) => Err(anyhow!("Session id '{id}' is ambiguous")),
+            _ => Err(anyhow!("Session '{id}' not found")),
+        }
+    }
+
+    pub fn info(&self, id: &str) -> Result<SessionInfo> {
+        let path = self.session_dir(id).join(SESSION_INFO_PATH);
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    fn save_info(&self, info: &SessionInfo) -> Result<()> {
+        let dir = self.session_dir(&info.id);
+        fs::create_dir_all(&dir)?;
+        fs::write(
+            dir.join(SESSION_INFO_PATH),
+            serde_json::to_string_pretty(info)?,
+        )?;
+        Ok(())
+    }
+
+    fn info_or_new(&self, id: &str) -> SessionInfo {
+        self.info(id)
+            .unwrap_or_else(|_| SessionInfo::new(id.to_string()))
+    }
+
+    pub fn messages(&self, id: &str) -> Result<Vec<Message>> {
+        let path = self.session_dir(id).join(HISTORY_PATH);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save_messages(&self, id: &str, messages: &[Message]) -> Result<()> {
+        let dir = self.session_dir(id);
+        if messages.is_empty() && !dir.exists() {
+            return Ok(());
+        }
+        let mut info = self.info_or_new(id);
+        if info.title.is_empty() {
+            info.title = session_title(messages).unwrap_or_default();
+        }
+        info.updated_at = Utc::now();
+        self.save_info(&info)?;
+        fs::write(
+            dir.join(HISTORY_PATH),
+            serde_json::to_string_pretty(messages)?,
+        )?;
+        Ok(())
+    }
+
+    /// Keeps a copy of the message history replaced by the compaction, returns path of the copy
+    pub fn archive_messages(&self, id: &str, messages: &[Message]) -> Result<PathBuf> {
+        let dir = self.session_dir(id).join(COMPACTED_HISTORY_DIR);
+        fs::create_dir_all(&dir)?;
+        let timestamp = Utc::now().format("%Y%m%d-%H%M%S");
+        let mut path = dir.join(format!("history-{timestamp}.json"));
+        let mut n = 1;
+        while path.exists() {
+            n += 1;
+            path = dir.join(format!("history-{timestamp}-{n}.json"));
+        }
+        fs::write(&path, serde_json::to_string_pretty(messages)?)?;
+        Ok(path)
+    }
+
+    pub fn add_usage(
+        &self,
+        id: &str,
+        input_tokens: u32,
+        completion_tokens: u32,
+        model_info: &ModelInfo,
+    ) -> Result<()> {
+        let mut info = self.info_or_new(id);
+        info.input_tokens += input_tokens as u64;
+        info.completion_tokens += completion_tokens as u64;
+        info.cost += model_info.input_price * input_tokens as f64
+            + model_info.completion_price * completion_tokens as f64;
+        self.save_info(&info)
+    }
+
+    pub fn rename(&self, id: &str, title: &str) -> Result<SessionInfo> {
+        let mut info = self.info(id)?;
+        info.title = title.trim().to_string();
+        self.save_info(&info)?;
+        Ok(info)
+    }
+
+    /// Copies session with all its data into a new session
+    pub fn fork(&self, id: &str) -> Result<SessionInfo> {
+        let source = self.info(id)?;
+        let mut info = self.create();
+        info.title = format!("{} (fork)", source.title);
+        info.input_tokens = source.input_tokens;
+        info.completion_tokens = source.completion_tokens;
+        info.cost = source.cost;
+        copy_dir(&self.session_dir(id), &self.session_dir(&info.id))?;
+        self.save_info(&info)?;
+        Ok(info)
+    }
+
+    pub fn delete(&self, id: &str) -> Result<()> {
+        let dir = self.session_dir(id);
+        if !dir.exists() {
+            return Err(anyhow!("Session '{id}' not found"));
+        }
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::test_dir;
+
+    fn test_store(name: &str) -> (PathBuf, SessionStore) {
+        let dir = test_dir(name);
+        let store = SessionStore::new(&dir).unwrap();
+        (dir, store)
+    }
+
+    #[test]
+    fn test_session_lifecycle() {
+        let (dir, store) = test_store("sessions");
+        let session = store.create();
+        store.save_messages(&session.id, &[]).unwrap();
+        assert!(store.list().is_empty());
+
+        let messages = vec![
+            Message::user("\nCreate hello world app\nin rust"),
+            Message::assistant("ok"),
+        ];
+        store.save_messages(&session.id, &messages).unwrap();
+        let info = store.resolve(&session.id[..8]).unwrap();
+        assert_eq!(info.title, "Create hello world app");
+        assert_eq!(store.messages(&session.id).unwrap(), messages);
+
+        let fork = store.fork(&session.id).unwrap();
+        assert_ne!(fork.id, session.id);
+        assert_eq!(store.messages(&fork.id).unwrap(), messages);
+        assert_eq!(store.list().len(), 2);
+        assert!(store.resolve(&session.id[..8]).is_err());
+
+        store.rename(&fork.id, "Forked task").unwrap();
+        store.save_messages(&fork.id, &messages).unwrap();
+        assert_eq!(store.info(&fork.id).unwrap().title, "Forked task");
+        assert_eq!(store.latest().unwrap().id, fork.id);
+
+        let first = store.archive_messages(&fork.id, &messages).unwrap();
+        let second = store.archive_messages(&fork.id, &messages[..1]).unwrap();
+        assert_ne!(first, second);
+        let archived: Vec<Message> =
+            serde_json::from_str(&fs::read_to_string(second).unwrap()).unwrap();
+        assert_eq!(archived, messages[..1]);
+        assert_eq!(store.messages(&fork.id).unwrap(), messages);
+
+        store.delete(&session.id).unwrap();
+        assert_eq!(store.list().len(), 1);
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_migrate_legacy_history() {
+        let dir = test_dir("legacy");
+        let messages = vec![Message::user("Legacy task")];
+        fs::write(
+            dir.join(HISTORY_PATH),
+            serde_json::to_string(&messages).unwrap(),
+        )
+        .unwrap();
+        let store = SessionStore::new(&dir).unwrap();
+        assert!(!dir.join(HISTORY_PATH).exists());
+        let session = store.latest().unwrap();
+        assert_eq!(session.title, "Legacy task");
+        assert_eq!(store.messages(&session.id).unwrap(), messages);
+        fs::remove_dir_all(dir).ok();
+    }
+}
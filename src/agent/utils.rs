@@ -5,24 +5,26 @@ use std::path::Path;
 use std::sync::Arc;
 
 use itertools::Itertools;
-use rig::message::{Message, UserContent};
+use rig::message::{ImageMediaType, Message, UserContent};
 use tokio::sync::RwLock;
 
+use crate::agent::repo_map::RepoMap;
 use crate::config::Config;
 use crate::templates::{ENV_DETAILS, SYSTEM_PROMPT};
 use crate::tools::execute_command::ProcessRegistry;
 use crate::tools::memory::indexer::MemoryIndexer;
-use crate::HISTORY_PATH;
 
 pub const MAX_FILES: usize = 10000;
 
-fn get_shell_path() -> String {
-    if let Ok(shell) = std::env::var("SHELL") {
-        shell
-    } else if let Ok(comspec) = std::env::var("COMSPEC") {
-        comspec
-    } else {
-        panic!("Could not determine shell path from environment variables")
+pub fn image_media_type(mime_type: &str) -> ImageMediaType {
+    match mime_type {
+        "image/jpeg" => ImageMediaType::JPEG,
+        "image/gif" => ImageMediaType::GIF,
+        "image/webp" => ImageMediaType::WEBP,
+        "image/heic" => ImageMediaType::HEIC,
+        "image/heif" => ImageMediaType::HEIF,
+        "image/svg+xml" => ImageMediaType::SVG,
+        _ => ImageMediaType::PNG,
     }
 }
 
@@ -39,7 +41,7 @@ pub async fn prepare_system_prompt(config: &Config) -> String {
             ("WORKSPACE_DIR", workspace_dir.as_str()),
             ("USER_NAME", &config.appearance.user_name),
             ("OS_NAME", std::env::consts::OS),
-            ("OS_SHELL_EXECUTABLE", &get_shell_path()),
+            ("OS_SHELL_EXECUTABLE", &config.terminal.shell),
             ("USER_HOME_DIR", dirs::home_dir().unwrap().to_str().unwrap()),
             ("USER_INSTRUCTION", &config.user_instructions),
         ]),
@@ -50,6 +52,8 @@ pub async fn prepare_system_prompt(config: &Config) -> String {
 pub async fn add_env_message<'a>(
     msg: &'a mut Message,
     memory_index: Arc<RwLock<MemoryIndexer>>,
+    repo_map: Option<Arc<RwLock<RepoMap>>>,
+    task: &str,
     data_dir: &'a Path,
     workspace: &'a Path,
     process_registry: Arc<RwLock<ProcessRegistry>>,
@@ -115,6 +119,21 @@ pub async fn add_env_message<'a>(
                 )
             })
             .join("\n");
+        let repo_map = match repo_map {
+            Some(repo_map) => {
+                // the map is cached for the task, so it's rendered again only when files change
+                let task = task.to_string();
+                let map =
+                    tokio::task::spawn_blocking(move || repo_map.blocking_write().render(&task))
+                        .await
+                        .unwrap_or_default();
+                format!(
+                    "\n# Repository Map (most relevant files and definitions)\n{}",
+                    map
+                )
+            }
+            None => String::new(),
+        };
         let env_content = subst::substitute(
             ENV_DETAILS,
             &HashMap::from([
@@ -123,6 +142,7 @@ pub async fn add_env_message<'a>(
                 ("MEMORY_ENTRIES", &memory_entries),
                 ("COMMANDS", &commands),
                 ("FILES", files),
+                ("REPO_MAP", &repo_map),
             ]),
         )
         .unwrap();
@@ -130,11 +150,3 @@ pub async fn add_env_message<'a>(
         content.push(UserContent::text(env_content));
     }
 }
-
-pub fn persist_history(data_dir: &Path, messages: &[Message]) {
-    fs::write(
-        data_dir.join(HISTORY_PATH),
-        serde_json::to_string_pretty(messages).unwrap(),
-    )
-    .unwrap();
-}
@@ -15,6 +15,8 @@ pub enum ProviderKind {
     OpenRouter,
     LMStudio,
     Anthropic,
+    /// Scripted provider that replays model responses from a fixture file
+    Replay,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -82,6 +84,34 @@ pub enum PermissionMode {
     DenyAll,
 }
 
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PermissionAction {
+    Allow,
+    Deny,
+    Ask,
+}
+
+/// Permission rule for a tool call, all specified conditions must match
+#[derive(Debug, Deserialize, Clone)]
+pub struct PermissionRule {
+    /// Tool name, `*` matches any tool
+    pub tool: String,
+    pub action: PermissionAction,
+    /// Glob pattern of the `path` argument relative to the workspace, e.g. `src/**/*.rs`
+    pub path: Option<String>,
+    /// Regular expression of the `command` argument
+    pub command: Option<String>,
+    /// Prefix of the `command` argument, e.g. `cargo test`
+    pub command_prefix: Option<String>,
+    /// Hosts of the `url` argument, `*.example.com` matches all subdomains
+    pub hosts: Option<Vec<String>>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
 fn default_user() -> String {
     "default_user".to_string()
 }
@@ -103,6 +133,215 @@ pub enum EmbeddingProvider {
     Fastembed,
 }
 
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TokenizerConfig {
+    /// Rough estimation of 4 characters per token
+    Approximate,
+    /// OpenAI BPE tokenizer, encoding is selected by model name if not set
+    Tiktoken { encoding: Option<String> },
+    /// HuggingFace `tokenizer.json` file of a local model
+    HuggingFace { path: PathBuf },
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ReplayConfig {
+    /// Fixture file (yaml or json) with model responses
+    pub fixture: PathBuf,
+    /// Record responses of the configured provider into the fixture file instead of replaying it
+    #[serde(default)]
+    pub record: bool,
+}
+
+fn default_compaction_threshold() -> f32 {
+    0.8
+}
+
+fn default_keep_last_messages() -> usize {
+    10
+}
+
+fn default_max_tool_result_tokens() -> u32 {
+    2000
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CompactionConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Part of the model context window (0.0 - 1.0) after which the history is compacted
+    #[serde(default = "default_compaction_threshold")]
+    pub threshold: f32,
+    /// Number of last messages that are never compacted
+    #[serde(default = "default_keep_last_messages")]
+    pub keep_last_messages: usize,
+    /// Tool results of older messages above this limit are truncated
+    #[serde(default = "default_max_tool_result_tokens")]
+    pub max_tool_result_tokens: u32,
+    /// Model used to summarize older messages, the main model is used if not set
+    pub model: Option<String>,
+}
+
+impl Default for CompactionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold: default_compaction_threshold(),
+            keep_last_messages: default_keep_last_messages(),
+            max_tool_result_tokens: default_max_tool_result_tokens(),
+            model: None,
+        }
+    }
+}
+
+fn default_terminal_rows() -> u16 {
+    24
+}
+
+fn default_shell() -> String {
+    "bash".to_string()
+}
+
+fn default_command_timeout() -> u64 {
+    30
+}
+
+fn default_max_command_output() -> usize {
+    10000
+}
+
+fn default_terminal_cols() -> u16 {
+    120
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TerminalConfig {
+    /// Run commands in a pseudo-terminal, so interactive programs, progress bars
+    /// and colored output behave like in a real terminal
+    #[serde(default)]
+    pub pty: bool,
+    /// Run commands in a long-lived shell session, so the working directory, environment
+    /// variables and shell functions are kept between commands
+    #[serde(default)]
+    pub persistent_shell: bool,
+    /// Shell running commands as `<shell> <shell_args> -c <command>`
+    #[serde(default = "default_shell")]
+    pub shell: String,
+    /// Arguments of the shell before `-c`, e.g. `--login`
+    #[serde(default)]
+    pub shell_args: Vec<String>,
+    /// Environment variables added to or overridden for commands
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Variables with values redacted from the command output and logs, variables
+    /// named like secrets (e.g. `API_KEY`, `GITHUB_TOKEN`) are redacted anyway
+    #[serde(default)]
+    pub secret_env: Vec<String>,
+    /// Size of the pseudo-terminal
+    #[serde(default = "default_terminal_rows")]
+    pub rows: u16,
+    #[serde(default = "default_terminal_cols")]
+    pub cols: u16,
+    /// Seconds to wait for a command before it continues in the background
+    #[serde(default = "default_command_timeout")]
+    pub timeout_secs: u64,
+    /// Characters of the command output returned to the model, the rest is elided in the middle
+    #[serde(default = "default_max_command_output")]
+    pub max_output: usize,
+}
+
+impl Default for TerminalConfig {
+    fn default() -> Self {
+        Self {
+            pty: false,
+            persistent_shell: false,
+            shell: default_shell(),
+            shell_args: Vec::new(),
+            env: HashMap::new(),
+            secret_env: Vec::new(),
+            rows: default_terminal_rows(),
+            cols: default_terminal_cols(),
+            timeout_secs: default_command_timeout(),
+            max_output: default_max_command_output(),
+        }
+    }
+}
+
+fn default_repo_map_tokens() -> u32 {
+    1024
+}
+
+fn default_container_runtime() -> String {
+    "docker".to_string()
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CommandExecutorConfig {
+    /// Commands are run in the host shell with the user privileges
+    #[default]
+    Host,
+    /// Linux namespaces sandbox: the workspace and writable paths are mounted read-write, the rest is read-only
+    Bubblewrap {
+        #[serde(default)]
+        network: bool,
+        #[serde(default)]
+        writable_paths: Vec<PathBuf>,
+    },
+    /// Every command is run in a new container with the workspace mounted at the same path
+    Container {
+        image: String,
+        /// Container CLI, e.g. docker or podman
+        #[serde(default = "default_container_runtime")]
+        runtime: String,
+        #[serde(default)]
+        network: bool,
+        /// Extra arguments of the `run` command
+        #[serde(default)]
+        args: Vec<String>,
+    },
+}
+
+/// Executors of commands selected by the permission mode
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct SandboxConfig {
+    /// Executor of permission modes without their own executor
+    #[serde(default)]
+    pub default: CommandExecutorConfig,
+    pub full_autonomous: Option<CommandExecutorConfig>,
+    pub manual_approval: Option<CommandExecutorConfig>,
+    pub deny_all: Option<CommandExecutorConfig>,
+}
+
+impl SandboxConfig {
+    pub fn executor(&self, mode: &PermissionMode) -> &CommandExecutorConfig {
+        let executor = match mode {
+            PermissionMode::FullAutonomous => &self.full_autonomous,
+            PermissionMode::ManualApproval => &self.manual_approval,
+            PermissionMode::DenyAll => &self.deny_all,
+        };
+        executor.as_ref().unwrap_or(&self.default)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct RepoMapConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Token budget of the map in the environment details
+    #[serde(default = "default_repo_map_tokens")]
+    pub max_tokens: u32,
+}
+
+impl Default for RepoMapConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_tokens: default_repo_map_tokens(),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
     pub provider: ProviderKind,
@@ -112,12 +351,34 @@ pub struct Config {
     pub model: String,
     pub appearance: Appearance,
     pub permission_mode: PermissionMode,
+    /// Rules evaluated before the permission mode, the first matching rule wins
+    #[serde(default)]
+    pub permissions: Vec<PermissionRule>,
     pub workspace: PathBuf,
+    /// Directories outside of the workspace the agent is allowed to read
+    #[serde(default)]
+    pub read_only_roots: Vec<PathBuf>,
     pub user_instructions: String,
     pub mcp: Option<McpConfig>,
     pub web_search: Option<WebSearchProvider>,
     pub web_fetch: Option<WebFetchProvider>,
     pub memory_embedding: EmbeddingProvider,
+    pub replay: Option<ReplayConfig>,
+    #[serde(default)]
+    pub compaction: CompactionConfig,
+    /// Execution of commands by the execute_command tool
+    #[serde(default)]
+    pub terminal: TerminalConfig,
+    /// Sandboxes of commands by the permission mode
+    #[serde(default)]
+    pub sandbox: SandboxConfig,
+    /// Ranked summary of the repository added to the environment details
+    #[serde(default)]
+    pub repo_map: RepoMapConfig,
+    /// Tokenizer used to count tokens when provider doesn't report usage, selected by model if not set
+    pub tokenizer: Option<TokenizerConfig>,
+    /// Whether the model accepts images, detected by the model info if not set
+    pub vision: Option<bool>,
 }
 
 impl Config {
@@ -0,0 +1,218 @@
+// Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+use std::io::Write;
+use std::process::ExitCode;
+
+use rig::message::{AssistantContent, Message, ToolResultContent, UserContent};
+use serde_json::json;
+use tokio::sync::mpsc;
+
+use crate::agent::event::AgentState;
+use crate::agent::{AgentControlEvent, AgentOutputEvent};
+
+/// Task finished with `attempt_completion`
+const EXIT_COMPLETED: u8 = 0;
+/// Agent stopped with an error
+const EXIT_ERROR: u8 = 1;
+/// Agent waits for tool approval or an answer to a followup question
+const EXIT_PAUSED_ON_APPROVAL: u8 = 2;
+/// Agent is paused or waits for the next user prompt
+const EXIT_PAUSED: u8 = 3;
+
+const MAX_TEXT_TOOL_RESULT: usize = 2048;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable plain text
+    Text,
+    /// One JSON object per line for every agent event
+    Json,
+}
+
+struct Printer {
+    format: OutputFormat,
+    /// length of the assistant text that is already printed (text mode only)
+    printed_len: usize,
+}
+
+/// Runs a single prompt without TUI and streams agent events to stdout.
+/// Returns the process exit code derived from the final agent state.
+pub async fn run(
+    prompt: String,
+    format: OutputFormat,
+    sender: &mpsc::UnboundedSender<AgentControlEvent>,
+    receiver: &mut mpsc::UnboundedReceiver<AgentOutputEvent>,
+) -> ExitCode {
+    let mut printer = Printer {
+        format,
+        printed_len: 0,
+    };
+    if sender.send(AgentControlEvent::SendMessage(prompt)).is_err() {
+        return ExitCode::from(EXIT_ERROR);
+    }
+    // the agent reports its initial (paused) state before the prompt is processed,
+    // so the final state is the first paused state after the agent starts working
+    let mut started = false;
+    while let Some(event) = receiver.recv().await {
+        printer.print(&event);
+        if let AgentOutputEvent::AgentStatus(_, _, state) = event {
+            if !state.is_paused() {
+                started = true;
+            } else if started {
+                printer.finish(&state);
+                return ExitCode::from(exit_code(&state));
+            }
+        }
+    }
+    ExitCode::from(EXIT_ERROR)
+}
+
+fn exit_code(state: &AgentState) -> u8 {
+    match state {
+        AgentState::Completed => EXIT_COMPLETED,
+        AgentState::Error(_) => EXIT_ERROR,
+        AgentState::ToolCall(_, true) => EXIT_PAUSED_ON_APPROVAL,
+        _ => EXIT_PAUSED,
+    }
+}
+
+fn state_to_json(state: &AgentState) -> serde_json::Value {
+    match state {
+        AgentState::Error(msg) => json!({ "state": "error", "message": msg }),
+        AgentState::ToolCall(tool_call, need_confirm) => json!({
+            "state": "tool_call",
+            "tool_call": tool_call,
+            "need_confirm": need_confirm,
+        }),
+        state => json!({ "state": state.to_string() }),
+    }
+}
+
+impl Printer {
+    fn print(&mut self, event: &AgentOutputEvent) {
+        match self.format {
+            OutputFormat::Text => self.print_text(event),
+            OutputFormat::Json => Self::print_json(event),
+        }
+    }
+
+    fn finish(&self, state: &AgentState) {
+        if self.format == OutputFormat::Text {
+            println!();
+            match state {
+                AgentState::Error(msg) => eprintln!("Error: {msg}"),
+                AgentState::ToolCall(tool_call, true) => eprintln!(
+                    "Agent is waiting for confirmation of tool '{}'",
+                    tool_call.function.name
+                ),
+                _ => {}
+            }
+        }
+    }
+
+    fn print_json(event: &AgentOutputEvent) {
+        let value = match event {
+            AgentOutputEvent::AddMessage(message) => {
+                json!({ "type": "add_message", "message": message })
+            }
+            AgentOutputEvent::UpdateMessage(message) => {
+                json!({ "type": "update_message", "message": message })
+            }
+            AgentOutputEvent::NewTask => json!({ "type": "new_task" }),
+            AgentOutputEvent::CommandStatus(statuses) => json!({
+                "type": "command_status",
+                "commands": statuses.iter().map(|status| json!({
+                    "command_id": status.command_id,
+                    "command": status.command,
+                    "output": status.output,
+                    "is_active": status.is_active,
+                })).collect::<Vec<_>>(),
+            }),
+            AgentOutputEvent::AgentStatus(input_tokens, completion_tokens, state) => json!({
+                "type": "agent_status",
+                "input_tokens": input_tokens,
+                "completion_tokens": completion_tokens,
+                "status": state_to_json(state),
+            }),
+            AgentOutputEvent::HighlightFile(path, is_new) => {
+                json!({ "type": "highlight_file", "path": path, "is_new": is_new })
+            }
+            AgentOutputEvent::ReplaceMessages(messages) => {
+                json!({ "type": "replace_messages", "messages": messages })
+            }
+            AgentOutputEvent::SessionChanged(session_id) => {
+                json!({ "type": "session_changed", "session_id": session_id })
+            }
+        };
+        let mut stdout = std::io::stdout().lock();
+        let _ = writeln!(stdout, "{value}");
+        let _ = stdout.flush();
+    }
+
+    fn print_text(&mut self, event: &AgentOutputEvent) {
+        let mut stdout = std::io::stdout().lock();
+        match event {
+            AgentOutputEvent::AddMessage(message) | AgentOutputEvent::UpdateMessage(message) => {
+                let is_update = matches!(event, AgentOutputEvent::UpdateMessage(_));
+                match message {
+                    Message::Assistant { content } => {
+                        for item in content.iter() {
+                            match item {
+                                AssistantContent::Text(text) => {
+                                    if !is_update {
+                                        self.printed_len = 0;
+                                    }
+                                    if let Some(delta) = text.text.get(self.printed_len..) {
+                                        let _ = write!(stdout, "{delta}");
+                                    }
+                                    self.printed_len = text.text.len();
+                                }
+                                AssistantContent::ToolCall(tool_call) => {
+                                    self.printed_len = 0;
+                                    let _ = writeln!(
+                                        stdout,
+                                        "\n[{}] {}",
+                                        tool_call.function.name, tool_call.function.arguments
+                                    );
+                                }
+                            }
+                        }
+                    }
+                    Message::User { content } => {
+                        for item in content.iter() {
+                            if let UserContent::ToolResult(tool_result) = item {
+                                for result in tool_result.content.iter() {
+                                    if let ToolResultContent::Text(text) = result {
+                                        let text =
+                                            serde_json::from_str::<serde_json::Value>(&text.text)
+                                                .ok()
+                                                .and_then(|v| v.as_str().map(|s| s.to_string()))
+                                                .unwrap_or(text.text.clone());
+                                        let _ = writeln!(
+                                            stdout,
+                                            "{}",
+                                            truncate(&text, MAX_TEXT_TOOL_RESULT)
+                                        );
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        let _ = stdout.flush();
+    }
+}
+
+fn truncate(text: &str, max_len: usize) -> String {
+    let text = text.trim();
+    if text.len() <= max_len {
+        return text.to_string();
+    }
+    let mut end = max_len;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}\n...truncated...", &text[..end])
+}
@@ -5,6 +5,7 @@ use std::io::stdout;
 use std::panic::set_hook;
 use std::panic::take_hook;
 use std::path::Path;
+use std::process::ExitCode;
 use std::sync::Arc;
 
 use crossterm::execute;
@@ -22,6 +23,10 @@ use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::Layer;
 
 use self::config::Config;
+use self::headless::OutputFormat;
+use crate::agent::checkpoint::CheckpointStore;
+use crate::agent::session::SessionInfo;
+use crate::agent::session::SessionStore;
 use crate::agent::AgentControlEvent;
 use crate::agent::AgentOutputEvent;
 use crate::tools::memory::indexer::MemoryIndexer;
@@ -30,6 +35,7 @@ use clap::Parser;
 
 mod agent;
 mod config;
+mod headless;
 pub mod providers;
 pub mod templates;
 pub mod tools;
@@ -41,9 +47,37 @@ const CONFIG_STATE_FILE_PATH: &str = "config_state.yaml";
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
-    /// Skip loading previous session from history.json file
+    /// Start a new session instead of resuming the latest one
     #[arg(short, long)]
     skip_load_messages: bool,
+    /// Resume the session by id (or unique id prefix)
+    #[arg(long, conflicts_with = "fork_session")]
+    session: Option<String>,
+    /// Resume the latest session in headless mode, it starts a new session by default
+    #[arg(long, conflicts_with = "skip_load_messages")]
+    resume: bool,
+    /// Copy the session by id into a new session and resume it
+    #[arg(long)]
+    fork_session: Option<String>,
+    /// Print the list of sessions and exit
+    #[arg(long)]
+    list_sessions: bool,
+    /// Rename the session and exit
+    #[arg(long, num_args = 2, value_names = ["ID", "TITLE"])]
+    rename_session: Option<Vec<String>>,
+    /// Delete the session and exit
+    #[arg(long)]
+    delete_session: Option<String>,
+    /// Print file checkpoints of the session (--session or the latest one) and exit
+    #[arg(long)]
+    list_checkpoints: bool,
+    /// Restore workspace files of the session (--session or the latest one)
+    /// to the state before the message with the given index and exit
+    #[arg(long, value_name = "MESSAGE_IDX")]
+    rollback: Option<usize>,
+    /// Also truncate the message history to the message on --rollback
+    #[arg(long, requires = "rollback")]
+    truncate_history: bool,
     /// Path to data directory
     #[arg(short, long, default_value = "data")]
     data: String,
@@ -53,6 +87,120 @@ struct Args {
     /// For theme development, auto reload theme file on changes
     #[arg(long, default_value = "false")]
     autoreload_theme: bool,
+    /// Run without TUI: send the prompt to the agent, print its output and exit
+    #[arg(short, long, conflicts_with = "prompt_file")]
+    prompt: Option<String>,
+    /// Same as --prompt but reads the prompt from file ("-" to read from stdin)
+    #[arg(long)]
+    prompt_file: Option<String>,
+    /// Output format of headless mode
+    #[arg(long, value_enum, default_value = "text")]
+    output_format: OutputFormat,
+}
+
+fn read_prompt(args: &Args) -> io::Result<Option<String>> {
+    if let Some(prompt) = &args.prompt {
+        return Ok(Some(prompt.clone()));
+    }
+    match args.prompt_file.as_deref() {
+        Some("-") => io::read_to_string(io::stdin()).map(Some),
+        Some(path) => fs::read_to_string(path).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Executes session management commands, returns true if a command was executed
+fn handle_session_commands(args: &Args, sessions: &SessionStore) -> anyhow::Result<bool> {
+    if args.list_sessions {
+        println!(
+            "{:<20} {:<17} {:>10} {:>8}  TITLE",
+            "ID", "UPDATED", "TOKENS", "COST"
+        );
+        for session in sessions.list() {
+            println!(
+                "{:<20} {:<17} {:>10} {:>8.2}  {}",
+                session.id,
+                session
+                    .updated_at
+                    .with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M"),
+                session.input_tokens + session.completion_tokens,
+                session.cost,
+                session.title
+            );
+        }
+        return Ok(true);
+    }
+    if let Some([id, title]) = args.rename_session.as_deref() {
+        let session = sessions.rename(&sessions.resolve(id)?.id, title)?;
+        println!("Session {} renamed to '{}'", session.id, session.title);
+        return Ok(true);
+    }
+    if let Some(id) = &args.delete_session {
+        let session = sessions.resolve(id)?;
+        sessions.delete(&session.id)?;
+        println!("Session {} deleted", session.id);
+        return Ok(true);
+    }
+    if args.list_checkpoints || args.rollback.is_some() {
+        let session = match &args.session {
+            Some(id) => sessions.resolve(id)?,
+            None => sessions
+                .latest()
+                .ok_or_else(|| anyhow::anyhow!("No sessions found"))?,
+        };
+        let checkpoints = CheckpointStore::new(&sessions.session_dir(&session.id));
+        if let Some(message_idx) = args.rollback {
+            for path in checkpoints.rollback(message_idx)? {
+                println!("Restored {}", path.display());
+            }
+            if args.truncate_history {
+                let mut messages = sessions.messages(&session.id)?;
+                messages.truncate(message_idx);
+                sessions.save_messages(&session.id, &messages)?;
+                println!("History truncated to {} messages", messages.len());
+            }
+        } else {
+            println!("{:>7} {:<17} FILE", "MESSAGE", "CREATED");
+            for checkpoint in checkpoints.list()? {
+                println!(
+                    "{:>7} {:<17} {}{}",
+                    checkpoint.message_idx,
+                    checkpoint
+                        .created_at
+                        .with_timezone(&chrono::Local)
+                        .format("%Y-%m-%d %H:%M"),
+                    checkpoint.path.display(),
+                    if checkpoint.snapshot.is_none() {
+                        " (new)"
+                    } else {
+                        ""
+                    }
+                );
+            }
+        }
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// Selects the session to start with according to command line arguments
+fn select_session(
+    args: &Args,
+    sessions: &SessionStore,
+    headless: bool,
+) -> anyhow::Result<SessionInfo> {
+    if let Some(id) = &args.fork_session {
+        return sessions.fork(&sessions.resolve(id)?.id);
+    }
+    if let Some(id) = &args.session {
+        return sessions.resolve(id);
+    }
+    // a headless run is a new task unless it's asked to continue the previous one
+    if args.skip_load_messages || (headless && !args.resume) {
+        return Ok(sessions.create());
+    }
+    Ok(sessions.latest().unwrap_or_else(|| sessions.create()))
 }
 
 fn init_logger(data_dir: &str) {
@@ -86,6 +234,11 @@ fn init_logger(data_dir: &str) {
 fn init_panic_hook() {
     let original_hook = take_hook();
     set_hook(Box::new(move |panic_info| {
+        // the panic of the document parser is returned as an error by the extracting thread
+        if std::thread::current().name() == Some(tools::document::EXTRACT_THREAD_NAME) {
+            tracing::warn!("Document extraction panicked: {}", panic_info);
+            return;
+        }
         // intentionally ignore errors here since we're already in a panic
         let backtrace = std::backtrace::Backtrace::capture();
         tracing::error!("{}, {:#?}", panic_info, backtrace);
@@ -109,14 +262,24 @@ pub fn restore_tui() -> io::Result<()> {
 }
 
 #[tokio::main]
-async fn main() -> color_eyre::Result<()> {
+async fn main() -> color_eyre::Result<ExitCode> {
     color_eyre::install()?;
     init_panic_hook();
     let args = Args::parse();
+    let headless_prompt = read_prompt(&args)?;
 
     init_logger(&args.data);
 
     tracing::info!("Start");
+    let sessions =
+        SessionStore::new(Path::new(&args.data)).map_err(|e| color_eyre::eyre::eyre!(e))?;
+    if handle_session_commands(&args, &sessions).map_err(|e| color_eyre::eyre::eyre!(e))? {
+        return Ok(ExitCode::SUCCESS);
+    }
+    let session = select_session(&args, &sessions, headless_prompt.is_some())
+        .map_err(|e| color_eyre::eyre::eyre!(e))?;
+    tracing::info!("Session: {} '{}'", session.id, session.title);
+
     let config = match Config::new(&args.config) {
         Ok(config) => config,
         Err(e) => {
@@ -134,17 +297,14 @@ async fn main() -> color_eyre::Result<()> {
         fs::create_dir_all(&config.workspace)?;
     }
 
-    let history_path = data_dir.join(HISTORY_PATH);
     // start agent
-    let (output_sender, output_receiver) =
+    let (output_sender, mut output_receiver) =
         tokio::sync::mpsc::unbounded_channel::<AgentOutputEvent>();
     let (control_sender, control_receiver) =
         tokio::sync::mpsc::unbounded_channel::<AgentControlEvent>();
-    let history = if !args.skip_load_messages && history_path.exists() {
-        serde_json::from_str(&std::fs::read_to_string(history_path).unwrap()).unwrap()
-    } else {
-        Vec::new()
-    };
+    let history = sessions
+        .messages(&session.id)
+        .map_err(|e| color_eyre::eyre::eyre!(e))?;
 
     let model_info = model_info(&args.data, &config).await?;
     tracing::info!("Model info: {:?}", model_info);
@@ -155,17 +315,44 @@ async fn main() -> color_eyre::Result<()> {
         memory_index.clone(),
         false,
     )));
-    let mut agent = agent::Agent::new(&args.data, config.clone(), memory.clone(), output_sender);
+    let mut agent = agent::Agent::new(
+        &args.data,
+        config.clone(),
+        model_info.clone(),
+        memory.clone(),
+        output_sender,
+    );
     memory_index.write().await.init(memory.clone()).await?;
 
     let messages = history.clone();
     let data_dir = args.data.clone();
+    let agent_sessions = sessions.clone();
     let agent_handler = tokio::spawn(async move {
         agent
-            .run(&args.data, control_receiver, messages, memory_index)
+            .run(
+                &args.data,
+                control_receiver,
+                agent_sessions,
+                session,
+                messages,
+                memory_index,
+            )
             .await;
     });
 
+    if let Some(prompt) = headless_prompt {
+        let exit_code = headless::run(
+            prompt,
+            args.output_format,
+            &control_sender,
+            &mut output_receiver,
+        )
+        .await;
+        agent_handler.abort();
+        let _ = agent_handler.await;
+        return Ok(exit_code);
+    }
+
     let terminal = init_tui().unwrap();
     let result = tui::App::new(
         config,
@@ -174,11 +361,12 @@ async fn main() -> color_eyre::Result<()> {
         control_sender,
         output_receiver,
         history,
+        sessions,
         args.autoreload_theme,
     )
     .run(terminal)
     .await;
     let _ = agent_handler.await;
     ratatui::restore();
-    result
+    result.map(|_| ExitCode::SUCCESS)
 }
@@ -9,6 +9,8 @@ use rig::tool::ToolSet;
 
 pub mod model_info;
 pub mod openrouter;
+pub mod replay;
+pub mod tokenizer;
 
 #[async_trait]
 pub trait HulyAgent: Send + Sync {
@@ -86,3 +88,24 @@ impl HulyAgent for Agent<rig::providers::anthropic::completion::CompletionModel>
         &self.tools
     }
 }
+
+#[async_trait]
+impl HulyAgent for Agent<replay::CompletionModel> {
+    async fn send_messages(
+        &self,
+        prompt: Message,
+        chat_history: Vec<Message>,
+    ) -> Result<
+        StreamingCompletionResponse<rig::providers::openai::StreamingCompletionResponse>,
+        CompletionError,
+    > {
+        self.stream_completion(prompt, chat_history)
+            .await?
+            .stream()
+            .await
+    }
+
+    fn tools(&self) -> &ToolSet {
+        &self.tools
+    }
+}
@@ -15,6 +15,8 @@ pub struct ModelInfo {
     pub input_price: f64,
     pub completion_price: f64,
     pub max_tokens: u32,
+    /// Model accepts images in the input
+    pub supports_vision: bool,
 }
 
 #[derive(Deserialize)]
@@ -22,6 +24,9 @@ struct LMStudioModelInfo {
     pub id: String,
     pub loaded_context_length: Option<u32>,
     pub max_context_length: u32,
+    /// `vlm` for vision models
+    #[serde(rename = "type")]
+    pub model_type: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -30,11 +35,19 @@ struct OpenRouterPriceInfo {
     pub completion: String,
 }
 
+#[derive(Deserialize, Default)]
+struct OpenRouterArchitectureInfo {
+    #[serde(default)]
+    pub input_modalities: Vec<String>,
+}
+
 #[derive(Deserialize)]
 struct OpenRouterModelInfo {
     pub id: String,
     pub pricing: OpenRouterPriceInfo,
     pub context_length: u32,
+    #[serde(default)]
+    pub architecture: OpenRouterArchitectureInfo,
 }
 
 #[derive(Deserialize)]
@@ -43,6 +56,8 @@ struct AnthropicModelInfo {
     pub input_price: f64,
     pub output_price: f64,
     pub max_context_tokens: u32,
+    #[serde(default)]
+    pub supports_vision: bool,
 }
 
 #[derive(Deserialize)]
@@ -51,6 +66,8 @@ struct OpenAIModelInfo {
     pub input_price: f64,
     pub output_price: f64,
     pub max_context_tokens: u32,
+    #[serde(default)]
+    pub supports_vision: bool,
 }
 
 pub async fn model_info(data_dir: &str, config: &Config) -> color_eyre::Result<ModelInfo> {
@@ -65,6 +82,7 @@ pub async fn model_info(data_dir: &str, config: &Config) -> color_eyre::Result<M
                     input_price: model.input_price,
                     completion_price: model.output_price,
                     max_tokens: model.max_context_tokens,
+                    supports_vision: model.supports_vision,
                 })
                 .ok_or_else(|| color_eyre::eyre::eyre!("Model not found"))
         }
@@ -89,6 +107,11 @@ pub async fn model_info(data_dir: &str, config: &Config) -> color_eyre::Result<M
                     input_price: model.pricing.prompt.parse::<f64>().unwrap_or(0.0),
                     completion_price: model.pricing.completion.parse::<f64>().unwrap_or(0.0),
                     max_tokens: model.context_length,
+                    supports_vision: model
+                        .architecture
+                        .input_modalities
+                        .iter()
+                        .any(|modality| modality == "image"),
                 })
                 .ok_or_else(|| color_eyre::eyre::eyre!("Model not found"))
         }
@@ -109,6 +132,7 @@ pub async fn model_info(data_dir: &str, config: &Config) -> color_eyre::Result<M
                     max_tokens: model
                         .loaded_context_length
                         .unwrap_or(model.max_context_length),
+                    supports_vision: model.model_type.as_deref() == Some("vlm"),
                 })
                 .ok_or_else(|| color_eyre::eyre::eyre!("Model not found"))
         }
@@ -121,8 +145,15 @@ pub async fn model_info(data_dir: &str, config: &Config) -> color_eyre::Result<M
                     input_price: model.input_price,
                     completion_price: model.output_price,
                     max_tokens: model.max_context_tokens,
+                    supports_vision: model.supports_vision,
                 })
                 .ok_or_else(|| color_eyre::eyre::eyre!("Model not found"))
         }
+        crate::config::ProviderKind::Replay => Ok(ModelInfo {
+            input_price: 0.0,
+            completion_price: 0.0,
+            max_tokens: 200000,
+            supports_vision: false,
+        }),
     }
 }
@@ -0,0 +1,354 @@
+// Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+//! Scripted completion provider that replays canned model responses from a fixture file,
+//! and a recorder that captures responses of a real provider into the same format.
+//!
+//! Fixture example:
+//! ```yaml
+//! turns:
+//!   - text: I will create the file
+//!     tool_calls:
+//!       - name: write_to_file
+//!         arguments: { path: hello.txt, content: Hello }
+//!   - tool_calls:
+//!       - name: attempt_completion
+//!         arguments: { result: Done }
+//! ```
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use async_stream::stream;
+use async_trait::async_trait;
+use futures::StreamExt;
+use rig::completion::{self, CompletionError, CompletionRequest};
+use rig::message::{AssistantContent, Message};
+use rig::providers::openai::Usage;
+use rig::streaming::{self, RawStreamingChoice, StreamingCompletionModel};
+use rig::tool::ToolSet;
+use rig::OneOrMany;
+use serde::{Deserialize, Serialize};
+
+use super::HulyAgent;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReplayToolCall {
+    /// Tool call id, generated if not specified
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub name: String,
+    #[serde(default)]
+    pub arguments: serde_json::Value,
+}
+
+/// Single model response
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReplayTurn {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ReplayToolCall>,
+    #[serde(default)]
+    pub prompt_tokens: usize,
+    #[serde(default)]
+    pub total_tokens: usize,
+    /// Provider error returned instead of the response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReplayFixture {
+    pub turns: Vec<ReplayTurn>,
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+}
+
+impl ReplayFixture {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let data = fs::read_to_string(path)?;
+        if is_json(path) {
+            Ok(serde_json::from_str(&data)?)
+        } else {
+            Ok(serde_yaml::from_str(&data)?)
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let data = if is_json(path) {
+            serde_json::to_string_pretty(self)?
+        } else {
+            serde_yaml::to_string(self)?
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, data)?;
+        Ok(())
+    }
+}
+
+/// Completion model that returns turns of the fixture one by one for every request
+#[derive(Clone)]
+pub struct CompletionModel {
+    turns: Arc<Mutex<VecDeque<ReplayTurn>>>,
+    tool_call_counter: Arc<AtomicUsize>,
+}
+
+impl CompletionModel {
+    pub fn new(fixture: ReplayFixture) -> Self {
+        Self {
+            turns: Arc::new(Mutex::new(fixture.turns.into())),
+            tool_call_counter: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    fn next_turn(&self) -> Result<ReplayTurn, CompletionError> {
+        let turn = self.turns.lock().unwrap().pop_front().ok_or_else(|| {
+            CompletionError::ProviderError("Replay fixture has no more turns".to_string())
+        })?;
+        if let Some(error) = turn.error {
+            return Err(CompletionError::ProviderError(error));
+        }
+        Ok(turn)
+    }
+
+    fn tool_call_id(&self, tool_call: &ReplayToolCall) -> String {
+        tool_call.id.clone().unwrap_or_else(|| {
+            format!(
+                "replay_{}",
+                self.tool_call_counter.fetch_add(1, Ordering::SeqCst)
+            )
+        })
+    }
+}
+
+impl completion::CompletionModel for CompletionModel {
+    type Response = ReplayTurn;
+
+    async fn completion(
+        &self,
+        _completion_request: CompletionRequest,
+    ) -> Result<completion::CompletionResponse<ReplayTurn>, CompletionError> {
+        let turn = self.next_turn()?;
+        let mut content = Vec::new();
+        if !turn.text.is_empty() {
+            content.push(AssistantContent::text(&turn.text));
+        }
+        for tool_call in turn.tool_calls.iter() {
+            content.push(AssistantContent::tool_call(
+                self.tool_call_id(tool_call),
+                &tool_call.name,
+                tool_call.arguments.clone(),
+            ));
+        }
+        let choice = OneOrMany::many(content).map_err(|_| {
+            CompletionError::ResponseError("Replay turn contains no text or tool call".to_owned())
+        })?;
+        Ok(completion::CompletionResponse {
+            choice,
+            raw_response: turn,
+        })
+    }
+}
+
+impl StreamingCompletionModel for CompletionModel {
+    type StreamingResponse = rig::providers::openai::StreamingCompletionResponse;
+
+    async fn stream(
+        &self,
+        _completion_request: CompletionRequest,
+    ) -> Result<streaming::StreamingCompletionResponse<Self::StreamingResponse>, CompletionError>
+    {
+        let turn = self.next_turn()?;
+        let tool_calls = turn
+            .tool_calls
+            .iter()
+            .map(|tool_call| (self.tool_call_id(tool_call), tool_call.clone()))
+            .collect::<Vec<_>>();
+
+        let stream = Box::pin(stream! {
+            if !turn.text.is_empty() {
+                yield Ok(RawStreamingChoice::Message(turn.text.clone()));
+            }
+            for (id, tool_call) in tool_calls.into_iter() {
+                yield Ok(RawStreamingChoice::ToolCall {
+                    id,
+                    name: tool_call.name,
+                    arguments: tool_call.arguments,
+                });
+            }
+            yield Ok(RawStreamingChoice::FinalResponse(rig::providers::openai::StreamingCompletionResponse {
+                usage: Usage {
+                    prompt_tokens: turn.prompt_tokens,
+                    total_tokens: turn.total_tokens,
+                }
+            }));
+        });
+        Ok(streaming::StreamingCompletionResponse::new(stream))
+    }
+}
+
+/// Appends model responses to the fixture file
+#[derive(Clone)]
+struct SessionRecorder {
+    path: PathBuf,
+    fixture: Arc<Mutex<ReplayFixture>>,
+}
+
+impl SessionRecorder {
+    fn record(&self, turn: ReplayTurn) {
+        let mut fixture = self.fixture.lock().unwrap();
+        fixture.turns.push(turn);
+        if let Err(e) = fixture.save(&self.path) {
+            tracing::error!("Failed to save replay fixture {:?}: {}", self.path, e);
+        }
+    }
+}
+
+/// Wraps real provider agent and records its responses into a replay fixture
+pub struct RecordingAgent {
+    inner: Box<dyn HulyAgent>,
+    recorder: SessionRecorder,
+}
+
+impl RecordingAgent {
+    pub fn new(inner: Box<dyn HulyAgent>, fixture_path: &Path) -> Self {
+        tracing::info!("Recording model responses to {:?}", fixture_path);
+        Self {
+            inner,
+            recorder: SessionRecorder {
+                path: fixture_path.to_path_buf(),
+                fixture: Arc::new(Mutex::new(ReplayFixture::default())),
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl HulyAgent for RecordingAgent {
+    async fn send_messages(
+        &self,
+        prompt: Message,
+        chat_history: Vec<Message>,
+    ) -> Result<
+        streaming::StreamingCompletionResponse<rig::providers::openai::StreamingCompletionResponse>,
+        CompletionError,
+    > {
+        let mut inner = match self.inner.send_messages(prompt, chat_history).await {
+            Ok(inner) => inner,
+            Err(e) => {
+                self.recorder.record(ReplayTurn {
+                    error: Some(e.to_string()),
+                    ..Default::default()
+                });
+                return Err(e);
+            }
+        };
+        let recorder = self.recorder.clone();
+        let stream = Box::pin(stream! {
+            let mut turn = ReplayTurn::default();
+            while let Some(result) = inner.next().await {
+                match result {
+                    Ok(AssistantContent::Text(text)) => {
+                        turn.text.push_str(&text.text);
+                        yield Ok(RawStreamingChoice::Message(text.text));
+                    }
+                    Ok(AssistantContent::ToolCall(tool_call)) => {
+                        turn.tool_calls.push(ReplayToolCall {
+                            id: Some(tool_call.id.clone()),
+                            name: tool_call.function.name.clone(),
+                            arguments: tool_call.function.arguments.clone(),
+                        });
+                        yield Ok(RawStreamingChoice::ToolCall {
+                            id: tool_call.id,
+                            name: tool_call.function.name,
+                            arguments: tool_call.function.arguments,
+                        });
+                    }
+                    Err(e) => {
+                        turn.error = Some(e.to_string());
+                        yield Err(e);
+                    }
+                }
+            }
+            let response: completion::CompletionResponse<
+                Option<rig::providers::openai::StreamingCompletionResponse>,
+            > = From::from(inner);
+            if let Some(raw_response) = response.raw_response {
+                turn.prompt_tokens = raw_response.usage.prompt_tokens;
+                turn.total_tokens = raw_response.usage.total_tokens;
+                recorder.record(turn);
+                yield Ok(RawStreamingChoice::FinalResponse(raw_response));
+            } else {
+                recorder.record(turn);
+            }
+        });
+        Ok(streaming::StreamingCompletionResponse::new(stream))
+    }
+
+    fn tools(&self) -> &ToolSet {
+        self.inner.tools()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_fixture() {
+        let fixture: ReplayFixture = serde_yaml::from_str(
+            r#"
+turns:
+  - text: I will create the file
+    tool_calls:
+      - name: write_to_file
+        arguments: { path: hello.txt, content: Hello }
+  - error: rate limit
+"#,
+        )
+        .unwrap();
+        assert_eq!(fixture.turns.len(), 2);
+        assert_eq!(fixture.turns[0].tool_calls[0].name, "write_to_file");
+        assert_eq!(fixture.turns[0].tool_calls[0].id, None);
+        assert_eq!(fixture.turns[1].error, Some("rate limit".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_stream_turns() {
+        let model = CompletionModel::new(ReplayFixture {
+            turns: vec![
+                ReplayTurn {
+                    text: "Hello".to_string(),
+                    tool_calls: vec![ReplayToolCall {
+                        id: None,
+                        name: "read_file".to_string(),
+                        arguments: serde_json::json!({ "path": "a.txt" }),
+                    }],
+                    ..Default::default()
+                },
+                ReplayTurn {
+                    error: Some("failed".to_string()),
+                    ..Default::default()
+                },
+            ],
+        });
+        let request = completion::CompletionModel::completion_request(&model, "test").build();
+        let mut stream = model.stream(request.clone()).await.unwrap();
+        let mut items = Vec::new();
+        while let Some(item) = stream.next().await {
+            items.push(item.unwrap());
+        }
+        assert_eq!(items.len(), 2);
+        assert!(matches!(&items[0], AssistantContent::Text(text) if text.text == "Hello"));
+        assert!(
+            matches!(&items[1], AssistantContent::ToolCall(tool_call) if tool_call.id == "replay_0")
+        );
+        assert!(model.stream(request.clone()).await.is_err());
+        assert!(model.stream(request).await.is_err());
+    }
+}
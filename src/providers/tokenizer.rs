@@ -0,0 +1,154 @@
+// Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+//! Token counting for providers that don't report usage and for context window estimations
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::config::{Config, ProviderKind, TokenizerConfig};
+
+const DEFAULT_TIKTOKEN_ENCODING: &str = "cl100k_base";
+
+pub trait Tokenizer: Send + Sync {
+    fn count_tokens(&self, text: &str) -> u32;
+}
+
+/// Rough estimation of 4 characters per token
+pub struct ApproximateTokenizer;
+
+impl Tokenizer for ApproximateTokenizer {
+    fn count_tokens(&self, text: &str) -> u32 {
+        text.len() as u32 / 4
+    }
+}
+
+/// BPE tokenizer used by OpenAI models
+pub struct TiktokenTokenizer {
+    bpe: tiktoken_rs::CoreBPE,
+}
+
+impl TiktokenTokenizer {
+    pub fn new(encoding: &str) -> anyhow::Result<Self> {
+        let bpe = match encoding {
+            "o200k_base" => tiktoken_rs::o200k_base()?,
+            "cl100k_base" => tiktoken_rs::cl100k_base()?,
+            "p50k_base" => tiktoken_rs::p50k_base()?,
+            "r50k_base" => tiktoken_rs::r50k_base()?,
+            _ => anyhow::bail!("Unknown tiktoken encoding '{encoding}'"),
+        };
+        Ok(Self { bpe })
+    }
+}
+
+impl Tokenizer for TiktokenTokenizer {
+    fn count_tokens(&self, text: &str) -> u32 {
+        self.bpe.encode_ordinary(text).len() as u32
+    }
+}
+
+/// Tokenizer of local models loaded from HuggingFace `tokenizer.json` file
+pub struct HuggingFaceTokenizer {
+    tokenizer: tokenizers::Tokenizer,
+}
+
+impl HuggingFaceTokenizer {
+    pub fn new(path: &Path) -> anyhow::Result<Self> {
+        let tokenizer = tokenizers::Tokenizer::from_file(path).map_err(|e| {
+            anyhow::anyhow!("Failed to load tokenizer from {}: {}", path.display(), e)
+        })?;
+        Ok(Self { tokenizer })
+    }
+}
+
+impl Tokenizer for HuggingFaceTokenizer {
+    fn count_tokens(&self, text: &str) -> u32 {
+        match self.tokenizer.encode(text, false) {
+            Ok(encoding) => encoding.len() as u32,
+            Err(e) => {
+                tracing::warn!("Failed to tokenize text: {}", e);
+                ApproximateTokenizer.count_tokens(text)
+            }
+        }
+    }
+}
+
+/// Selects tiktoken encoding by model name, e.g. `gpt-4o` or `openai/gpt-4.1-mini`
+fn tiktoken_encoding(model: &str) -> &'static str {
+    let model = model.rsplit('/').next().unwrap_or(model);
+    if model.starts_with("gpt-4o")
+        || model.starts_with("gpt-4.1")
+        || model.starts_with("gpt-4.5")
+        || model.starts_with("o1")
+        || model.starts_with("o3")
+        || model.starts_with("o4")
+        || model.starts_with("chatgpt-4o")
+    {
+        "o200k_base"
+    } else {
+        DEFAULT_TIKTOKEN_ENCODING
+    }
+}
+
+/// Tokenizer configuration used when it is not set explicitly in the config
+fn default_tokenizer_config(provider: &ProviderKind, model: &str) -> TokenizerConfig {
+    match provider {
+        // local models have own tokenizers, which can be configured with `tokenizer.json`
+        ProviderKind::LMStudio | ProviderKind::Replay => TokenizerConfig::Approximate,
+        // Anthropic tokenizer is not public, cl100k_base gives a close estimation
+        ProviderKind::OpenAI | ProviderKind::OpenRouter | ProviderKind::Anthropic => {
+            TokenizerConfig::Tiktoken {
+                encoding: Some(tiktoken_encoding(model).to_string()),
+            }
+        }
+    }
+}
+
+pub fn create_tokenizer(config: &Config) -> Arc<dyn Tokenizer> {
+    let tokenizer_config = config
+        .tokenizer
+        .clone()
+        .unwrap_or_else(|| default_tokenizer_config(&config.provider, &config.model));
+    tracing::info!("Use tokenizer: {:?}", tokenizer_config);
+    let tokenizer: anyhow::Result<Arc<dyn Tokenizer>> = match &tokenizer_config {
+        TokenizerConfig::Approximate => Ok(Arc::new(ApproximateTokenizer)),
+        TokenizerConfig::Tiktoken { encoding } => TiktokenTokenizer::new(
+            encoding
+                .as_deref()
+                .unwrap_or_else(|| tiktoken_encoding(&config.model)),
+        )
+        .map(|tokenizer| Arc::new(tokenizer) as Arc<dyn Tokenizer>),
+        TokenizerConfig::HuggingFace { path } => HuggingFaceTokenizer::new(path)
+            .map(|tokenizer| Arc::new(tokenizer) as Arc<dyn Tokenizer>),
+    };
+    tokenizer.unwrap_or_else(|e| {
+        tracing::error!(
+            "Failed to create tokenizer, use approximate counting: {}",
+            e
+        );
+        Arc::new(ApproximateTokenizer)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tiktoken_encoding() {
+        assert_eq!(tiktoken_encoding("gpt-4o"), "o200k_base");
+        assert_eq!(tiktoken_encoding("openai/gpt-4.1-mini"), "o200k_base");
+        assert_eq!(tiktoken_encoding("o3-mini"), "o200k_base");
+        assert_eq!(tiktoken_encoding("gpt-4-turbo"), "cl100k_base");
+        assert_eq!(
+            tiktoken_encoding("anthropic/claude-3.5-sonnet"),
+            "cl100k_base"
+        );
+    }
+
+    #[test]
+    fn test_count_tokens() {
+        let text = "Hello, world! This is a tokenizer test.";
+        assert_eq!(ApproximateTokenizer.count_tokens(text), 9);
+        let tokenizer = TiktokenTokenizer::new("cl100k_base").unwrap();
+        assert_eq!(tokenizer.count_tokens("Hello, world!"), 4);
+        assert!(TiktokenTokenizer::new("unknown").is_err());
+    }
+}
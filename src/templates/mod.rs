@@ -3,3 +3,5 @@ pub const SYSTEM_PROMPT: &str = include_str!("system_prompt.txt");
 pub const TOOL_USAGE_ERROR: &str = include_str!("tool_usage_error.txt");
 pub const TOOL_CALL_ERROR: &str = include_str!("tool_call_error.txt");
 pub const ENV_DETAILS: &str = include_str!("env_details.txt");
+pub const COMPACTION_PROMPT: &str = include_str!("compaction_prompt.txt");
+pub const COMPACTION_SUMMARY: &str = include_str!("compaction_summary.txt");
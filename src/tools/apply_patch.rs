@@ -0,0 +1,1045 @@
+// Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use indoc::{formatdoc, indoc};
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::tools::{create_patch, resolve_write_path, workspace_to_string};
+
+/// Number of leading and trailing context lines of a hunk that may be ignored to apply it
+const MAX_FUZZ: usize = 2;
+const V4A_BEGIN: &str = "*** Begin Patch";
+const V4A_END: &str = "*** End Patch";
+const V4A_END_OF_FILE: &str = "*** End of File";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApplyPatchToolArgs {
+    pub patch: String,
+}
+
+pub struct ApplyPatchTool {
+    pub workspace: PathBuf,
+}
+
+impl ApplyPatchTool {
+    pub fn new(workspace: PathBuf) -> Self {
+        Self { workspace }
+    }
+}
+
+impl Tool for ApplyPatchTool {
+    const NAME: &'static str = "apply_patch";
+
+    type Error = std::io::Error;
+    type Args = ApplyPatchToolArgs;
+    type Output = String;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name(),
+            description: formatdoc! {"\
+                Request to apply a patch which can add, update, delete and move several files at once. \
+                The patch is applied with tolerance to shifted line numbers, whitespace differences and \
+                slightly outdated context lines. If any change fails to apply, no files are modified. \
+                The result of every hunk is reported."}.to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "patch": {
+                        "type": "string",
+                        "description": formatdoc!{"
+                            The patch in the unified diff format (as produced by `git diff`) or in the following format:
+                              ```
+                              *** Begin Patch
+                              *** Add File: path/to/new_file.rs
+                              +line of the new file
+                              *** Update File: path/to/file.rs
+                              *** Move to: path/to/renamed_file.rs
+                              @@ fn main() {{
+                               context line
+                              -removed line
+                              +added line
+                              *** Delete File: path/to/obsolete_file.rs
+                              *** End Patch
+                              ```
+                              {}
+                              Paths are relative to the current working directory {}",
+                            indoc!{"
+                              Rules:
+                                * Every line of a hunk starts with ' ' (context), '-' (removed) or '+' (added).
+                                * Include about 3 lines of context around every change.
+                                * `@@` line may contain a line preceding the hunk (e.g. the function declaration) to locate it.
+                                * `*** Move to:` is optional and renames the updated file.
+                            "},
+                            workspace_to_string(&self.workspace)
+                        }
+                    }
+                },
+                "required": ["patch"]
+            })
+
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        tracing::info!("Apply patch");
+        let outcome = compute_patch(&self.workspace, &args.patch)?;
+        if outcome.failed {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "No changes were made, the patch failed to apply:\n{}",
+                    outcome.report.join("\n")
+                ),
+            ));
+        }
+        write_files(&outcome.files)?;
+        Ok(format!(
+            "{}\n\nThe user made the following updates to your content:\n\n{}",
+            outcome.report.join("\n"),
+            outcome.diff()
+        ))
+    }
+}
+
+/// Computes the changes of the pending tool call without modifying the files,
+/// fails if any change of the patch can't be applied
+pub fn preview_changes(workspace: &Path, args: &ApplyPatchToolArgs) -> std::io::Result<String> {
+    let outcome = compute_patch(workspace, &args.patch)?;
+    if outcome.failed {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            outcome.report.join("\n"),
+        ));
+    }
+    Ok(outcome.diff())
+}
+
+/// Paths of all files added, modified, moved or deleted by the patch
+pub fn patch_paths(patch: &str) -> Vec<String> {
+    let Ok(changes) = parse_patch(patch) else {
+        return Vec::new();
+    };
+    changes
+        .into_iter()
+        .flat_map(|change| match change {
+            FileChange::Add { path, .. } | FileChange::Delete { path } => vec![path],
+            FileChange::Update { path, move_to, .. } => {
+                std::iter::once(path).chain(move_to).collect()
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, PartialEq)]
+enum HunkLine {
+    Context(String),
+    Delete(String),
+    Insert(String),
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct PatchHunk {
+    /// 1-based line of the hunk in the original file from the unified diff hunk header
+    old_start: Option<usize>,
+    /// Line preceding the hunk from the `@@` line of V4A patch
+    anchor: Option<String>,
+    lines: Vec<HunkLine>,
+}
+
+#[derive(Debug, PartialEq)]
+enum FileChange {
+    Add {
+        path: String,
+        content: String,
+    },
+    Delete {
+        path: String,
+    },
+    Update {
+        path: String,
+        move_to: Option<String>,
+        hunks: Vec<PatchHunk>,
+    },
+}
+
+fn parse_patch(patch: &str) -> Result<Vec<FileChange>, String> {
+    if patch.lines().any(|line| line.trim() == V4A_BEGIN) {
+        parse_v4a_patch(patch)
+    } else {
+        parse_unified_patch(patch)
+    }
+}
+
+fn parse_hunk_line(line: &str) -> Result<HunkLine, String> {
+    match line.chars().next() {
+        Some(' ') => Ok(HunkLine::Context(line[1..].to_string())),
+        Some('-') => Ok(HunkLine::Delete(line[1..].to_string())),
+        Some('+') => Ok(HunkLine::Insert(line[1..].to_string())),
+        // editors and models often strip the trailing space of empty context lines
+        None => Ok(HunkLine::Context(String::new())),
+        _ => Err(format!(
+            "Invalid hunk line, it must start with ' ', '-' or '+': {line}"
+        )),
+    }
+}
+
+fn parse_v4a_patch(patch: &str) -> Result<Vec<FileChange>, String> {
+    let mut changes = Vec::new();
+    let mut lines = patch
+        .lines()
+        .skip_while(|line| line.trim() != V4A_BEGIN)
+        .skip(1)
+        .peekable();
+    while let Some(line) = lines.next() {
+        if line.trim() == V4A_END {
+            break;
+        }
+        if let Some(path) = line.strip_prefix("*** Add File: ") {
+            let mut content = String::new();
+            while let Some(line) = lines.next_if(|line| !line.starts_with("*** ")) {
+                content.push_str(line.strip_prefix('+').unwrap_or(line));
+                content.push('\n');
+            }
+            changes.push(FileChange::Add {
+                path: path.trim().to_string(),
+                content,
+            });
+        } else if let Some(path) = line.strip_prefix("*** Delete File: ") {
+            changes.push(FileChange::Delete {
+                path: path.trim().to_string(),
+            });
+        } else if let Some(path) = line.strip_prefix("*** Update File: ") {
+            let move_to = lines
+                .next_if(|line| line.starts_with("*** Move to: "))
+                .map(|line| line["*** Move to: ".len()..].trim().to_string());
+            let mut hunks = Vec::new();
+            let mut hunk = PatchHunk::default();
+            while let Some(line) =
+                lines.next_if(|line| !line.starts_with("*** ") || line.trim() == V4A_END_OF_FILE)
+            {
+                if line.trim() == V4A_END_OF_FILE {
+                    continue;
+                }
+                if let Some(anchor) = line.strip_prefix("@@") {
+                    if !hunk.lines.is_empty() {
+                        hunks.push(std::mem::take(&mut hunk));
+                    }
+                    let anchor = anchor.trim();
+                    hunk.anchor = (!anchor.is_empty()).then(|| anchor.to_string());
+                } else {
+                    hunk.lines.push(parse_hunk_line(line)?);
+                }
+            }
+            if !hunk.lines.is_empty() {
+                hunks.push(hunk);
+            }
+            changes.push(FileChange::Update {
+                path: path.trim().to_string(),
+                move_to,
+                hunks,
+            });
+        } else if !line.trim().is_empty() {
+            return Err(format!("Unexpected line in the patch: {line}"));
+        }
+    }
+    Ok(changes)
+}
+
+/// Path from `---` or `+++` header, None for `/dev/null`
+fn header_path(header: &str) -> Option<String> {
+    // the path may be followed by a tab and the modification time
+    let path = header.split('\t').next().unwrap_or_default().trim();
+    (path != "/dev/null").then(|| path.to_string())
+}
+
+fn hunk_old_start(header: &str) -> Option<usize> {
+    header
+        .strip_prefix("@@ -")?
+        .split([',', ' '])
+        .next()?
+        .parse()
+        .ok()
+}
+
+fn unified_file_change(
+    old_path: Option<String>,
+    new_path: Option<String>,
+    hunks: Vec<PatchHunk>,
+) -> Result<FileChange, String> {
+    // git prefixes paths with a/ and b/
+    let strip = |path: Option<String>, prefix: &str| {
+        path.map(|path| {
+            path.strip_prefix(prefix)
+                .map(str::to_string)
+                .unwrap_or(path)
+        })
+    };
+    let has_git_prefixes = old_path.as_ref().is_none_or(|path| path.starts_with("a/"))
+        && new_path.as_ref().is_none_or(|path| path.starts_with("b/"));
+    let (old_path, new_path) = if has_git_prefixes {
+        (strip(old_path, "a/"), strip(new_path, "b/"))
+    } else {
+        (old_path, new_path)
+    };
+    match (old_path, new_path) {
+        (None, Some(path)) => {
+            let mut content = String::new();
+            for line in hunks.iter().flat_map(|hunk| hunk.lines.iter()) {
+                if let HunkLine::Insert(line) = line {
+                    content.push_str(line);
+                    content.push('\n');
+                }
+            }
+            Ok(FileChange::Add { path, content })
+        }
+        (Some(path), None) => Ok(FileChange::Delete { path }),
+        (Some(path), Some(new_path)) => Ok(FileChange::Update {
+            move_to: (new_path != path).then_some(new_path),
+            path,
+            hunks,
+        }),
+        (None, None) => Err("Both paths of the file header are /dev/null".to_string()),
+    }
+}
+
+fn parse_unified_patch(patch: &str) -> Result<Vec<FileChange>, String> {
+    let mut changes = Vec::new();
+    let mut file: Option<(Option<String>, Option<String>, Vec<PatchHunk>)> = None;
+    let mut in_hunk = false;
+    let mut lines = patch.lines().peekable();
+    while let Some(line) = lines.next() {
+        if line.starts_with("--- ") && lines.peek().is_some_and(|next| next.starts_with("+++ ")) {
+            if let Some((old_path, new_path, hunks)) = file.take() {
+                changes.push(unified_file_change(old_path, new_path, hunks)?);
+            }
+            let new_path = lines.next().and_then(|line| header_path(&line[4..]));
+            file = Some((header_path(&line[4..]), new_path, Vec::new()));
+            in_hunk = false;
+        } else if line.starts_with("@@") {
+            let Some((_, _, hunks)) = file.as_mut() else {
+                return Err(format!("Hunk without the file header: {line}"));
+            };
+            hunks.push(PatchHunk {
+                old_start: hunk_old_start(line),
+                ..Default::default()
+            });
+            in_hunk = true;
+        } else if in_hunk {
+            if line.starts_with('\\') {
+                // "\ No newline at end of file"
+                continue;
+            }
+            match parse_hunk_line(line) {
+                Ok(hunk_line) => {
+                    if let Some(hunk) = file.as_mut().and_then(|(_, _, hunks)| hunks.last_mut()) {
+                        hunk.lines.push(hunk_line);
+                    }
+                }
+                // git headers of the next file, e.g. `diff --git` or `index`
+                Err(_) => in_hunk = false,
+            }
+        }
+    }
+    if let Some((old_path, new_path, hunks)) = file.take() {
+        changes.push(unified_file_change(old_path, new_path, hunks)?);
+    }
+    Ok(changes)
+}
+
+/// Position of the hunk found in the file lines
+struct HunkLocation {
+    pos: usize,
+    /// Number of ignored leading and trailing context lines
+    leading: usize,
+    trailing: usize,
+    fuzz: usize,
+    ignore_whitespace: bool,
+    expected: Option<usize>,
+}
+
+fn locate_hunk(
+    lines: &[String],
+    hunk: &PatchHunk,
+    min_pos: usize,
+    offset: isize,
+) -> Option<HunkLocation> {
+    let is_context = |line: &&HunkLine| matches!(line, HunkLine::Context(_));
+    let leading_context = hunk.lines.iter().take_while(is_context).count();
+    let trailing_context = hunk.lines.iter().rev().take_while(is_context).count();
+    let anchor_pos = hunk.anchor.as_ref().and_then(|anchor| {
+        lines
+            .iter()
+            .skip(min_pos)
+            .position(|line| line.trim() == anchor.trim())
+            .map(|idx| min_pos + idx + 1)
+    });
+    for fuzz in 0..=MAX_FUZZ {
+        let leading = fuzz.min(leading_context);
+        let trailing = fuzz.min(trailing_context);
+        if fuzz > 0 && leading + trailing == 0 {
+            break;
+        }
+        if leading + trailing >= hunk.lines.len() {
+            break;
+        }
+        let old_lines = hunk.lines[leading..hunk.lines.len() - trailing]
+            .iter()
+            .filter_map(|line| match line {
+                HunkLine::Context(line) | HunkLine::Delete(line) => Some(line.as_str()),
+                HunkLine::Insert(_) => None,
+            })
+            .collect::<Vec<_>>();
+        let expected = hunk
+            .old_start
+            .map(|start| {
+                (start.saturating_sub(1) as isize + offset + leading as isize).max(0) as usize
+            })
+            .or(anchor_pos);
+        if old_lines.is_empty() {
+            // the hunk only adds lines
+            return Some(HunkLocation {
+                pos: expected.unwrap_or(lines.len()).clamp(min_pos, lines.len()),
+                leading,
+                trailing,
+                fuzz,
+                ignore_whitespace: false,
+                expected,
+            });
+        }
+        if lines.len() < old_lines.len() + min_pos {
+            continue;
+        }
+        for ignore_whitespace in [false, true] {
+            let matches = |pos: &usize| {
+                lines[*pos..*pos + old_lines.len()]
+                    .iter()
+                    .zip(old_lines.iter())
+                    .all(|(line, old_line)| {
+                        if ignore_whitespace {
+                            line.trim() == old_line.trim()
+                        } else {
+                            line == old_line
+                        }
+                    })
+            };
+            let mut candidates = (min_pos..=lines.len() - old_lines.len()).filter(matches);
+            let pos = match expected {
+                Some(expected) => candidates.min_by_key(|pos| pos.abs_diff(expected)),
+                None => candidates.next(),
+            };
+            if let Some(pos) = pos {
+                return Some(HunkLocation {
+                    pos,
+                    leading,
+                    trailing,
+                    fuzz,
+                    ignore_whitespace,
+                    expected,
+                });
+            }
+        }
+    }
+    None
+}
+
+/// Applies the hunks to the file content one by one, returns the new content
+/// (None if any hunk failed) and the result of every hunk.
+/// `diffy::apply` isn't used: it needs exact hunk headers and context, while V4A hunks
+/// have no line numbers and model patches often have wrong counts or whitespace.
+fn apply_hunks(content: &str, hunks: &[PatchHunk]) -> (Option<String>, Vec<String>) {
+    let line_ending = if content.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut lines = content.lines().map(str::to_string).collect::<Vec<_>>();
+    let mut results = Vec::new();
+    let mut failed = false;
+    let mut min_pos = 0;
+    let mut offset = 0isize;
+    for (idx, hunk) in hunks.iter().enumerate() {
+        let Some(location) = locate_hunk(&lines, hunk, min_pos, offset) else {
+            failed = true;
+            results.push(format!(
+                "Hunk {}: FAILED, the lines to change were not found{}",
+                idx + 1,
+                hunk.old_start
+                    .map(|start| format!(" near line {start}"))
+                    .unwrap_or_default()
+            ));
+            continue;
+        };
+        let mut replacement = Vec::new();
+        let mut cursor = location.pos;
+        for line in hunk.lines[location.leading..hunk.lines.len() - location.trailing].iter() {
+            match line {
+                // context lines are kept as they are in the file, they may differ in whitespaces
+                HunkLine::Context(_) => {
+                    replacement.push(lines[cursor].clone());
+                    cursor += 1;
+                }
+                HunkLine::Delete(_) => cursor += 1,
+                HunkLine::Insert(line) => replacement.push(line.clone()),
+            }
+        }
+        let mut notes = Vec::new();
+        if let (Some(expected), Some(_)) = (location.expected, hunk.old_start) {
+            if location.pos != expected {
+                notes.push(format!(
+                    "offset {:+} lines",
+                    location.pos as isize - expected as isize
+                ));
+            }
+        }
+        if location.fuzz > 0 {
+            notes.push(format!("fuzz {}", location.fuzz));
+        }
+        if location.ignore_whitespace {
+            notes.push("ignoring whitespace".to_string());
+        }
+        results.push(format!(
+            "Hunk {}: applied at line {}{}",
+            idx + 1,
+            location.pos + 1,
+            if notes.is_empty() {
+                String::new()
+            } else {
+                format!(" ({})", notes.join(", "))
+            }
+        ));
+        offset += replacement.len() as isize - (cursor - location.pos) as isize;
+        min_pos = location.pos + replacement.len();
+        lines.splice(location.pos..cursor, replacement);
+    }
+    if failed {
+        return (None, results);
+    }
+    let mut new_content = lines.join(line_ending);
+    if !lines.is_empty() && (content.is_empty() || content.ends_with('\n')) {
+        new_content.push_str(line_ending);
+    }
+    (Some(new_content), results)
+}
+
+/// File changed by the patch
+struct PatchedFile {
+    /// Path as it is written in the patch
+    path: String,
+    full_path: PathBuf,
+    original: Option<String>,
+    /// None if the file is deleted
+    content: Option<String>,
+}
+
+/// Changes of all files of the patch computed without modifying them
+#[derive(Default)]
+struct PatchOutcome {
+    files: Vec<PatchedFile>,
+    report: Vec<String>,
+    failed: bool,
+}
+
+impl PatchOutcome {
+    /// Content of the file with the changes of the previous patch sections applied
+    fn current_content(&self, full_path: &Path) -> std::io::Result<Option<String>> {
+        match self.files.iter().find(|file| file.full_path == full_path) {
+            Some(file) => Ok(file.content.clone()),
+            None if full_path.is_file() => Ok(Some(fs::read_to_string(full_path)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn set_content(
+        &mut self,
+        path: &str,
+        full_path: PathBuf,
+        content: Option<String>,
+    ) -> std::io::Result<()> {
+        match self
+            .files
+            .iter_mut()
+            .find(|file| file.full_path == full_path)
+        {
+            Some(file) => file.content = content,
+            None => {
+                let original = self.current_content(&full_path)?;
+                self.files.push(PatchedFile {
+                    path: path.to_string(),
+                    full_path,
+                    original,
+                    content,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn fail(&mut self, message: String) {
+        self.failed = true;
+        self.report.push(message);
+    }
+
+    fn diff(&self) -> String {
+        self.files
+            .iter()
+            .map(|file| match &file.content {
+                Some(content) => format!(
+                    "{}:\n{}",
+                    file.path,
+                    create_patch(file.original.as_deref().unwrap_or_default(), content)
+                ),
+                None => format!("{}: deleted", file.path),
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+/// File of the patch written to a temporary file before it replaces the target
+struct StagedFile<'a> {
+    file: &'a PatchedFile,
+    /// Temporary file with the new content, None if the file is deleted
+    new: Option<PathBuf>,
+    /// The original file moved aside until the whole patch is written
+    backup: Option<PathBuf>,
+    /// The temporary file replaced the target
+    replaced: bool,
+}
+
+fn temp_path(path: &Path, suffix: &str) -> std::io::Result<PathBuf> {
+    let (Some(dir), Some(file_name)) = (path.parent(), path.file_name()) else {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("Invalid file path: {}", path.display()),
+        ));
+    };
+    Ok(dir.join(format!(
+        ".{}.{}.{suffix}",
+        file_name.to_string_lossy(),
+        std::process::id()
+    )))
+}
+
+/// Writes all files of the patch or none of them: new contents are written to temporary
+/// files first, then they replace the targets, the replaced files are restored on error
+fn write_files(files: &[PatchedFile]) -> std::io::Result<()> {
+    let mut staged = Vec::new();
+    let mut created_dirs = Vec::new();
+    let result =
+        stage_files(files, &mut staged, &mut created_dirs).and_then(|_| replace_files(&mut staged));
+    if result.is_err() {
+        restore_files(&staged, &mut created_dirs);
+        return result;
+    }
+    for file in staged {
+        if let Some(backup) = file.backup {
+            fs::remove_file(backup).ok();
+        }
+    }
+    Ok(())
+}
+
+fn stage_files<'a>(
+    files: &'a [PatchedFile],
+    staged: &mut Vec<StagedFile<'a>>,
+    created_dirs: &mut Vec<PathBuf>,
+) -> std::io::Result<()> {
+    for file in files {
+        let Some(content) = &file.content else {
+            staged.push(StagedFile {
+                file,
+                new: None,
+                backup: None,
+                replaced: false,
+            });
+            continue;
+        };
+        if let Some(parent) = file.full_path.parent() {
+            created_dirs.extend(
+                parent
+                    .ancestors()
+                    .take_while(|dir| !dir.exists())
+                    .map(Path::to_path_buf),
+            );
+            fs::create_dir_all(parent)?;
+        }
+        let new = temp_path(&file.full_path, "patch")?;
+        staged.push(StagedFile {
+            file,
+            new: Some(new.clone()),
+            backup: None,
+            replaced: false,
+        });
+        let mut tmp = fs::File::create(&new)?;
+        tmp.write_all(content.as_bytes())?;
+        tmp.sync_all()?;
+        if let Ok(metadata) = fs::metadata(&file.full_path) {
+            fs::set_permissions(&new, metadata.permissions())?;
+        }
+    }
+    Ok(())
+}
+
+fn replace_files(staged: &mut [StagedFile]) -> std::io::Result<()> {
+    for file in staged.iter_mut() {
+        let target = &file.file.full_path;
+        if target.exists() {
+            let backup = temp_path(target, "orig")?;
+            fs::rename(target, &backup)?;
+            file.backup = Some(backup);
+        }
+        if let Some(new) = &file.new {
+            fs::rename(new, target)?;
+            file.replaced = true;
+        }
+    }
+    Ok(())
+}
+
+/// Removes the written files and temporary files, moves the original files back
+fn restore_files(staged: &[StagedFile], created_dirs: &mut [PathBuf]) {
+    for file in staged.iter().rev() {
+        let target = &file.file.full_path;
+        if file.replaced {
+            fs::remove_file(target).ok();
+        } else if let Some(new) = &file.new {
+            fs::remove_file(new).ok();
+        }
+        if let Some(backup) = &file.backup {
+            if let Err(e) = fs::rename(backup, target) {
+                tracing::error!("Failed to restore {}: {}", target.display(), e);
+            }
+        }
+    }
+    // nested directories are removed before their parents
+    created_dirs.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+    for dir in created_dirs.iter() {
+        fs::remove_dir(dir).ok();
+    }
+}
+
+fn compute_patch(workspace: &Path, patch: &str) -> std::io::Result<PatchOutcome> {
+    let changes =
+        parse_patch(patch).map_err(|e| std::io::Error::new(ErrorKind::InvalidInput, e))?;
+    if changes.is_empty() {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidInput,
+            "The patch doesn't contain any file changes",
+        ));
+    }
+    let mut outcome = PatchOutcome::default();
+    for change in changes {
+        match change {
+            FileChange::Add { path, content } => {
+                let full_path = resolve_write_path(workspace, &path)?;
+                if outcome.current_content(&full_path)?.is_some() {
+                    outcome.fail(format!("Add {path}: FAILED, the file already exists"));
+                    continue;
+                }
+                outcome.report.push(format!("Add {path}: created"));
+                outcome.set_content(&path, full_path, Some(content))?;
+            }
+            FileChange::Delete { path } => {
+                let full_path = resolve_write_path(workspace, &path)?;
+                if outcome.current_content(&full_path)?.is_none() {
+                    outcome.fail(format!("Delete {path}: FAILED, the file doesn't exist"));
+                    continue;
+                }
+                outcome.report.push(format!("Delete {path}: deleted"));
+                outcome.set_content(&path, full_path, None)?;
+            }
+            FileChange::Update {
+                path,
+                move_to,
+                hunks,
+            } => {
+                let full_path = resolve_write_path(workspace, &path)?;
+                let Some(content) = outcome.current_content(&full_path)? else {
+                    outcome.fail(format!("Update {path}: FAILED, the file doesn't exist"));
+                    continue;
+                };
+                let (new_content, results) = apply_hunks(&content, &hunks);
+                let title = match &move_to {
+                    Some(move_to) => format!("Update {path} -> {move_to}:"),
+                    None => format!("Update {path}:"),
+                };
+                let report = std::iter::once(title)
+                    .chain(results.into_iter().map(|result| format!("  {result}")))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let Some(new_content) = new_content else {
+                    outcome.fail(report);
+                    continue;
+                };
+                let new_full_path = match &move_to {
+                    Some(move_to) => resolve_write_path(workspace, move_to)?,
+                    None => full_path.clone(),
+                };
+                if new_full_path != full_path && outcome.current_content(&new_full_path)?.is_some()
+                {
+                    outcome.fail(format!(
+                        "{report}\n  FAILED, the destination file already exists"
+                    ));
+                    continue;
+                }
+                outcome.report.push(report);
+                match move_to {
+                    Some(move_to) => {
+                        outcome.set_content(&path, full_path, None)?;
+                        outcome.set_content(&move_to, new_full_path, Some(new_content))?;
+                    }
+                    None => outcome.set_content(&path, full_path, Some(new_content))?,
+                }
+            }
+        }
+    }
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::test_dir;
+
+    fn workspace(name: &str) -> PathBuf {
+        let workspace = test_dir(name);
+        fs::create_dir_all(workspace.join("src")).unwrap();
+        workspace
+    }
+
+    #[test]
+    fn test_parse_unified_patch() {
+        let patch = indoc! {"
+            diff --git a/src/main.rs b/src/main.rs
+            index 1234567..89abcde 100644
+            --- a/src/main.rs
+            +++ b/src/main.rs
+            @@ -10,3 +10,3 @@ fn main() {
+                 let a = 1;
+            -    let b = 2;
+            +    let b = 3;
+            \\ No newline at end of file
+            diff --git a/src/new.rs b/src/new.rs
+            --- /dev/null
+            +++ b/src/new.rs
+            @@ -0,0 +1,2 @@
+            +fn new() {
+            +}
+            --- a/src/old.rs
+            +++ /dev/null
+            @@ -1 +0,0 @@
+            -fn old() {}
+        "};
+        let changes = parse_patch(patch).unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                FileChange::Update {
+                    path: "src/main.rs".to_string(),
+                    move_to: None,
+                    hunks: vec![PatchHunk {
+                        old_start: Some(10),
+                        anchor: None,
+                        lines: vec![
+                            HunkLine::Context("    let a = 1;".to_string()),
+                            HunkLine::Delete("    let b = 2;".to_string()),
+                            HunkLine::Insert("    let b = 3;".to_string()),
+                        ],
+                    }],
+                },
+                FileChange::Add {
+                    path: "src/new.rs".to_string(),
+                    content: "fn new() {\n}\n".to_string(),
+                },
+                FileChange::Delete {
+                    path: "src/old.rs".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_apply_v4a_patch() {
+        let workspace = workspace("apply-patch");
+        let main = (1..=20)
+            .map(|idx| format!("line {idx}\n"))
+            .collect::<String>();
+        fs::write(workspace.join("src/main.rs"), &main).unwrap();
+        fs::write(workspace.join("src/old.rs"), "fn old() {}\n").unwrap();
+        let patch = indoc! {"
+            *** Begin Patch
+            *** Add File: src/new.rs
+            +fn new() {}
+            *** Update File: src/main.rs
+            *** Move to: src/lib.rs
+            @@ line 2
+             line 3
+            -line 4
+            +line four
+             line 5
+            @@
+             line 14
+               line 15
+            -line 16
+            +line sixteen
+             line 17
+             changed line 18
+            *** Delete File: src/old.rs
+            *** End Patch
+        "};
+        let outcome = compute_patch(&workspace, patch).unwrap();
+        assert!(!outcome.failed);
+        assert_eq!(
+            outcome.report,
+            vec![
+                "Add src/new.rs: created".to_string(),
+                "Update src/main.rs -> src/lib.rs:\n  Hunk 1: applied at line 3\n  Hunk 2: applied at line 15 (fuzz 1, ignoring whitespace)".to_string(),
+                "Delete src/old.rs: deleted".to_string(),
+            ]
+        );
+        let lib = outcome
+            .files
+            .iter()
+            .find(|file| file.path == "src/lib.rs")
+            .and_then(|file| file.content.clone())
+            .unwrap();
+        assert_eq!(
+            lib,
+            main.replace("line 4\n", "line four\n")
+                .replace("line 16\n", "line sixteen\n")
+        );
+        assert_eq!(
+            patch_paths(patch),
+            vec!["src/new.rs", "src/main.rs", "src/lib.rs", "src/old.rs"]
+        );
+        fs::remove_dir_all(workspace).ok();
+    }
+
+    #[tokio::test]
+    async fn test_apply_unified_patch() {
+        let workspace = workspace("apply-unified-patch");
+        let main = (1..=20)
+            .map(|idx| format!("line {idx}\n"))
+            .collect::<String>();
+        fs::write(workspace.join("src/main.rs"), &main).unwrap();
+        let patch = |second_hunk: &str| {
+            formatdoc! {"
+                --- a/src/main.rs
+                +++ b/src/main.rs
+                @@ -1,3 +1,3 @@
+                 line 1
+                -line 2
+                +line two
+                 line 3
+                @@ -8,3 +8,3 @@
+                {}
+            ", second_hunk}
+        };
+
+        let (content, results) = apply_hunks(
+            &main,
+            &match parse_patch(&patch(" line 11\n-line 12\n+line twelve\n line 13"))
+                .unwrap()
+                .remove(0)
+            {
+                FileChange::Update { hunks, .. } => hunks,
+                _ => unreachable!(),
+            },
+        );
+        assert_eq!(
+            results,
+            vec![
+                "Hunk 1: applied at line 1",
+                "Hunk 2: applied at line 11 (offset +3 lines)"
+            ]
+        );
+        assert_eq!(
+            content.unwrap(),
+            main.replace("line 2\n", "line two\n")
+                .replace("line 12\n", "line twelve\n")
+        );
+
+        // a failed hunk leaves all files untouched
+        let err = ApplyPatchTool::new(workspace.clone())
+            .call(ApplyPatchToolArgs {
+                patch: patch(" line 11\n-line 21\n+line twelve"),
+            })
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Hunk 1: applied at line 1"));
+        assert!(err.contains("Hunk 2: FAILED, the lines to change were not found near line 8"));
+        assert_eq!(
+            fs::read_to_string(workspace.join("src/main.rs")).unwrap(),
+            main
+        );
+        fs::remove_dir_all(workspace).ok();
+    }
+
+    #[tokio::test]
+    async fn test_apply_patch_atomic() {
+        let workspace = workspace("apply-patch-atomic");
+        fs::write(workspace.join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(workspace.join("src/lib.rs"), "pub fn lib() {}\n").unwrap();
+        // the directory of the new file can't be created, since a file has the same name
+        fs::write(workspace.join("blocker"), "").unwrap();
+        let tool = ApplyPatchTool::new(workspace.clone());
+        let result = tool
+            .call(ApplyPatchToolArgs {
+                patch: indoc! {"
+                    *** Begin Patch
+                    *** Update File: src/main.rs
+                    @@
+                    -fn main() {}
+                    +fn main() { lib() }
+                    *** Delete File: src/lib.rs
+                    *** Add File: new/dir/file.rs
+                    +fn file() {}
+                    *** Add File: blocker/file.rs
+                    +fn file() {}
+                    *** End Patch
+                "}
+                .to_string(),
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(
+            fs::read_to_string(workspace.join("src/main.rs")).unwrap(),
+            "fn main() {}\n"
+        );
+        assert!(workspace.join("src/lib.rs").exists());
+        assert!(!workspace.join("new").exists());
+        assert_eq!(fs::read_dir(workspace.join("src")).unwrap().count(), 2);
+
+        // moving the file over another one is rejected
+        let err = tool
+            .call(ApplyPatchToolArgs {
+                patch: indoc! {"
+                    *** Begin Patch
+                    *** Update File: src/main.rs
+                    *** Move to: src/lib.rs
+                    @@
+                    -fn main() {}
+                    +fn main() { lib() }
+                    *** End Patch
+                "}
+                .to_string(),
+            })
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Update src/main.rs -> src/lib.rs:\n  Hunk 1: applied at line 1"));
+        assert!(err.contains("  FAILED, the destination file already exists"));
+        assert_eq!(
+            fs::read_to_string(workspace.join("src/lib.rs")).unwrap(),
+            "pub fn lib() {}\n"
+        );
+        fs::remove_dir_all(workspace).ok();
+    }
+}
@@ -0,0 +1,404 @@
+// Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tree_sitter::{Node, Parser, Tree};
+
+pub mod tools;
+
+/// Larger files are skipped, they are usually generated or minified
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeLanguage {
+    Rust,
+    TypeScript,
+    Tsx,
+    JavaScript,
+    Python,
+    Go,
+}
+
+impl CodeLanguage {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "rs" => Some(Self::Rust),
+            "ts" | "mts" | "cts" => Some(Self::TypeScript),
+            "tsx" => Some(Self::Tsx),
+            "js" | "jsx" | "mjs" | "cjs" => Some(Self::JavaScript),
+            "py" | "pyi" => Some(Self::Python),
+            "go" => Some(Self::Go),
+            _ => None,
+        }
+    }
+
+    fn grammar(&self) -> tree_sitter::Language {
+        match self {
+            Self::Rust => tree_sitter_rust::LANGUAGE.into(),
+            Self::TypeScript => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            Self::Tsx => tree_sitter_typescript::LANGUAGE_TSX.into(),
+            Self::JavaScript => tree_sitter_javascript::LANGUAGE.into(),
+            Self::Python => tree_sitter_python::LANGUAGE.into(),
+            Self::Go => tree_sitter_go::LANGUAGE.into(),
+        }
+    }
+
+    /// Kind of the symbol defined by the syntax node, None if the node isn't a definition
+    fn symbol_kind(&self, node: &Node) -> Option<&'static str> {
+        let is_script = matches!(self, Self::TypeScript | Self::Tsx | Self::JavaScript);
+        let kind = match (self, node.kind()) {
+            (Self::Rust, "function_item" | "function_signature_item") => "function",
+            (Self::Rust, "struct_item") => "struct",
+            (Self::Rust, "enum_item") => "enum",
+            (Self::Rust, "trait_item") => "trait",
+            (Self::Rust, "impl_item") => "impl",
+            (Self::Rust, "mod_item") => "module",
+            (Self::Rust, "const_item" | "static_item") => "constant",
+            (Self::Rust, "type_item") => "type",
+            (Self::Rust, "macro_definition") => "macro",
+            (_, "function_declaration" | "generator_function_declaration") if is_script => {
+                "function"
+            }
+            (_, "class_declaration" | "abstract_class_declaration") if is_script => "class",
+            (_, "method_definition" | "method_signature" | "abstract_method_signature")
+                if is_script =>
+            {
+                "method"
+            }
+            (_, "interface_declaration") if is_script => "interface",
+            (_, "type_alias_declaration") if is_script => "type",
+            (_, "enum_declaration") if is_script => "enum",
+            // `const handler = () => {}`
+            (_, "variable_declarator") if is_script => {
+                let value = node.child_by_field_name("value")?;
+                if !matches!(
+                    value.kind(),
+                    "arrow_function" | "function_expression" | "function"
+                ) {
+                    return None;
+                }
+                "function"
+            }
+            (Self::Python, "function_definition") => "function",
+            (Self::Python, "class_definition") => "class",
+            (Self::Go, "function_declaration") => "function",
+            (Self::Go, "method_declaration") => "method",
+            (Self::Go, "type_spec") => "type",
+            _ => return None,
+        };
+        Some(kind)
+    }
+}
+
+/// Definition found in the source file
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: &'static str,
+    /// Name of the enclosing symbol, e.g. the class of a method
+    pub container: Option<String>,
+    /// Nesting level of the symbol inside other symbols
+    pub depth: usize,
+    /// 1-based lines of the definition
+    pub start_line: usize,
+    pub end_line: usize,
+    pub start_byte: usize,
+    pub end_byte: usize,
+    /// First line of the definition
+    pub signature: String,
+}
+
+/// Usage of the name in the source file
+#[derive(Debug, Clone)]
+pub struct Reference {
+    /// 1-based line of the reference
+    pub line: usize,
+    /// The name is defined here rather than used
+    pub is_definition: bool,
+}
+
+pub struct SourceFile {
+    pub language: CodeLanguage,
+    pub source: String,
+    tree: Tree,
+}
+
+impl SourceFile {
+    /// Parses the file, returns None for unsupported, too large or unreadable files
+    pub fn parse(path: &Path) -> Option<Self> {
+        let language = CodeLanguage::from_path(path)?;
+        if fs::metadata(path).ok()?.len() > MAX_FILE_SIZE {
+            return None;
+        }
+        Self::parse_source(language, fs::read_to_string(path).ok()?)
+    }
+
+    pub fn parse_source(language: CodeLanguage, source: String) -> Option<Self> {
+        let mut parser = Parser::new();
+        parser.set_language(&language.grammar()).ok()?;
+        let tree = parser.parse(&source, None)?;
+        Some(Self {
+            language,
+            source,
+            tree,
+        })
+    }
+
+    fn text(&self, node: Node) -> &str {
+        &self.source[node.byte_range()]
+    }
+
+    fn symbol_name(&self, node: Node) -> Option<String> {
+        // impl blocks are named by the implementing type, so they are found together with it
+        let name_field = if node.kind() == "impl_item" {
+            "type"
+        } else {
+            "name"
+        };
+        node.child_by_field_name(name_field)
+            .map(|name| self.text(name).to_string())
+    }
+
+    /// Definitions of the file in the source order
+    pub fn symbols(&self) -> Vec<Symbol> {
+        let mut symbols = Vec::new();
+        self.collect_symbols(self.tree.root_node(), 0, None, &mut symbols);
+        symbols
+    }
+
+    fn collect_symbols(
+        &self,
+        node: Node,
+        depth: usize,
+        container: Option<&str>,
+        symbols: &mut Vec<Symbol>,
+    ) {
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            let Some(kind) = self.language.symbol_kind(&child) else {
+                self.collect_symbols(child, depth, container, symbols);
+                continue;
+            };
+            let Some(name) = self.symbol_name(child) else {
+                continue;
+            };
+            // the declaration of a script function is the parent of the declarator
+            let definition = match child.kind() {
+                "variable_declarator" => child.parent().unwrap_or(child),
+                _ => child,
+            };
+            let signature = self
+                .text(definition)
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .trim_end_matches('{')
+                .trim_end()
+                .to_string();
+            symbols.push(Symbol {
+                name: name.clone(),
+                kind,
+                container: container.map(str::to_string),
+                depth,
+                start_line: definition.start_position().row + 1,
+                end_line: definition.end_position().row + 1,
+                start_byte: definition.start_byte(),
+                end_byte: definition.end_byte(),
+                signature,
+            });
+            // bodies of functions contain local definitions only
+            if !matches!(kind, "function" | "method") {
+                self.collect_symbols(child, depth + 1, Some(&name), symbols);
+            }
+        }
+    }
+
+    /// Source text of the symbol
+    pub fn symbol_source(&self, symbol: &Symbol) -> &str {
+        &self.source[symbol.start_byte..symbol.end_byte]
+    }
+
+    /// Calls the function for every identifier of the file
+    fn for_each_identifier(&self, mut f: impl FnMut(Node)) {
+        let mut cursor = self.tree.walk();
+        loop {
+            let node = cursor.node();
+            if node.child_count() == 0 && node.kind().ends_with("identifier") {
+                f(node);
+            }
+            if cursor.goto_first_child() || cursor.goto_next_sibling() {
+                continue;
+            }
+            loop {
+                if !cursor.goto_parent() {
+                    return;
+                }
+                if cursor.goto_next_sibling() {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Identifiers matching the name, found by syntax rather than by name resolution
+    pub fn references(&self, name: &str) -> Vec<Reference> {
+        let mut references = Vec::new();
+        self.for_each_identifier(|node| {
+            if self.text(node) != name {
+                return;
+            }
+            let is_definition = node.parent().is_some_and(|parent| {
+                self.language.symbol_kind(&parent).is_some()
+                    && parent
+                        .child_by_field_name("name")
+                        .is_some_and(|name| name.id() == node.id())
+            });
+            references.push(Reference {
+                line: node.start_position().row + 1,
+                is_definition,
+            });
+        });
+        references
+    }
+
+    /// All distinct identifiers used in the file
+    pub fn identifiers(&self) -> HashSet<String> {
+        let mut identifiers = HashSet::new();
+        self.for_each_identifier(|node| {
+            identifiers.insert(self.text(node).to_string());
+        });
+        identifiers
+    }
+
+    pub fn line(&self, line: usize) -> &str {
+        self.source.lines().nth(line - 1).unwrap_or_default()
+    }
+}
+
+/// Supported source files in the directory (or the file itself), ignored files are skipped
+pub fn source_files(path: &Path) -> impl Iterator<Item = PathBuf> {
+    ignore::Walk::new(path)
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+        .map(|entry| entry.into_path())
+        .filter(|path| CodeLanguage::from_path(path).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outline(language: CodeLanguage, source: &str) -> Vec<String> {
+        SourceFile::parse_source(language, source.to_string())
+            .unwrap()
+            .symbols()
+            .iter()
+            .map(|symbol| {
+                format!(
+                    "{}{} {} {}-{}",
+                    "  ".repeat(symbol.depth),
+                    symbol.kind,
+                    symbol.name,
+                    symbol.start_line,
+                    symbol.end_line
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_symbols() {
+        let rust = indoc::indoc! {"
+            pub struct Config {
+                name: String,
+            }
+
+            impl Config {
+                pub fn new() -> Self {
+                    fn helper() {}
+                    Self { name: String::new() }
+                }
+            }
+        "};
+        assert_eq!(
+            outline(CodeLanguage::Rust, rust),
+            vec![
+                "struct Config 1-3",
+                "impl Config 5-10",
+                "  function new 6-9"
+            ]
+        );
+
+        let python = indoc::indoc! {"
+            class Parser:
+                @staticmethod
+                def parse(text):
+                    return text
+
+            def main():
+                pass
+        "};
+        assert_eq!(
+            outline(CodeLanguage::Python, python),
+            vec![
+                "class Parser 1-4",
+                "  function parse 3-4",
+                "function main 6-7"
+            ]
+        );
+
+        let typescript = indoc::indoc! {"
+            export interface Props {
+                name: string;
+            }
+            export const render = (props: Props) => props.name;
+            class View {
+                update(): void {}
+            }
+        "};
+        assert_eq!(
+            outline(CodeLanguage::TypeScript, typescript),
+            vec![
+                "interface Props 1-3",
+                "function render 4-4",
+                "class View 5-7",
+                "  method update 6-6"
+            ]
+        );
+
+        let go = indoc::indoc! {"
+            type Server struct {}
+
+            func (s *Server) Run() error {
+                return nil
+            }
+        "};
+        assert_eq!(
+            outline(CodeLanguage::Go, go),
+            vec!["type Server 1-1", "method Run 3-5"]
+        );
+    }
+
+    #[test]
+    fn test_references() {
+        let source = indoc::indoc! {"
+            fn parse(text: &str) -> usize {
+                text.len()
+            }
+
+            fn main() {
+                let parsed = parse(\"a\");
+                let other = parse(\"b\");
+            }
+        "};
+        let file = SourceFile::parse_source(CodeLanguage::Rust, source.to_string()).unwrap();
+        let references = file
+            .references("parse")
+            .iter()
+            .map(|reference| (reference.line, reference.is_definition))
+            .collect::<Vec<_>>();
+        assert_eq!(references, vec![(1, true), (6, false), (7, false)]);
+    }
+}
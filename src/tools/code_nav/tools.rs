@@ -0,0 +1,468 @@
+// Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+use std::path::{Path, PathBuf};
+
+use indoc::formatdoc;
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::tools::{resolve_read_path, workspace_to_string, AgentToolError};
+
+use super::{source_files, SourceFile};
+
+/// Maximal number of lines in the outline of a directory
+const MAX_OUTLINE_LINES: usize = 1000;
+/// Maximal number of definitions returned by find_symbol
+const MAX_SYMBOL_MATCHES: usize = 10;
+/// Maximal number of lines of the symbol source, the rest is available via read_file
+const MAX_SYMBOL_LINES: usize = 500;
+/// Maximal number of references returned by find_references
+const MAX_REFERENCES: usize = 200;
+
+const SUPPORTED_LANGUAGES: &str = "Rust, TypeScript, JavaScript, Python and Go";
+
+/// Path of the file relative to the workspace for the output
+fn display_path(workspace: &Path, path: &Path) -> String {
+    let relative = workspace
+        .canonicalize()
+        .ok()
+        .and_then(|workspace| path.strip_prefix(workspace).ok().map(Path::to_path_buf))
+        .unwrap_or_else(|| path.to_path_buf());
+    workspace_to_string(&relative)
+}
+
+fn path_description(workspace: &Path, what: &str) -> String {
+    format!(
+        "The path of the file or directory {} (relative to the current working directory {}). \
+        Directories are searched recursively, files ignored by .gitignore are skipped.",
+        what,
+        workspace_to_string(workspace)
+    )
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodeOutlineToolArgs {
+    pub path: String,
+}
+
+pub struct CodeOutlineTool {
+    pub workspace: PathBuf,
+    /// Directories outside of the workspace allowed for reading
+    pub read_only_roots: Vec<PathBuf>,
+}
+
+impl CodeOutlineTool {
+    pub fn new(workspace: PathBuf, read_only_roots: Vec<PathBuf>) -> Self {
+        Self {
+            workspace,
+            read_only_roots,
+        }
+    }
+}
+
+impl Tool for CodeOutlineTool {
+    const NAME: &'static str = "code_outline";
+
+    type Error = AgentToolError;
+    type Args = CodeOutlineToolArgs;
+    type Output = String;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name(),
+            description: formatdoc! {"\
+                Request to list the definitions (functions, methods, classes, structs, traits, interfaces, types etc.) \
+                of the source file or of all source files in the directory, with their line ranges and signatures. \
+                Use this tool to get an overview of the code structure without reading whole files. \
+                Supported languages: {}.", SUPPORTED_LANGUAGES}.to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": path_description(&self.workspace, "to outline"),
+                    }
+                },
+                "required": ["path"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let path = resolve_read_path(&self.workspace, &self.read_only_roots, &args.path)?;
+        tracing::info!("Code outline of '{}'", path.display());
+        let mut lines = Vec::new();
+        let mut skipped_files = 0;
+        for file_path in source_files(&path) {
+            let Some(file) = SourceFile::parse(&file_path) else {
+                continue;
+            };
+            let symbols = file.symbols();
+            if symbols.is_empty() {
+                continue;
+            }
+            if lines.len() >= MAX_OUTLINE_LINES {
+                skipped_files += 1;
+                continue;
+            }
+            lines.push(format!("{}:", display_path(&self.workspace, &file_path)));
+            // large files are truncated, so the outline is never empty
+            let shown = symbols.len().min(MAX_OUTLINE_LINES - lines.len());
+            let hidden = symbols.len() - shown;
+            for symbol in symbols.into_iter().take(shown) {
+                lines.push(format!(
+                    "{}{}-{}: {}",
+                    "  ".repeat(symbol.depth + 1),
+                    symbol.start_line,
+                    symbol.end_line,
+                    symbol.signature
+                ));
+            }
+            if hidden > 0 {
+                lines.push(format!(
+                    "  ... {} more definitions are not shown, use find_symbol or read_file",
+                    hidden
+                ));
+            }
+        }
+        if lines.is_empty() {
+            return Ok(format!(
+                "No definitions found, supported languages: {}",
+                SUPPORTED_LANGUAGES
+            ));
+        }
+        let mut res = lines.join("\n");
+        if skipped_files > 0 {
+            res.push_str(&format!(
+                "\n... outline of {} more files is not shown, request the outline of a subdirectory",
+                skipped_files
+            ));
+        }
+        Ok(res)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FindSymbolToolArgs {
+    /// Name of the symbol, optionally qualified by the container, e.g. `Config::new`
+    pub name: String,
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+pub struct FindSymbolTool {
+    pub workspace: PathBuf,
+    /// Directories outside of the workspace allowed for reading
+    pub read_only_roots: Vec<PathBuf>,
+}
+
+impl FindSymbolTool {
+    pub fn new(workspace: PathBuf, read_only_roots: Vec<PathBuf>) -> Self {
+        Self {
+            workspace,
+            read_only_roots,
+        }
+    }
+}
+
+/// Splits `Type::method` or `Class.method` into the container and the name
+fn split_qualified_name(name: &str) -> (Option<&str>, &str) {
+    match name.rsplit_once("::").or_else(|| name.rsplit_once('.')) {
+        Some((container, name)) => (container.rsplit(['.', ':']).next(), name),
+        None => (None, name),
+    }
+}
+
+impl Tool for FindSymbolTool {
+    const NAME: &'static str = "find_symbol";
+
+    type Error = AgentToolError;
+    type Args = FindSymbolToolArgs;
+    type Output = String;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name(),
+            description: formatdoc! {"\
+                Request to find the definition of the named function, method, class, struct, trait, interface or type \
+                and return its full source with line numbers. Use this tool instead of reading whole files \
+                when you know the name of the symbol. Returns at most {} definitions. \
+                Supported languages: {}.", MAX_SYMBOL_MATCHES, SUPPORTED_LANGUAGES}.to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "name": {
+                        "type": "string",
+                        "description": "The name of the symbol, optionally qualified by the enclosing type, e.g. `parse`, `Config::new` or `Parser.parse`",
+                    },
+                    "path": {
+                        "type": "string",
+                        "description": path_description(&self.workspace, "to search in, the whole workspace by default"),
+                    }
+                },
+                "required": ["name"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let path = resolve_read_path(
+            &self.workspace,
+            &self.read_only_roots,
+            args.path.as_deref().unwrap_or("."),
+        )?;
+        tracing::info!("Find symbol '{}' in '{}'", args.name, path.display());
+        let (container, name) = split_qualified_name(&args.name);
+        let mut matches = Vec::new();
+        let mut total = 0;
+        for file_path in source_files(&path) {
+            let Some(file) = SourceFile::parse(&file_path) else {
+                continue;
+            };
+            for symbol in file.symbols() {
+                if symbol.name != name
+                    || container.is_some_and(|c| symbol.container.as_deref() != Some(c))
+                {
+                    continue;
+                }
+                total += 1;
+                if matches.len() >= MAX_SYMBOL_MATCHES {
+                    continue;
+                }
+                let source = file.symbol_source(&symbol);
+                let mut text = format!(
+                    "{}:{}-{} ({})\n",
+                    display_path(&self.workspace, &file_path),
+                    symbol.start_line,
+                    symbol.end_line,
+                    symbol.kind
+                );
+                let width = symbol.end_line.to_string().len();
+                for (idx, line) in source.lines().take(MAX_SYMBOL_LINES).enumerate() {
+                    text.push_str(&format!(
+                        "{:>width$}|{}\n",
+                        symbol.start_line + idx,
+                        line,
+                        width = width
+                    ));
+                }
+                if symbol.end_line - symbol.start_line >= MAX_SYMBOL_LINES {
+                    text.push_str(&format!(
+                        "... truncated, use read_file with start_line={} to continue reading.\n",
+                        symbol.start_line + MAX_SYMBOL_LINES
+                    ));
+                }
+                matches.push(text);
+            }
+        }
+        if matches.is_empty() {
+            return Ok(format!("No definitions of '{}' found", args.name));
+        }
+        let mut res = matches.join("\n");
+        if total > matches.len() {
+            res.push_str(&format!(
+                "\n... {} more definitions are not shown, narrow the search with the path or a qualified name",
+                total - matches.len()
+            ));
+        }
+        Ok(res)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FindReferencesToolArgs {
+    pub name: String,
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+pub struct FindReferencesTool {
+    pub workspace: PathBuf,
+    /// Directories outside of the workspace allowed for reading
+    pub read_only_roots: Vec<PathBuf>,
+}
+
+impl FindReferencesTool {
+    pub fn new(workspace: PathBuf, read_only_roots: Vec<PathBuf>) -> Self {
+        Self {
+            workspace,
+            read_only_roots,
+        }
+    }
+}
+
+impl Tool for FindReferencesTool {
+    const NAME: &'static str = "find_references";
+
+    type Error = AgentToolError;
+    type Args = FindReferencesToolArgs;
+    type Output = String;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name(),
+            description: formatdoc! {"\
+                Request to find all usages of the identifier (function, method, type, variable or field name) in the source code. \
+                Unlike search_files, matches in comments, strings and longer identifiers are skipped. \
+                References are found by syntax, so unrelated symbols with the same name are reported too. \
+                Returns at most {} references. Supported languages: {}.", MAX_REFERENCES, SUPPORTED_LANGUAGES}.to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "name": {
+                        "type": "string",
+                        "description": "The identifier to find, e.g. `parse` or `Config`",
+                    },
+                    "path": {
+                        "type": "string",
+                        "description": path_description(&self.workspace, "to search in, the whole workspace by default"),
+                    }
+                },
+                "required": ["name"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let path = resolve_read_path(
+            &self.workspace,
+            &self.read_only_roots,
+            args.path.as_deref().unwrap_or("."),
+        )?;
+        tracing::info!("Find references of '{}' in '{}'", args.name, path.display());
+        let (_, name) = split_qualified_name(&args.name);
+        let mut lines = Vec::new();
+        let mut total = 0;
+        let mut files = 0;
+        for file_path in source_files(&path) {
+            let Some(file) = SourceFile::parse(&file_path) else {
+                continue;
+            };
+            let mut references = file.references(name);
+            // several references on one line are reported once
+            references.dedup_by_key(|reference| reference.line);
+            if references.is_empty() {
+                continue;
+            }
+            files += 1;
+            total += references.len();
+            for reference in references {
+                if lines.len() >= MAX_REFERENCES {
+                    break;
+                }
+                lines.push(format!(
+                    "{}:{}:{} {}",
+                    display_path(&self.workspace, &file_path),
+                    reference.line,
+                    if reference.is_definition {
+                        " (definition)"
+                    } else {
+                        ""
+                    },
+                    file.line(reference.line).trim()
+                ));
+            }
+        }
+        if lines.is_empty() {
+            return Ok(format!("No references of '{}' found", name));
+        }
+        let mut res = lines.join("\n");
+        if total > lines.len() {
+            res.push_str(&format!(
+                "\n... {} more references are not shown, narrow the search with the path",
+                total - lines.len()
+            ));
+        }
+        res.push_str(&format!("\nFound {} references in {} files", total, files));
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::tools::test_dir;
+
+    #[tokio::test]
+    async fn test_code_nav_tools() {
+        let workspace = test_dir("code-nav");
+        fs::create_dir_all(workspace.join("src")).unwrap();
+        fs::write(
+            workspace.join("src/config.rs"),
+            indoc::indoc! {"
+                pub struct Config {}
+
+                impl Config {
+                    pub fn new() -> Self {
+                        Config {}
+                    }
+                }
+            "},
+        )
+        .unwrap();
+        fs::write(
+            workspace.join("src/main.py"),
+            indoc::indoc! {"
+                class Config:
+                    def new(self):
+                        return Config()
+            "},
+        )
+        .unwrap();
+
+        let outline = CodeOutlineTool::new(workspace.clone(), Vec::new())
+            .call(CodeOutlineToolArgs {
+                path: "src/config.rs".to_string(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            outline,
+            "src/config.rs:\n  1-1: pub struct Config {}\n  3-7: impl Config\n    4-6: pub fn new() -> Self"
+        );
+
+        // definitions of a large file are truncated
+        let source = (0..MAX_OUTLINE_LINES + 10)
+            .map(|idx| format!("fn f{idx}() {{}}\n"))
+            .collect::<String>();
+        fs::write(workspace.join("src/large.rs"), source).unwrap();
+        let outline = CodeOutlineTool::new(workspace.clone(), Vec::new())
+            .call(CodeOutlineToolArgs {
+                path: "src/large.rs".to_string(),
+            })
+            .await
+            .unwrap();
+        assert!(outline.starts_with("src/large.rs:\n  1-1: fn f0()\n"));
+        assert!(outline
+            .ends_with("\n  ... 11 more definitions are not shown, use find_symbol or read_file"));
+        fs::remove_file(workspace.join("src/large.rs")).unwrap();
+
+        let symbol = FindSymbolTool::new(workspace.clone(), Vec::new())
+            .call(FindSymbolToolArgs {
+                name: "Config::new".to_string(),
+                path: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            symbol,
+            "src/config.rs:4-6 (function)\n4|    pub fn new() -> Self {\n5|        Config {}\n6|    }\n"
+        );
+
+        let references = FindReferencesTool::new(workspace.clone(), Vec::new())
+            .call(FindReferencesToolArgs {
+                name: "Config".to_string(),
+                path: Some("src/main.py".to_string()),
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            references,
+            "src/main.py:1: (definition) class Config:\nsrc/main.py:3: return Config()\nFound 2 references in 1 files"
+        );
+        fs::remove_dir_all(workspace).ok();
+    }
+}
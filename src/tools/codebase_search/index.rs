@@ -0,0 +1,513 @@
+// Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::EmbeddingProvider;
+use crate::tools::code_nav::SourceFile;
+use crate::tools::memory::indexer::MemoryEmbeddingModel;
+
+/// Maximal number of lines of one chunk, larger definitions are split
+const MAX_CHUNK_LINES: usize = 60;
+/// Lines shared by consecutive chunks of split files
+const CHUNK_OVERLAP: usize = 10;
+/// Larger files are not indexed, they are usually generated
+const MAX_FILE_SIZE: u64 = 512 * 1024;
+const MAX_INDEXED_FILES: usize = 10000;
+/// Number of chunks embedded in one request
+const EMBEDDING_BATCH_SIZE: usize = 64;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Chunk {
+    /// 1-based lines of the chunk
+    pub start_line: usize,
+    pub end_line: usize,
+    pub text: String,
+    embedding: Vec<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexedFile {
+    modified: SystemTime,
+    chunks: Vec<Chunk>,
+}
+
+/// Embedding model of the stored index, embeddings of other models aren't comparable
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct EmbeddingModelId {
+    provider: String,
+    model: String,
+    dimensions: usize,
+}
+
+impl EmbeddingModelId {
+    fn new(provider: &EmbeddingProvider) -> Self {
+        match provider {
+            EmbeddingProvider::Fastembed => Self {
+                provider: "fastembed".to_string(),
+                model: "all-MiniLM-L6-v2".to_string(),
+                dimensions: 384,
+            },
+            EmbeddingProvider::VoyageAi {
+                model, dimensions, ..
+            } => Self {
+                provider: "voyage_ai".to_string(),
+                model: model.clone(),
+                dimensions: *dimensions,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct IndexStorage {
+    workspace: PathBuf,
+    #[serde(default)]
+    embedding_model: EmbeddingModelId,
+    /// Indexed files by the path relative to the workspace
+    files: HashMap<PathBuf, IndexedFile>,
+}
+
+impl IndexStorage {
+    /// Moves embedded chunks of the files to the index
+    fn insert_files(&mut self, files: &mut [(PathBuf, SystemTime, Vec<Chunk>)]) {
+        for (path, modified, chunks) in files {
+            self.files.insert(
+                path.clone(),
+                IndexedFile {
+                    modified: *modified,
+                    chunks: std::mem::take(chunks),
+                },
+            );
+        }
+    }
+}
+
+/// Index of workspace source files for the semantic search, embeddings are persisted
+/// in the data directory and refreshed for modified files before every search
+pub struct CodebaseIndex {
+    storage_path: PathBuf,
+    workspace: PathBuf,
+    embedding_provider: EmbeddingProvider,
+    embedding_model: Option<MemoryEmbeddingModel>,
+    storage: Option<IndexStorage>,
+}
+
+fn is_indexed_file(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    // lock files are large and irrelevant for the search
+    !(name.ends_with(".lock") || name.ends_with("-lock.json") || name.ends_with("-lock.yaml"))
+        && fs::metadata(path).is_ok_and(|metadata| metadata.len() <= MAX_FILE_SIZE)
+}
+
+/// Splits lines `start..end` (0-based, exclusive) into overlapping windows
+fn line_windows(start: usize, end: usize) -> Vec<(usize, usize)> {
+    let mut windows = Vec::new();
+    let mut from = start;
+    loop {
+        let to = (from + MAX_CHUNK_LINES).min(end);
+        windows.push((from, to));
+        if to >= end {
+            return windows;
+        }
+        from = to - CHUNK_OVERLAP;
+    }
+}
+
+/// Line ranges of chunks, definitions of supported languages are kept in separate chunks
+/// and the code between them (imports, top-level statements) is chunked by lines
+fn chunk_ranges(path: &Path, content: &str) -> Vec<(usize, usize)> {
+    let line_count = content.lines().count();
+    let symbols = SourceFile::parse(path)
+        .map(|file| file.symbols())
+        .unwrap_or_default();
+    if symbols.is_empty() {
+        return line_windows(0, line_count);
+    }
+    let mut ranges = Vec::new();
+    let mut covered_until = 0;
+    for (idx, symbol) in symbols.iter().enumerate() {
+        if symbol.start_line <= covered_until {
+            continue;
+        }
+        let has_members = symbols
+            .get(idx + 1)
+            .is_some_and(|next| next.depth > symbol.depth);
+        let lines = symbol.end_line - symbol.start_line + 1;
+        // members of large types are chunked separately
+        if lines > MAX_CHUNK_LINES && has_members {
+            continue;
+        }
+        ranges.extend(line_windows(symbol.start_line - 1, symbol.end_line));
+        covered_until = symbol.end_line;
+    }
+    let lines = content.lines().collect::<Vec<_>>();
+    let mut covered = vec![false; line_count];
+    for (start, end) in ranges.iter() {
+        covered[(*start).min(line_count)..(*end).min(line_count)].fill(true);
+    }
+    // only gaps with code are chunked, closing brackets and blank lines aren't worth a chunk
+    let has_code = |idx: &usize| lines[*idx].chars().any(char::is_alphanumeric);
+    let mut idx = 0;
+    while idx < line_count {
+        if covered[idx] {
+            idx += 1;
+            continue;
+        }
+        let gap_end = (idx..line_count)
+            .find(|idx| covered[*idx])
+            .unwrap_or(line_count);
+        if let (Some(start), Some(end)) = (
+            (idx..gap_end).find(has_code),
+            (idx..gap_end).rev().find(has_code),
+        ) {
+            ranges.extend(line_windows(start, end + 1));
+        }
+        idx = gap_end;
+    }
+    ranges.sort();
+    ranges
+}
+
+/// Loads the stored index, it's discarded if it was built for another workspace or embedding model
+fn load_storage(path: &Path, workspace: &Path, embedding_model: EmbeddingModelId) -> IndexStorage {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<IndexStorage>(&content).ok())
+        .filter(|storage| {
+            storage.workspace == workspace && storage.embedding_model == embedding_model
+        })
+        .unwrap_or_else(|| IndexStorage {
+            workspace: workspace.to_path_buf(),
+            embedding_model,
+            files: HashMap::new(),
+        })
+}
+
+fn save_storage(path: &Path, storage: &IndexStorage) -> color_eyre::Result<()> {
+    fs::write(path, serde_json::to_string(storage)?)?;
+    Ok(())
+}
+
+/// Files of the workspace found by the scan
+#[derive(Default)]
+struct WorkspaceScan {
+    /// All indexed files relative to the workspace
+    seen: HashSet<PathBuf>,
+    /// Files modified since they were indexed with their chunks to be embedded
+    pending: Vec<(PathBuf, SystemTime, Vec<Chunk>)>,
+}
+
+/// Walks the workspace and chunks the files modified since the `indexed` modification times,
+/// binary and unreadable files are not seen, so they are dropped from the index
+fn scan_workspace(workspace: &Path, indexed: &HashMap<PathBuf, SystemTime>) -> WorkspaceScan {
+    let mut scan = WorkspaceScan::default();
+    let files = ignore::Walk::new(workspace)
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+        .map(|entry| entry.into_path())
+        .filter(|path| is_indexed_file(path))
+        .take(MAX_INDEXED_FILES)
+        .collect::<Vec<_>>();
+    for path in files {
+        let Ok(modified) = fs::metadata(&path).and_then(|m| m.modified()) else {
+            continue;
+        };
+        let relative = path.strip_prefix(workspace).unwrap_or(&path).to_path_buf();
+        if indexed.get(&relative) == Some(&modified) {
+            scan.seen.insert(relative);
+            continue;
+        }
+        let content = match fs::read_to_string(&path) {
+            Ok(content) if !content.contains('\0') => content,
+            _ => continue,
+        };
+        let lines = content.lines().collect::<Vec<_>>();
+        let chunks = chunk_ranges(&path, &content)
+            .into_iter()
+            .map(|(start, end)| Chunk {
+                start_line: start + 1,
+                end_line: end,
+                text: lines[start..end].join("\n"),
+                embedding: Vec::new(),
+            })
+            .filter(|chunk| !chunk.text.trim().is_empty())
+            .collect::<Vec<_>>();
+        scan.seen.insert(relative.clone());
+        scan.pending.push((relative, modified, chunks));
+    }
+    scan
+}
+
+/// Embeds chunks of the pending files, chunks of several files share embedding requests.
+/// Files are moved to the index as soon as all their chunks are embedded, so a failed
+/// request doesn't discard the finished work.
+async fn embed_files(
+    model: &MemoryEmbeddingModel,
+    storage: &mut IndexStorage,
+    pending: &mut [(PathBuf, SystemTime, Vec<Chunk>)],
+) -> color_eyre::Result<()> {
+    let chunk_refs = pending
+        .iter()
+        .enumerate()
+        .flat_map(|(file_idx, (_, _, chunks))| {
+            (0..chunks.len()).map(move |chunk_idx| (file_idx, chunk_idx))
+        })
+        .collect::<Vec<_>>();
+    let mut indexed = 0;
+    for (batch_idx, batch) in chunk_refs.chunks(EMBEDDING_BATCH_SIZE).enumerate() {
+        // the path gives the model context of the chunk
+        let texts = batch
+            .iter()
+            .map(|(file_idx, chunk_idx)| {
+                let (path, _, chunks) = &pending[*file_idx];
+                format!("{}\n{}", path.display(), chunks[*chunk_idx].text)
+            })
+            .collect();
+        let embeddings = model.embed_texts(texts).await?;
+        for ((file_idx, chunk_idx), embedding) in batch.iter().zip(embeddings) {
+            pending[*file_idx].2[*chunk_idx].embedding = embedding.vec;
+        }
+        // files before the next chunk are embedded completely
+        let next_file = chunk_refs
+            .get((batch_idx + 1) * EMBEDDING_BATCH_SIZE)
+            .map(|(file_idx, _)| *file_idx)
+            .unwrap_or(pending.len());
+        storage.insert_files(&mut pending[indexed..next_file]);
+        indexed = next_file;
+    }
+    // files without chunks to embed
+    storage.insert_files(&mut pending[indexed..]);
+    Ok(())
+}
+
+fn cosine_similarity(a: &[f64], b: &[f64]) -> f64 {
+    let dot = a.iter().zip(b).map(|(a, b)| a * b).sum::<f64>();
+    let norm = |v: &[f64]| v.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+impl CodebaseIndex {
+    pub fn new(data_dir: &Path, workspace: &Path, embedding_provider: EmbeddingProvider) -> Self {
+        Self {
+            storage_path: data_dir.join("codebase_embeddings.json"),
+            workspace: workspace.to_path_buf(),
+            embedding_provider,
+            embedding_model: None,
+            storage: None,
+        }
+    }
+
+    /// Embeds files modified since the previous refresh and drops removed files. The workspace
+    /// is scanned and the index is persisted in blocking tasks, the index is saved once
+    /// after all files are embedded or when an embedding request fails.
+    async fn refresh(&mut self) -> color_eyre::Result<()> {
+        if self.storage.is_none() {
+            let storage_path = self.storage_path.clone();
+            let workspace = self.workspace.clone();
+            let embedding_model = EmbeddingModelId::new(&self.embedding_provider);
+            self.storage = Some(
+                tokio::task::spawn_blocking(move || {
+                    load_storage(&storage_path, &workspace, embedding_model)
+                })
+                .await?,
+            );
+        }
+        let storage = self.storage.as_mut().unwrap();
+        let indexed = storage
+            .files
+            .iter()
+            .map(|(path, file)| (path.clone(), file.modified))
+            .collect::<HashMap<_, _>>();
+        let workspace = self.workspace.clone();
+        let mut scan =
+            tokio::task::spawn_blocking(move || scan_workspace(&workspace, &indexed)).await?;
+        let count = storage.files.len();
+        storage.files.retain(|path, _| scan.seen.contains(path));
+        if scan.pending.is_empty() && count == storage.files.len() {
+            return Ok(());
+        }
+
+        tracing::debug!("Indexing {} files", scan.pending.len());
+        let model = self
+            .embedding_model
+            .get_or_insert_with(|| MemoryEmbeddingModel::new(&self.embedding_provider));
+        let result = embed_files(model, storage, &mut scan.pending).await;
+        let saved = self.save().await;
+        match result {
+            Ok(()) => saved,
+            Err(e) => {
+                if let Err(e) = saved {
+                    tracing::warn!("Failed to save codebase index: {}", e);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Writes the index in a blocking task, the storage is moved there and back
+    async fn save(&mut self) -> color_eyre::Result<()> {
+        let Some(storage) = self.storage.take() else {
+            return Ok(());
+        };
+        let storage_path = self.storage_path.clone();
+        let (storage, result) = tokio::task::spawn_blocking(move || {
+            let result = save_storage(&storage_path, &storage);
+            (storage, result)
+        })
+        .await?;
+        self.storage = Some(storage);
+        result
+    }
+
+    /// Chunks most similar to the query with the paths of their files
+    pub async fn search(
+        &mut self,
+        query: &str,
+        limit: usize,
+    ) -> color_eyre::Result<Vec<(PathBuf, Chunk)>> {
+        self.refresh().await?;
+        let (Some(model), Some(storage)) = (&self.embedding_model, &self.storage) else {
+            return Ok(Vec::new());
+        };
+        let query = model
+            .embed_texts(vec![query.to_string()])
+            .await?
+            .pop()
+            .map(|embedding| embedding.vec)
+            .unwrap_or_default();
+        let mut results = storage
+            .files
+            .iter()
+            .flat_map(|(path, file)| file.chunks.iter().map(move |chunk| (path, chunk)))
+            .map(|(path, chunk)| (cosine_similarity(&query, &chunk.embedding), path, chunk))
+            .collect::<Vec<_>>();
+        results.sort_by(|(a, _, _), (b, _, _)| b.total_cmp(a));
+        Ok(results
+            .into_iter()
+            .take(limit)
+            .map(|(_, path, chunk)| (path.clone(), chunk.clone()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::test_dir;
+
+    #[test]
+    fn test_chunk_ranges() {
+        assert_eq!(line_windows(0, 20), vec![(0, 20)]);
+        assert_eq!(line_windows(0, 130), vec![(0, 60), (50, 110), (100, 130)]);
+
+        let mut source = String::from("use std::fs;\n\nstruct Large {}\n\nimpl Large {\n");
+        for idx in 0..70 {
+            source.push_str(&format!("    fn method_{idx}() {{}}\n"));
+        }
+        source.push_str("}\n\nfn small() {\n}\n");
+        let dir = test_dir("chunks");
+        let path = dir.join("source.rs");
+        fs::write(&path, &source).unwrap();
+        let ranges = chunk_ranges(&path, &source);
+        fs::remove_dir_all(&dir).ok();
+        // the import, the struct, the impl header, every method of the large impl and the function
+        assert_eq!(ranges.len(), 74);
+        assert_eq!(ranges[0], (0, 1));
+        assert_eq!(ranges[1], (2, 3));
+        assert_eq!(ranges[2], (4, 5));
+        assert_eq!(ranges[3], (5, 6));
+        assert_eq!(ranges[73], (77, 79));
+        assert!((cosine_similarity(&[1.0, 0.0], &[1.0, 0.0]) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_load_storage() {
+        let dir = test_dir("index");
+        let storage_path = dir.join("codebase_embeddings.json");
+        let model_id = |dimensions: usize| {
+            EmbeddingModelId::new(&EmbeddingProvider::VoyageAi {
+                api_key: String::new(),
+                model: "voyage-code-3".to_string(),
+                dimensions,
+            })
+        };
+        let mut storage = load_storage(&storage_path, &dir, model_id(1024));
+        storage.files.insert(
+            PathBuf::from("main.rs"),
+            IndexedFile {
+                modified: SystemTime::UNIX_EPOCH,
+                chunks: Vec::new(),
+            },
+        );
+        save_storage(&storage_path, &storage).unwrap();
+        assert_eq!(
+            load_storage(&storage_path, &dir, model_id(1024))
+                .files
+                .len(),
+            1
+        );
+
+        // embeddings of another model have other dimensions
+        assert!(load_storage(&storage_path, &dir, model_id(512))
+            .files
+            .is_empty());
+        let fastembed = EmbeddingModelId::new(&EmbeddingProvider::Fastembed);
+        assert!(load_storage(&storage_path, &dir, fastembed)
+            .files
+            .is_empty());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_refresh_and_search() {
+        let dir = test_dir("index-search");
+        let workspace = dir.join("workspace");
+        fs::create_dir_all(&workspace).unwrap();
+        fs::write(
+            workspace.join("session.rs"),
+            "fn save_session(session: &Session) {\n    persist the session to disk\n}\n",
+        )
+        .unwrap();
+        fs::write(
+            workspace.join("retry.rs"),
+            "fn retry_request(request: Request) {\n    retry http request with backoff\n}\n",
+        )
+        .unwrap();
+        let mut index = CodebaseIndex::new(&dir, &workspace, EmbeddingProvider::Fastembed);
+        index.embedding_model = Some(MemoryEmbeddingModel::stub());
+
+        let results = index.search("retry http request", 1).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, PathBuf::from("retry.rs"));
+        assert_eq!((results[0].1.start_line, results[0].1.end_line), (1, 3));
+
+        // removed files are dropped from the persisted index
+        fs::remove_file(workspace.join("retry.rs")).unwrap();
+        let results = index.search("retry http request", 10).await.unwrap();
+        assert!(results
+            .iter()
+            .all(|(path, _)| path == Path::new("session.rs")));
+        let storage = load_storage(
+            &index.storage_path,
+            &workspace,
+            EmbeddingModelId::new(&EmbeddingProvider::Fastembed),
+        );
+        assert_eq!(
+            storage.files.keys().collect::<Vec<_>>(),
+            vec![Path::new("session.rs")]
+        );
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,111 @@
+// Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+use std::sync::Arc;
+
+use indoc::formatdoc;
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::RwLock;
+
+use crate::tools::{workspace_to_string, AgentToolError};
+
+use self::index::CodebaseIndex;
+
+pub mod index;
+
+const DEFAULT_MAX_RESULTS: usize = 10;
+const MAX_RESULTS: usize = 50;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodebaseSearchToolArgs {
+    pub query: String,
+    #[serde(default)]
+    pub max_results: Option<usize>,
+}
+
+pub struct CodebaseSearchTool {
+    index: Arc<RwLock<CodebaseIndex>>,
+}
+
+impl CodebaseSearchTool {
+    pub fn new(index: Arc<RwLock<CodebaseIndex>>) -> Self {
+        Self { index }
+    }
+}
+
+impl Tool for CodebaseSearchTool {
+    const NAME: &'static str = "codebase_search";
+
+    type Error = AgentToolError;
+    type Args = CodebaseSearchToolArgs;
+    type Output = String;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name(),
+            description: formatdoc! {"\
+                Request to find code snippets semantically related to the natural-language query across the workspace, \
+                e.g. 'where are the user sessions persisted' or 'retry logic of HTTP requests'. \
+                Use this tool when you don't know the exact names to search for with search_files or find_symbol. \
+                Returns the most relevant snippets with their file paths and line ranges. \
+                Files ignored by .gitignore are skipped, the first search in a large workspace can take a while \
+                to build the index."}.to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "query": {
+                        "type": "string",
+                        "description": "The natural-language description of the code to find",
+                    },
+                    "max_results": {
+                        "type": "integer",
+                        "description": format!("Maximal number of snippets to return (default {}, max {})", DEFAULT_MAX_RESULTS, MAX_RESULTS),
+                    }
+                },
+                "required": ["query"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        tracing::info!("Codebase search '{}'", args.query);
+        let limit = args
+            .max_results
+            .unwrap_or(DEFAULT_MAX_RESULTS)
+            .clamp(1, MAX_RESULTS);
+        let results = self
+            .index
+            .write()
+            .await
+            .search(&args.query, limit)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to search the codebase: {}", e))?;
+        if results.is_empty() {
+            return Ok("No results found".to_string());
+        }
+        Ok(results
+            .into_iter()
+            .map(|(path, chunk)| {
+                let width = chunk.end_line.to_string().len();
+                let lines = chunk
+                    .text
+                    .lines()
+                    .enumerate()
+                    .map(|(idx, line)| {
+                        format!("{:>width$}|{}", chunk.start_line + idx, line, width = width)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                format!(
+                    "{}:{}-{}\n{}\n",
+                    workspace_to_string(&path),
+                    chunk.start_line,
+                    chunk.end_line,
+                    lines
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+}
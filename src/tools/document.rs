@@ -0,0 +1,154 @@
+// Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::path::Path;
+
+use calamine::{open_workbook_auto, Reader as _};
+use quick_xml::events::Event;
+
+/// Extracts text from documents by the file extension, returns None for other files
+pub fn extract_text(path: &Path) -> Option<std::io::Result<String>> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    match extension.as_str() {
+        "pdf" => Some(extract_pdf_text(path)),
+        "docx" => Some(extract_zipped_xml_text(path, "word/document.xml")),
+        "odt" => Some(extract_zipped_xml_text(path, "content.xml")),
+        "xlsx" | "xls" | "ods" => Some(extract_spreadsheet_csv(path)),
+        _ => None,
+    }
+}
+
+fn invalid_data(e: impl ToString) -> std::io::Error {
+    std::io::Error::new(ErrorKind::InvalidData, e.to_string())
+}
+
+/// Name of the threads extracting text, the panic hook doesn't exit the app on their panics
+pub const EXTRACT_THREAD_NAME: &str = "document-extract";
+
+/// Runs the extraction in a separate thread, so a panic of the parser is reported as an error
+fn extract_in_thread(
+    path: &Path,
+    extract: impl FnOnce() -> std::io::Result<String> + Send + 'static,
+) -> std::io::Result<String> {
+    std::thread::Builder::new()
+        .name(EXTRACT_THREAD_NAME.to_string())
+        .spawn(extract)?
+        .join()
+        .map_err(|_| invalid_data(format!("Failed to parse file {}", path.display())))?
+}
+
+fn extract_pdf_text(path: &Path) -> std::io::Result<String> {
+    // pdf-extract panics on some malformed documents
+    let pdf_path = path.to_path_buf();
+    extract_in_thread(path, move || {
+        pdf_extract::extract_text(&pdf_path).map_err(invalid_data)
+    })
+}
+
+fn extract_zipped_xml_text(path: &Path, entry: &str) -> std::io::Result<String> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?).map_err(invalid_data)?;
+    let mut xml = String::new();
+    archive
+        .by_name(entry)
+        .map_err(invalid_data)?
+        .read_to_string(&mut xml)?;
+    extract_xml_text(&xml)
+}
+
+/// Collects text nodes of DOCX or ODT document XML, paragraphs are separated by new lines
+fn extract_xml_text(xml: &str) -> std::io::Result<String> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut text = String::new();
+    // text outside of paragraphs is the formatting of XML
+    let mut paragraph_depth = 0;
+    let is_paragraph = |name: &[u8]| matches!(name, b"p" | b"h");
+    loop {
+        match reader.read_event().map_err(invalid_data)? {
+            Event::Start(e) if is_paragraph(e.local_name().as_ref()) => paragraph_depth += 1,
+            Event::End(e) if is_paragraph(e.local_name().as_ref()) => {
+                paragraph_depth -= 1;
+                text.push('\n');
+            }
+            Event::Text(e) if paragraph_depth > 0 => {
+                text.push_str(&e.unescape().map_err(invalid_data)?)
+            }
+            Event::Empty(e) => match e.local_name().as_ref() {
+                b"p" | b"h" => text.push('\n'),
+                b"tab" => text.push('\t'),
+                b"br" | b"cr" | b"line-break" => text.push('\n'),
+                b"s" => text.push(' '),
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(text)
+}
+
+fn csv_field(value: String) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+/// Converts every sheet of the workbook to CSV
+fn extract_spreadsheet_csv(path: &Path) -> std::io::Result<String> {
+    let mut workbook = open_workbook_auto(path).map_err(invalid_data)?;
+    let mut text = String::new();
+    for name in workbook.sheet_names() {
+        let range = workbook.worksheet_range(&name).map_err(invalid_data)?;
+        text.push_str(&format!("## Sheet: {name}\n"));
+        for row in range.rows() {
+            let row = row
+                .iter()
+                .map(|cell| csv_field(cell.to_string()))
+                .collect::<Vec<_>>();
+            text.push_str(&row.join(","));
+            text.push('\n');
+        }
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_xml_text() {
+        let docx = r#"<w:document xmlns:w="w"><w:body>
+            <w:p><w:r><w:t>Hello</w:t></w:r><w:r><w:tab/><w:t xml:space="preserve">world &amp; all</w:t></w:r></w:p>
+            <w:p/>
+            <w:p><w:r><w:t>Second</w:t><w:br/><w:t>line</w:t></w:r></w:p>
+        </w:body></w:document>"#;
+        assert_eq!(
+            extract_xml_text(docx).unwrap(),
+            "Hello\tworld & all\n\nSecond\nline\n"
+        );
+
+        let odt =
+            r#"<office:text><text:h>Title</text:h><text:p>One<text:s/>two</text:p></office:text>"#;
+        assert_eq!(extract_xml_text(odt).unwrap(), "Title\nOne two\n");
+        assert_eq!(csv_field("a,b".to_string()), "\"a,b\"");
+    }
+
+    #[test]
+    fn test_extract_panic() {
+        // the hook of the app exits the process on panics of other threads
+        crate::init_panic_hook();
+        let path = crate::tools::test_dir("pdf").join("broken.pdf");
+        std::fs::write(
+            &path,
+            "%PDF-1.4\n1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\ntrailer\n",
+        )
+        .unwrap();
+        let pdf = extract_text(&path).unwrap();
+        let panic = extract_in_thread(&path, || panic!("malformed document"));
+        drop(std::panic::take_hook());
+        assert!(pdf.is_err());
+        assert_eq!(panic.unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+}
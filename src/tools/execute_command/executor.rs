@@ -0,0 +1,289 @@
+// Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+//! Executors of commands: the shell runs on the host or is wrapped into a sandbox,
+//! the executor is selected by the permission mode
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::config::{CommandExecutorConfig, Config};
+
+/// Command line prepared by the executor
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecutorCommand {
+    /// Program followed by its arguments
+    pub args: Vec<String>,
+    /// Command removing the sandbox, since it may outlive the killed program
+    pub cleanup: Option<Vec<String>>,
+}
+
+pub trait CommandExecutor: Send + Sync {
+    /// Wraps the shell command run in the directory, `tty` is set for pseudo-terminal commands
+    fn wrap(&self, command: Vec<String>, cwd: &str, tty: bool) -> ExecutorCommand;
+}
+
+/// Runs commands in the host with the user privileges
+pub struct HostExecutor;
+
+impl CommandExecutor for HostExecutor {
+    fn wrap(&self, command: Vec<String>, _cwd: &str, _tty: bool) -> ExecutorCommand {
+        ExecutorCommand {
+            args: command,
+            cleanup: None,
+        }
+    }
+}
+
+/// Runs commands in Linux namespaces with bubblewrap: the file system is read-only
+/// except the workspace, the extra writable paths and a private `/tmp`
+pub struct BubblewrapExecutor {
+    workspace: PathBuf,
+    network: bool,
+    writable_paths: Vec<PathBuf>,
+}
+
+impl BubblewrapExecutor {
+    pub fn new(workspace: &Path, network: bool, writable_paths: Vec<PathBuf>) -> Self {
+        Self {
+            workspace: absolute_path(workspace),
+            network,
+            writable_paths: writable_paths.iter().map(|p| absolute_path(p)).collect(),
+        }
+    }
+}
+
+impl CommandExecutor for BubblewrapExecutor {
+    fn wrap(&self, command: Vec<String>, cwd: &str, tty: bool) -> ExecutorCommand {
+        let mut args = [
+            "bwrap",
+            "--ro-bind",
+            "/",
+            "/",
+            "--dev",
+            "/dev",
+            "--proc",
+            "/proc",
+            "--tmpfs",
+            "/tmp",
+        ]
+        .map(String::from)
+        .to_vec();
+        for path in std::iter::once(&self.workspace).chain(&self.writable_paths) {
+            let path = path.to_string_lossy().to_string();
+            args.extend(["--bind".to_string(), path.clone(), path]);
+        }
+        if !self.network {
+            args.push("--unshare-net".to_string());
+        }
+        args.extend(["--unshare-pid", "--die-with-parent"].map(String::from));
+        // commands without a pseudo-terminal would share the controlling terminal of huly-coder
+        // and could inject input into it with TIOCSTI, the pseudo-terminal is a session of its own
+        if !tty {
+            args.push("--new-session".to_string());
+        }
+        args.push("--chdir".to_string());
+        args.push(absolute_path(Path::new(cwd)).to_string_lossy().to_string());
+        args.push("--".to_string());
+        args.extend(command);
+        ExecutorCommand {
+            args,
+            cleanup: None,
+        }
+    }
+}
+
+/// Runs every command in a new container of the image with the workspace mounted at the same path
+pub struct ContainerExecutor {
+    workspace: PathBuf,
+    runtime: String,
+    image: String,
+    network: bool,
+    extra_args: Vec<String>,
+    /// Names of variables passed from the command environment
+    env: Vec<String>,
+    counter: AtomicUsize,
+}
+
+impl ContainerExecutor {
+    pub fn new(
+        workspace: &Path,
+        runtime: String,
+        image: String,
+        network: bool,
+        extra_args: Vec<String>,
+        env: Vec<String>,
+    ) -> Self {
+        Self {
+            workspace: absolute_path(workspace),
+            runtime,
+            image,
+            network,
+            extra_args,
+            env,
+            counter: AtomicUsize::new(0),
+        }
+    }
+
+    /// Runs the container as the owner of the workspace, so created files aren't owned by root
+    #[cfg(unix)]
+    fn user(&self) -> Option<String> {
+        use std::os::unix::fs::MetadataExt;
+
+        let metadata = std::fs::metadata(&self.workspace).ok()?;
+        Some(format!("{}:{}", metadata.uid(), metadata.gid()))
+    }
+
+    #[cfg(not(unix))]
+    fn user(&self) -> Option<String> {
+        None
+    }
+}
+
+impl CommandExecutor for ContainerExecutor {
+    fn wrap(&self, command: Vec<String>, cwd: &str, tty: bool) -> ExecutorCommand {
+        let name = format!(
+            "huly-coder-{}-{}",
+            std::process::id(),
+            self.counter.fetch_add(1, Ordering::SeqCst) + 1
+        );
+        let workspace = self.workspace.to_string_lossy();
+        let mut args = vec![
+            self.runtime.clone(),
+            "run".to_string(),
+            "--rm".to_string(),
+            "-i".to_string(),
+            "--name".to_string(),
+            name.clone(),
+        ];
+        if tty {
+            args.push("-t".to_string());
+        }
+        if !self.network {
+            args.extend(["--network".to_string(), "none".to_string()]);
+        }
+        if let Some(user) = self.user() {
+            args.extend(["--user".to_string(), user]);
+        }
+        // values are taken from the environment of the runtime, so they aren't visible in arguments
+        for name in &self.env {
+            args.extend(["-e".to_string(), name.clone()]);
+        }
+        args.extend([
+            "-v".to_string(),
+            format!("{workspace}:{workspace}"),
+            "-w".to_string(),
+            absolute_path(Path::new(cwd)).to_string_lossy().to_string(),
+        ]);
+        args.extend(self.extra_args.iter().cloned());
+        args.push(self.image.clone());
+        args.extend(command);
+        ExecutorCommand {
+            args,
+            cleanup: Some(vec![
+                self.runtime.clone(),
+                "rm".to_string(),
+                "-f".to_string(),
+                name,
+            ]),
+        }
+    }
+}
+
+fn absolute_path(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or(path.to_path_buf())
+}
+
+/// Executor configured for the permission mode
+pub fn create_executor(config: &Config) -> Box<dyn CommandExecutor> {
+    match config.sandbox.executor(&config.permission_mode) {
+        CommandExecutorConfig::Host => Box::new(HostExecutor),
+        CommandExecutorConfig::Bubblewrap {
+            network,
+            writable_paths,
+        } => Box::new(BubblewrapExecutor::new(
+            &config.workspace,
+            *network,
+            writable_paths.clone(),
+        )),
+        CommandExecutorConfig::Container {
+            runtime,
+            image,
+            network,
+            args,
+        } => Box::new(ContainerExecutor::new(
+            &config.workspace,
+            runtime.clone(),
+            image.clone(),
+            *network,
+            args.clone(),
+            config.terminal.env.keys().cloned().collect(),
+        )),
+    }
+}
+
+/// Removes the sandbox of the terminated command in the background
+pub(super) fn run_cleanup(cleanup: &[String]) {
+    let Some((program, args)) = cleanup.split_first() else {
+        return;
+    };
+    tracing::debug!("Cleanup of the command sandbox: {:?}", cleanup);
+    if let Err(e) = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+    {
+        tracing::warn!("Failed to cleanup the command sandbox: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shell(command: &str) -> Vec<String> {
+        vec!["bash".to_string(), "-c".to_string(), command.to_string()]
+    }
+
+    #[test]
+    fn test_executors() {
+        assert_eq!(
+            HostExecutor.wrap(shell("ls"), "/work", false).args,
+            shell("ls")
+        );
+
+        let bwrap = BubblewrapExecutor::new(Path::new("/work"), false, vec![]);
+        assert_eq!(
+            bwrap.wrap(shell("ls"), "/work/src", false).args.join(" "),
+            "bwrap --ro-bind / / --dev /dev --proc /proc --tmpfs /tmp --bind /work /work \
+             --unshare-net --unshare-pid --die-with-parent --new-session --chdir /work/src -- bash -c ls"
+        );
+        // the sandboxed shell never keeps the controlling terminal of the host
+        assert!(bwrap
+            .wrap(shell("ls"), "/work", false)
+            .args
+            .contains(&"--new-session".to_string()));
+        assert!(!bwrap
+            .wrap(shell("ls"), "/work", true)
+            .args
+            .contains(&"--new-session".to_string()));
+
+        let container = ContainerExecutor::new(
+            Path::new("/work"),
+            "docker".to_string(),
+            "rust:1".to_string(),
+            true,
+            vec!["--cpus=2".to_string()],
+            vec!["RUST_LOG".to_string()],
+        );
+        let command = container.wrap(shell("ls"), "/work", true);
+        let name = format!("huly-coder-{}-1", std::process::id());
+        assert_eq!(
+            command.args.join(" "),
+            format!("docker run --rm -i --name {name} -t -e RUST_LOG -v /work:/work -w /work --cpus=2 rust:1 bash -c ls")
+        );
+        assert_eq!(
+            command.cleanup.unwrap().join(" "),
+            format!("docker rm -f {name}")
+        );
+    }
+}
@@ -1,7 +1,10 @@
 // Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
 
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::process::ExitStatus;
+use std::sync::Arc;
 
 use anyhow::Result;
 use process_wrap::tokio::{TokioChildWrapper, TokioCommandWrap};
@@ -10,30 +13,79 @@ use tokio::process::{ChildStderr, ChildStdin, ChildStdout};
 use tokio::sync::{mpsc, oneshot};
 
 use crate::agent::event::AgentCommandStatus;
+use crate::config::TerminalConfig;
 
+use self::executor::{run_cleanup, CommandExecutor, ExecutorCommand};
+use self::output::{CommandOutput, Redactor};
+use self::pty::PtyRuntime;
+use self::session::{SessionCommand, ShellSession};
+
+pub mod executor;
+mod output;
+mod pty;
+mod session;
 pub mod tools;
 
-const SHELL: &str = "bash";
+/// Directory in the data dir with full outputs of commands, the path is absolute
+/// since the model reads the files relative to the workspace
+pub fn command_output_dir(data_dir: &Path) -> PathBuf {
+    let dir = data_dir.join("command_output");
+    std::path::absolute(&dir).unwrap_or(dir)
+}
 
-#[derive(Default)]
 pub struct ProcessRegistry {
     counter: usize,
     processes: HashMap<usize, ProcessData>,
+    config: TerminalConfig,
+    output_dir: PathBuf,
+    /// Prefix of the output files, the directory is shared by sessions and instances of huly-coder
+    output_prefix: String,
+    executor: Box<dyn CommandExecutor>,
+    /// Redacts secrets of the command environment from the output
+    redactor: Arc<Redactor>,
+    /// Persistent shell session, started by the first command
+    shell_session: Option<ShellSession>,
+    /// Last command run in the shell session
+    session_command: Option<usize>,
 }
 
 struct ProcessData {
     command: String,
-    output: String,
+    output: CommandOutput,
+    /// Emulated terminal screen of commands run in a pseudo-terminal
+    screen: Option<Box<vt100::Screen>>,
     exit_status: Option<i32>,
     receiver: mpsc::UnboundedReceiver<ProcessOutput>,
     terminate_sender: Option<oneshot::Sender<()>>,
     input_sender: Option<mpsc::UnboundedSender<Vec<u8>>>,
+    /// Command removing the sandbox after the process is terminated
+    cleanup: Option<Vec<String>>,
+}
+
+impl ProcessData {
+    fn terminate(&mut self) {
+        if self.exit_status.is_none() {
+            if let Some(sender) = self.terminate_sender.take() {
+                sender.send(()).ok();
+            }
+            if let Some(cleanup) = self.cleanup.take() {
+                run_cleanup(&cleanup);
+            }
+        }
+    }
 }
 
 enum ProcessOutput {
-    Exited(Option<ExitStatus>),
+    /// Exit code of the process, None if it's unknown
+    Exited(Option<i32>),
     Output(String),
     Error(String),
+    /// Output of the pseudo-terminal converted to plain text with the current screen
+    Terminal {
+        text: String,
+        partial_line: String,
+        screen: Box<vt100::Screen>,
+    },
 }
 
 struct ProcessRuntime {
@@ -87,7 +139,11 @@ impl ProcessRuntime {
                 }
             }
         }
-        self.sender.send(ProcessOutput::Exited(exit_status)).ok();
+        self.sender
+            .send(ProcessOutput::Exited(
+                exit_status.map(|s| s.code().unwrap_or_default()),
+            ))
+            .ok();
     }
 
     async fn handle_stdout(stdout: ChildStdout, sender: mpsc::UnboundedSender<ProcessOutput>) {
@@ -148,9 +204,61 @@ impl ProcessRuntime {
 }
 
 impl ProcessRegistry {
+    pub fn new(
+        config: TerminalConfig,
+        output_dir: PathBuf,
+        executor: Box<dyn CommandExecutor>,
+    ) -> Self {
+        if !config.env.is_empty() {
+            // values may be secrets, so only names are logged
+            tracing::info!(
+                "Command environment: {}",
+                config
+                    .env
+                    .keys()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        Self {
+            counter: 0,
+            processes: HashMap::new(),
+            redactor: Arc::new(Redactor::new(&config)),
+            config,
+            output_dir,
+            output_prefix: format!(
+                "command-{}-{}",
+                chrono::Local::now().format("%Y%m%d-%H%M%S"),
+                std::process::id()
+            ),
+            executor,
+            shell_session: None,
+            session_command: None,
+        }
+    }
+
+    pub fn config(&self) -> &TerminalConfig {
+        &self.config
+    }
+
+    /// Replaces secrets of the command environment in the text
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        self.redactor.redact(text)
+    }
+
+    /// Configured shell with the arguments wrapped by the executor
+    fn command_line(&self, shell_args: &[&str], cwd: &str, tty: bool) -> ExecutorCommand {
+        let command = std::iter::once(self.config.shell.clone())
+            .chain(self.config.shell_args.iter().cloned())
+            .chain(shell_args.iter().map(|arg| arg.to_string()))
+            .collect();
+        self.executor.wrap(command, cwd, tty)
+    }
+
     async fn spawn_process(
         &self,
-        command: &str,
+        args: &[String],
         cwd: &str,
     ) -> Result<(
         Box<dyn TokioChildWrapper>,
@@ -158,15 +266,17 @@ impl ProcessRegistry {
         ChildStderr,
         ChildStdin,
     )> {
-        let mut child = TokioCommandWrap::with_new(SHELL, |cmd| {
+        let (program, args) = args
+            .split_first()
+            .ok_or_else(|| anyhow::anyhow!("Empty command line"))?;
+        let mut child = TokioCommandWrap::with_new(program, |cmd| {
             cmd.current_dir(cwd)
+                .envs(&self.config.env)
                 .stdin(std::process::Stdio::piped())
                 .stdout(std::process::Stdio::piped())
                 .stderr(std::process::Stdio::piped());
 
-            cmd.arg("-c");
-
-            cmd.arg(command);
+            cmd.args(args);
         });
         child.wrap(process_wrap::tokio::KillOnDrop);
 
@@ -195,47 +305,125 @@ impl ProcessRegistry {
         Ok((process, stdout, stderr, stdin))
     }
 
-    pub async fn execute_command(&mut self, command: &str, cwd: &str) -> Result<usize> {
+    /// Runs the command in `cwd` or in the workspace, the persistent shell session
+    /// keeps its current directory unless `cwd` is set
+    pub async fn execute_command(
+        &mut self,
+        command: &str,
+        workspace: &str,
+        cwd: Option<&str>,
+    ) -> Result<usize> {
         self.counter = self.counter.saturating_add(1);
-        let (process, stdout, stderr, stdin) = self.spawn_process(command, cwd).await?;
         let (tx, rx) = mpsc::unbounded_channel();
         let (t_tx, t_rx) = tokio::sync::oneshot::channel();
         let (in_tx, in_rx) = mpsc::unbounded_channel();
 
-        let runtime = ProcessRuntime {
-            _process: process,
-            stdout,
-            stderr,
-            stdin,
-            sender: tx,
-            input_signal: in_rx,
-            terminate_signal: t_rx,
+        let cleanup = if self.config.persistent_shell {
+            let cleanup = self
+                .execute_in_session(command, workspace, cwd, tx, in_rx, t_rx)
+                .await?;
+            self.session_command = Some(self.counter);
+            cleanup
+        } else if self.config.pty {
+            let cwd = cwd.unwrap_or(workspace);
+            let command_line = self.command_line(&["-c", command], cwd, true);
+            let runtime =
+                PtyRuntime::spawn(&command_line.args, cwd, &self.config, tx, in_rx, t_rx)?;
+            tokio::spawn(runtime.run());
+            command_line.cleanup
+        } else {
+            let cwd = cwd.unwrap_or(workspace);
+            let command_line = self.command_line(&["-c", command], cwd, false);
+            let (process, stdout, stderr, stdin) =
+                self.spawn_process(&command_line.args, cwd).await?;
+            let runtime = ProcessRuntime {
+                _process: process,
+                stdout,
+                stderr,
+                stdin,
+                sender: tx,
+                input_signal: in_rx,
+                terminate_signal: t_rx,
+            };
+            tokio::spawn(runtime.run());
+            command_line.cleanup
         };
 
-        tokio::spawn(runtime.run());
-
         self.processes.insert(
             self.counter,
             ProcessData {
                 command: command.to_string(),
-                output: String::new(),
+                output: CommandOutput::new(
+                    self.output_dir
+                        .join(format!("{}-{}.log", self.output_prefix, self.counter)),
+                    self.redactor.clone(),
+                ),
+                screen: None,
                 exit_status: None,
                 receiver: rx,
                 terminate_sender: Some(t_tx),
                 input_sender: Some(in_tx),
+                cleanup,
             },
         );
         Ok(self.counter)
     }
 
+    /// Runs the command in the shell session, returns the cleanup of the session sandbox
+    async fn execute_in_session(
+        &mut self,
+        command: &str,
+        workspace: &str,
+        cwd: Option<&str>,
+        sender: mpsc::UnboundedSender<ProcessOutput>,
+        input_signal: mpsc::UnboundedReceiver<Vec<u8>>,
+        terminate_signal: oneshot::Receiver<()>,
+    ) -> Result<Option<Vec<String>>> {
+        let session = match self.shell_session.take() {
+            Some(session) if session.is_alive() => session,
+            _ => {
+                tracing::info!("Start shell session");
+                let command_line = self.command_line(&["-s"], workspace, false);
+                let (process, stdout, stderr, stdin) =
+                    self.spawn_process(&command_line.args, workspace).await?;
+                ShellSession::start(process, stdout, stderr, stdin, command_line.cleanup)
+            }
+        };
+        let session = self.shell_session.insert(session);
+        if session.is_busy() {
+            anyhow::bail!(
+                "The shell session is busy with command {}, wait for its result with get_command_result or terminate it",
+                self.session_command.unwrap_or_default()
+            );
+        }
+        session.execute(SessionCommand {
+            command: command.to_string(),
+            cwd: cwd.map(str::to_string),
+            sender,
+            input_signal,
+            terminate_signal,
+        })?;
+        Ok(session.cleanup().cloned())
+    }
+
+    /// Terminates the shell session, the next command starts a new one
+    pub fn reset_shell(&mut self) {
+        if let Some(id) = self.session_command.take() {
+            self.stop_process(id).ok();
+        }
+        if let Some(session) = self.shell_session.take() {
+            tracing::info!("Reset shell session");
+            if let Some(cleanup) = session.cleanup() {
+                run_cleanup(cleanup);
+            }
+        }
+    }
+
     pub fn stop(&mut self) {
         tracing::info!("Stop all running terminal commands");
+        self.reset_shell();
         for (_, mut process) in self.processes.drain() {
-            if process.exit_status.is_none() {
-                if let Some(sender) = process.terminate_sender.take() {
-                    sender.send(()).ok();
-                }
-            }
+            process.terminate();
         }
     }
 
@@ -246,19 +434,25 @@ impl ProcessRegistry {
                 while let Ok(output) = process.receiver.try_recv() {
                     match output {
                         ProcessOutput::Exited(exit_status) => {
-                            process.exit_status = Some(
-                                exit_status
-                                    .map(|s| s.code().unwrap_or_default())
-                                    .unwrap_or(1),
-                            )
+                            process.exit_status = Some(exit_status.unwrap_or(1));
+                            process.output.finish();
+                        }
+                        ProcessOutput::Output(str) => process.output.push(&str),
+                        ProcessOutput::Error(str) => process.output.push(&str),
+                        ProcessOutput::Terminal {
+                            text,
+                            partial_line,
+                            screen,
+                        } => {
+                            process.output.push_terminal(&text, &partial_line);
+                            process.screen = Some(screen);
                         }
-                        ProcessOutput::Output(str) => process.output += &str,
-                        ProcessOutput::Error(str) => process.output += &str,
                     }
                     modified_terminal_states.push(AgentCommandStatus {
                         command_id: *id,
                         command: None,
-                        output: process.output.clone(),
+                        output: process.output.text().to_string(),
+                        screen: process.screen.clone(),
                         is_active: process.exit_status.is_none(),
                     });
                 }
@@ -267,9 +461,19 @@ impl ProcessRegistry {
         modified_terminal_states
     }
 
-    pub fn get_process(&self, id: usize) -> Option<(Option<i32>, &String)> {
+    pub fn get_process(&self, id: usize) -> Option<(Option<i32>, &str)> {
         let process = self.processes.get(&id)?;
-        Some((process.exit_status, &process.output))
+        Some((process.exit_status, process.output.text()))
+    }
+
+    /// Output of the command for the model truncated to the limit of characters
+    pub fn get_truncated_output(&self, id: usize, max_output: usize) -> Option<String> {
+        Some(self.processes.get(&id)?.output.truncated(max_output))
+    }
+
+    /// Emulated terminal screen of the command run in a pseudo-terminal
+    pub fn screen(&self, id: usize) -> Option<Box<vt100::Screen>> {
+        self.processes.get(&id)?.screen.clone()
     }
 
     pub fn processes(&self) -> impl Iterator<Item = (usize, Option<i32>, &String)> {
@@ -282,11 +486,7 @@ impl ProcessRegistry {
         let Some(process) = self.processes.get_mut(&id) else {
             return Ok(());
         };
-        if process.exit_status.is_none() {
-            if let Some(sender) = process.terminate_sender.take() {
-                sender.send(()).ok();
-            }
-        }
+        process.terminate();
         Ok(())
     }
 
@@ -298,3 +498,94 @@ impl ProcessRegistry {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::executor::HostExecutor;
+    use super::*;
+    use crate::tools::test_dir;
+
+    async fn wait_output(registry: &mut ProcessRegistry, id: usize) -> (i32, String) {
+        loop {
+            registry.poll();
+            if let Some((Some(exit_status), output)) = registry.get_process(id) {
+                return (exit_status, output.to_string());
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_persistent_shell() {
+        let dir = test_dir("shell");
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        let cwd = dir.to_str().unwrap();
+        let src = dir.join("src").to_str().unwrap().to_string();
+        let config = TerminalConfig {
+            persistent_shell: true,
+            ..Default::default()
+        };
+        let mut registry = ProcessRegistry::new(config, dir.join("output"), Box::new(HostExecutor));
+
+        let id = registry
+            .execute_command("cd src && export VALUE=42", cwd, None)
+            .await
+            .unwrap();
+        assert_eq!(wait_output(&mut registry, id).await, (0, String::new()));
+        let id = registry
+            .execute_command(
+                "echo $VALUE $(basename $PWD); printf tail; false",
+                cwd,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            wait_output(&mut registry, id).await,
+            (1, "42 src\ntail\n".to_string())
+        );
+
+        registry.reset_shell();
+        let id = registry
+            .execute_command("echo ${VALUE:-unset} $(basename $PWD)", cwd, Some(&src))
+            .await
+            .unwrap();
+        assert_eq!(
+            wait_output(&mut registry, id).await,
+            (0, "unset src\n".to_string())
+        );
+        registry.stop();
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_persistent_shell_invalid_utf8() {
+        let dir = test_dir("utf8");
+        let cwd = dir.to_str().unwrap();
+        let config = TerminalConfig {
+            persistent_shell: true,
+            ..Default::default()
+        };
+        let mut registry = ProcessRegistry::new(config, dir.join("output"), Box::new(HostExecutor));
+
+        let id = registry
+            .execute_command(r"printf 'a\xffb\n'", cwd, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            wait_output(&mut registry, id).await,
+            (0, "a\u{FFFD}b\n".to_string())
+        );
+        // the session is still alive after the invalid output
+        let id = registry
+            .execute_command("echo ok", cwd, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            wait_output(&mut registry, id).await,
+            (0, "ok\n".to_string())
+        );
+        registry.stop();
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
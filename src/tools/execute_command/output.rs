@@ -0,0 +1,273 @@
+// Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+//! Output of commands: the buffered output is bounded and the full output is written
+//! to the log file, the model gets the beginning and the end of large outputs
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::config::TerminalConfig;
+use crate::tools::workspace_to_string;
+
+/// Buffered output larger than this is trimmed in the middle
+const MAX_BUFFERED_OUTPUT: usize = 1024 * 1024;
+/// Variables with these parts in the name are considered secrets
+const SECRET_NAME_PARTS: &[&str] = &["KEY", "TOKEN", "SECRET", "PASSWORD", "PASSWD", "CREDENTIAL"];
+/// Shorter values aren't redacted, they would match too much of the output
+const MIN_SECRET_LEN: usize = 4;
+const REDACTED: &str = "[REDACTED]";
+
+/// Replaces values of secret environment variables of commands
+#[derive(Default)]
+pub(super) struct Redactor {
+    secrets: Vec<String>,
+}
+
+impl Redactor {
+    pub fn new(config: &TerminalConfig) -> Self {
+        let is_secret_name = |name: &str| {
+            let name = name.to_uppercase();
+            SECRET_NAME_PARTS.iter().any(|part| name.contains(part))
+        };
+        let mut secrets = config
+            .env
+            .iter()
+            .filter(|(name, _)| is_secret_name(name))
+            .map(|(_, value)| value.clone())
+            .chain(config.secret_env.iter().filter_map(|name| {
+                config
+                    .env
+                    .get(name)
+                    .cloned()
+                    .or_else(|| std::env::var(name).ok())
+            }))
+            .filter(|value| value.len() >= MIN_SECRET_LEN)
+            .collect::<Vec<_>>();
+        // a secret containing another one is replaced completely
+        secrets.sort_by_key(|secret| Reverse(secret.len()));
+        secrets.dedup();
+        Self { secrets }
+    }
+
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for secret in &self.secrets {
+            if text.contains(secret.as_str()) {
+                text = Cow::Owned(text.replace(secret.as_str(), REDACTED));
+            }
+        }
+        text
+    }
+}
+
+fn ceil_char_boundary(text: &str, mut idx: usize) -> usize {
+    while !text.is_char_boundary(idx) {
+        idx += 1;
+    }
+    idx
+}
+
+fn floor_char_boundary(text: &str, mut idx: usize) -> usize {
+    while !text.is_char_boundary(idx) {
+        idx -= 1;
+    }
+    idx
+}
+
+/// Start of the first line beginning at or after the index
+fn next_line_start(text: &str, idx: usize) -> usize {
+    let idx = ceil_char_boundary(text, idx);
+    if idx == 0 || text[..idx].ends_with('\n') {
+        return idx;
+    }
+    text[idx..]
+        .find('\n')
+        .map(|pos| idx + pos + 1)
+        .unwrap_or(text.len())
+}
+
+pub(super) struct CommandOutput {
+    text: String,
+    /// Length of the incomplete last line of the terminal output, it's replaced by the next update
+    partial_line_len: usize,
+    /// Lines removed from the middle of the buffer
+    elided_lines: usize,
+    /// Completed lines of the whole output
+    total_lines: usize,
+    log_path: PathBuf,
+    log: Option<File>,
+    redactor: Arc<Redactor>,
+}
+
+impl CommandOutput {
+    pub fn new(log_path: PathBuf, redactor: Arc<Redactor>) -> Self {
+        let log = log_path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| File::create(&log_path))
+            .inspect_err(|e| tracing::warn!("Failed to create log {}: {}", log_path.display(), e))
+            .ok();
+        Self {
+            text: String::new(),
+            partial_line_len: 0,
+            elided_lines: 0,
+            total_lines: 0,
+            log_path,
+            log,
+            redactor,
+        }
+    }
+
+    /// Buffered output shown in the terminal
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn push(&mut self, text: &str) {
+        let text = self.redactor.redact(text);
+        if let Some(log) = self.log.as_mut() {
+            log.write_all(text.as_bytes()).ok();
+        }
+        self.total_lines += text.matches('\n').count();
+        self.text.push_str(&text);
+        self.trim();
+    }
+
+    /// Appends completed lines of the terminal output and replaces the incomplete last line
+    pub fn push_terminal(&mut self, text: &str, partial_line: &str) {
+        self.text.truncate(self.text.len() - self.partial_line_len);
+        self.push(text);
+        let partial_line = self.redactor.redact(partial_line);
+        self.text.push_str(&partial_line);
+        self.partial_line_len = partial_line.len();
+    }
+
+    /// Writes the incomplete last line of the terminal output to the log
+    pub fn finish(&mut self) {
+        if let Some(mut log) = self.log.take() {
+            let partial_line = &self.text[self.text.len() - self.partial_line_len..];
+            log.write_all(partial_line.as_bytes()).ok();
+        }
+    }
+
+    fn elided_marker(&self, lines: usize) -> String {
+        format!(
+            "... [{} lines elided, the full output is in {}, use read_file with start_line to page through it] ...\n",
+            lines,
+            workspace_to_string(&self.log_path)
+        )
+    }
+
+    /// Drops lines in the middle of the buffer, so it doesn't grow without bound
+    fn trim(&mut self) {
+        if self.text.len() <= MAX_BUFFERED_OUTPUT {
+            return;
+        }
+        // the beginning of the buffer never changes, so the previous marker is removed as well
+        let start = next_line_start(&self.text, MAX_BUFFERED_OUTPUT / 4);
+        let end = next_line_start(&self.text, self.text.len() - MAX_BUFFERED_OUTPUT / 2);
+        if end <= start {
+            return;
+        }
+        let removed = self.text[start..end].matches('\n').count();
+        let marker_lines = usize::from(self.elided_lines > 0);
+        self.elided_lines += removed.saturating_sub(marker_lines);
+        let marker = self.elided_marker(self.elided_lines);
+        self.text.replace_range(start..end, &marker);
+    }
+
+    /// Output for the model, the beginning and the end of the output fitting into the limit of characters
+    pub fn truncated(&self, max_output: usize) -> String {
+        let text = self.text.as_str();
+        if text.len() <= max_output {
+            return text.to_string();
+        }
+        let head_end = floor_char_boundary(text, max_output / 2);
+        let head_end = text[..head_end]
+            .rfind('\n')
+            .map(|pos| pos + 1)
+            .unwrap_or(head_end);
+        let tail_start = next_line_start(text, text.len() - max_output / 2).max(head_end);
+        let (head, tail) = (&text[..head_end], &text[tail_start..]);
+        let total_lines = self.total_lines + usize::from(!text.is_empty() && !text.ends_with('\n'));
+        let shown_lines = head.lines().count() + tail.lines().count();
+        let mut result = head.to_string();
+        if !result.is_empty() && !result.ends_with('\n') {
+            result.push('\n');
+        }
+        result.push_str(&self.elided_marker(total_lines.saturating_sub(shown_lines)));
+        result.push_str(tail);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::test_dir;
+
+    #[test]
+    fn test_command_output() {
+        let log_path = test_dir("output").join("command-1.log");
+        let mut output = CommandOutput::new(log_path.clone(), Arc::default());
+        for idx in 1..=10 {
+            output.push(&format!("line {idx}\n"));
+        }
+        assert_eq!(output.truncated(1000), output.text());
+        assert_eq!(
+            output.truncated(30),
+            format!(
+                "line 1\nline 2\n... [6 lines elided, the full output is in {}, use read_file with start_line to page through it] ...\nline 9\nline 10\n",
+                workspace_to_string(&log_path)
+            )
+        );
+
+        // progress line of the terminal is replaced
+        output.push_terminal("", "50%");
+        output.push_terminal("100%\n", "$ ");
+        output.finish();
+        assert!(output.text().ends_with("line 10\n100%\n$ "));
+        assert!(fs::read_to_string(&log_path)
+            .unwrap()
+            .ends_with("line 10\n100%\n$ "));
+
+        // the buffer is trimmed in the middle
+        let mut output =
+            CommandOutput::new(log_path.with_file_name("command-2.log"), Arc::default());
+        let line = format!("{}\n", "x".repeat(1023));
+        for _ in 0..2048 {
+            output.push(&line);
+        }
+        assert!(output.text().len() <= MAX_BUFFERED_OUTPUT);
+        assert!(output.text()[MAX_BUFFERED_OUTPUT / 4..].starts_with("... ["));
+        assert_eq!(
+            output.elided_lines + output.text().lines().count() - 1,
+            2048
+        );
+        fs::remove_dir_all(log_path.parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn test_redactor() {
+        let config = TerminalConfig {
+            env: [
+                ("OPENAI_API_KEY", "sk-secret-value"),
+                ("DATABASE_URL", "postgres://user:pass@db"),
+                ("RUST_LOG", "debug"),
+                ("SHORT_TOKEN", "abc"),
+            ]
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+            secret_env: vec!["DATABASE_URL".to_string()],
+            ..Default::default()
+        };
+        let redactor = Redactor::new(&config);
+        assert_eq!(
+            redactor.redact("key=sk-secret-value url=postgres://user:pass@db log=debug abc"),
+            "key=[REDACTED] url=[REDACTED] log=debug abc"
+        );
+    }
+}
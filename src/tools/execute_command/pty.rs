@@ -0,0 +1,248 @@
+// Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+//! Pseudo-terminal backend of commands: programs see a real terminal, the screen is emulated
+//! for the TUI and the output is converted to plain text for the model
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use anyhow::Result;
+use portable_pty::{native_pty_system, ChildKiller, CommandBuilder, MasterPty, PtySize};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::config::TerminalConfig;
+
+use super::ProcessOutput;
+
+/// Time to wait for the output remaining in the terminal after the process exited
+const DRAIN_TIMEOUT: Duration = Duration::from_millis(200);
+
+#[derive(Default, Clone, Copy, PartialEq)]
+enum EscapeState {
+    #[default]
+    None,
+    /// After ESC
+    Escape,
+    /// Control sequence `ESC [ ... final`
+    Csi,
+    /// String sequence (OSC, DCS etc.) terminated by BEL or `ESC \`
+    String,
+    StringEscape,
+    /// Sequence with one more character, e.g. charset selection `ESC ( B`
+    Argument,
+}
+
+/// Converts raw terminal output to plain text: escape sequences are dropped and
+/// carriage returns overwrite the current line like progress bars do
+#[derive(Default)]
+struct TextCleaner {
+    state: EscapeState,
+    /// Incomplete UTF-8 sequence at the end of the previous chunk
+    pending: Vec<u8>,
+    /// Current line is overwritten by the next character after a carriage return
+    carriage_return: bool,
+    line: String,
+}
+
+impl TextCleaner {
+    /// Feeds the chunk of output, returns completed lines
+    fn feed(&mut self, data: &[u8]) -> String {
+        self.pending.extend_from_slice(data);
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(text) => text.len(),
+            // incomplete sequence at the end is kept for the next chunk
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => self.pending.len(),
+        };
+        let bytes = self.pending.drain(..valid).collect::<Vec<_>>();
+        let mut completed = String::new();
+        for c in String::from_utf8_lossy(&bytes).chars() {
+            self.state = match (self.state, c) {
+                (EscapeState::None, '\x1b') => EscapeState::Escape,
+                (EscapeState::None, _) => {
+                    self.push_char(c, &mut completed);
+                    EscapeState::None
+                }
+                (EscapeState::Escape, '[') => EscapeState::Csi,
+                (EscapeState::Escape, ']' | 'P' | 'X' | '^' | '_') => EscapeState::String,
+                (EscapeState::Escape, '(' | ')' | '*' | '+' | '#' | '%') => EscapeState::Argument,
+                (EscapeState::Csi, '@'..='~') => EscapeState::None,
+                (EscapeState::Csi, _) => EscapeState::Csi,
+                (EscapeState::String, '\x07') => EscapeState::None,
+                (EscapeState::String, '\x1b') => EscapeState::StringEscape,
+                (EscapeState::StringEscape, '\\') => EscapeState::None,
+                (EscapeState::String | EscapeState::StringEscape, _) => EscapeState::String,
+                (EscapeState::Escape | EscapeState::Argument, _) => EscapeState::None,
+            };
+        }
+        completed
+    }
+
+    fn push_char(&mut self, c: char, completed: &mut String) {
+        match c {
+            '\n' => {
+                completed.push_str(&self.line);
+                completed.push('\n');
+                self.line.clear();
+                self.carriage_return = false;
+            }
+            '\r' => self.carriage_return = true,
+            '\x08' => {
+                self.line.pop();
+            }
+            c if c != '\t' && c.is_control() => {}
+            c => {
+                if self.carriage_return {
+                    self.line.clear();
+                    self.carriage_return = false;
+                }
+                self.line.push(c);
+            }
+        }
+    }
+
+    /// Current incomplete line, e.g. a prompt waiting for the input
+    fn partial_line(&self) -> &str {
+        &self.line
+    }
+}
+
+pub(super) struct PtyRuntime {
+    master: Box<dyn MasterPty + Send>,
+    killer: Box<dyn ChildKiller + Send + Sync>,
+    output: mpsc::UnboundedReceiver<Vec<u8>>,
+    exit: oneshot::Receiver<i32>,
+    parser: vt100::Parser,
+    cleaner: TextCleaner,
+    sender: mpsc::UnboundedSender<ProcessOutput>,
+    terminate_signal: oneshot::Receiver<()>,
+}
+
+impl PtyRuntime {
+    /// Spawns the program with arguments in a new pseudo-terminal, reading, writing and waiting
+    /// for the process are done in separate threads since the terminal IO is blocking
+    pub(super) fn spawn(
+        args: &[String],
+        cwd: &str,
+        config: &TerminalConfig,
+        sender: mpsc::UnboundedSender<ProcessOutput>,
+        mut input_signal: mpsc::UnboundedReceiver<Vec<u8>>,
+        terminate_signal: oneshot::Receiver<()>,
+    ) -> Result<Self> {
+        let pair = native_pty_system().openpty(PtySize {
+            rows: config.rows,
+            cols: config.cols,
+            pixel_width: 0,
+            pixel_height: 0,
+        })?;
+        let mut cmd = CommandBuilder::from_argv(args.iter().map(Into::into).collect());
+        cmd.cwd(cwd);
+        cmd.env("TERM", "xterm-256color");
+        for (name, value) in &config.env {
+            cmd.env(name, value);
+        }
+        let mut child = pair.slave.spawn_command(cmd)?;
+        drop(pair.slave);
+        let killer = child.clone_killer();
+        let mut reader = pair.master.try_clone_reader()?;
+        let mut writer = pair.master.take_writer()?;
+
+        let (output_sender, output) = mpsc::unbounded_channel();
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            loop {
+                match reader.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        if output_sender.send(buf[..n].to_vec()).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+        std::thread::spawn(move || {
+            while let Some(data) = input_signal.blocking_recv() {
+                tracing::trace!("Writing data to terminal: {:?}", data);
+                if let Err(e) = writer.write_all(&data).and_then(|_| writer.flush()) {
+                    tracing::error!(error = ?e, "Error writing data to terminal");
+                    break;
+                }
+            }
+        });
+        let (exit_sender, exit) = oneshot::channel();
+        std::thread::spawn(move || {
+            let code = child
+                .wait()
+                .map(|status| status.exit_code() as i32)
+                .unwrap_or(1);
+            exit_sender.send(code).ok();
+        });
+
+        Ok(Self {
+            master: pair.master,
+            killer,
+            output,
+            exit,
+            parser: vt100::Parser::new(config.rows, config.cols, 0),
+            cleaner: TextCleaner::default(),
+            sender,
+            terminate_signal,
+        })
+    }
+
+    fn process_output(&mut self, data: &[u8]) {
+        self.parser.process(data);
+        let text = self.cleaner.feed(data);
+        self.sender
+            .send(ProcessOutput::Terminal {
+                text,
+                partial_line: self.cleaner.partial_line().to_string(),
+                screen: Box::new(self.parser.screen().clone()),
+            })
+            .ok();
+    }
+
+    pub(super) async fn run(mut self) {
+        let exit_code = loop {
+            tokio::select! {
+                Some(data) = self.output.recv() => self.process_output(&data),
+                code = &mut self.exit => break code.ok(),
+                _ = &mut self.terminate_signal => {
+                    tracing::debug!("Receive terminal_signal");
+                    self.killer.kill().ok();
+                    break (&mut self.exit).await.ok();
+                }
+            }
+        };
+        // background processes may keep the terminal open, so the rest of output is awaited shortly
+        while let Ok(Some(data)) = tokio::time::timeout(DRAIN_TIMEOUT, self.output.recv()).await {
+            self.process_output(&data);
+        }
+        drop(self.master);
+        self.sender.send(ProcessOutput::Exited(exit_code)).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_cleaner() {
+        let mut cleaner = TextCleaner::default();
+        assert_eq!(
+            cleaner.feed(b"\x1b[1;32mCompiling\x1b[0m crate\r\n"),
+            "Compiling crate\n"
+        );
+        // progress bar redrawn with carriage returns
+        assert_eq!(
+            cleaner.feed(b"[#   ] 25%\r[### ] 75%\r[####] 100%\r\n"),
+            "[####] 100%\n"
+        );
+        // window title and partial line without a newline
+        assert_eq!(cleaner.feed(b"\x1b]0;title\x07Continue? [y/N] "), "");
+        assert_eq!(cleaner.partial_line(), "Continue? [y/N] ");
+        // UTF-8 character split between chunks
+        assert_eq!(cleaner.feed(b"y\r\n\xe2\x9c"), "Continue? [y/N] y\n");
+        assert_eq!(cleaner.feed(b"\x93 done\x08\x08\x08\x08ok\n"), "✓ ok\n");
+    }
+}
@@ -0,0 +1,247 @@
+// Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+//! Persistent shell session: commands are written to the stdin of a long-lived shell, so the
+//! working directory, environment variables and shell functions are kept between commands.
+//! The end of a command is detected by a sentinel line printed with its exit code.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use anyhow::Result;
+use process_wrap::tokio::TokioChildWrapper;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{ChildStderr, ChildStdin, ChildStdout};
+use tokio::sync::{mpsc, oneshot};
+
+use super::ProcessOutput;
+
+/// Command of the session with channels of the registered process
+pub(super) struct SessionCommand {
+    pub command: String,
+    /// Directory the session changes to before the command
+    pub cwd: Option<String>,
+    pub sender: mpsc::UnboundedSender<ProcessOutput>,
+    pub input_signal: mpsc::UnboundedReceiver<Vec<u8>>,
+    pub terminate_signal: oneshot::Receiver<()>,
+}
+
+pub(super) struct ShellSession {
+    commands: mpsc::UnboundedSender<SessionCommand>,
+    busy: Arc<AtomicBool>,
+    /// Command removing the sandbox of the shell
+    cleanup: Option<Vec<String>>,
+}
+
+struct SessionRuntime {
+    process: Box<dyn TokioChildWrapper>,
+    stdout: BufReader<ChildStdout>,
+    stdin: ChildStdin,
+    _stderr: ChildStderr,
+    commands: mpsc::UnboundedReceiver<SessionCommand>,
+    busy: Arc<AtomicBool>,
+    counter: usize,
+}
+
+/// Quotes the text as a single shell word
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// Sentinel line printed after the command, unique per session and command
+fn sentinel(counter: usize) -> String {
+    format!("__HULY_CODER_DONE_{}_{}__", std::process::id(), counter)
+}
+
+/// Script running the command in the session and printing the sentinel with the exit code.
+/// It's a single line, so the shell reads the whole script before running the command
+/// and the command can't consume the sentinel from stdin.
+fn command_script(command: &str, cwd: Option<&str>, sentinel: &str) -> String {
+    let cd = cwd
+        .map(|cwd| format!("cd {} && ", shell_quote(cwd)))
+        .unwrap_or_default();
+    format!(
+        "{}eval {}; printf '%s %d\\n' {} $?\n",
+        cd,
+        shell_quote(command),
+        sentinel,
+    )
+}
+
+/// Splits the output line at the sentinel, returns the output before it and the exit code
+fn parse_sentinel<'a>(line: &'a str, sentinel: &str) -> Option<(&'a str, Option<i32>)> {
+    let pos = line.find(sentinel)?;
+    let exit_code = line[pos + sentinel.len()..].trim().parse().ok();
+    Some((&line[..pos], exit_code))
+}
+
+impl ShellSession {
+    /// Starts the session in the spawned shell reading commands from stdin
+    pub(super) fn start(
+        process: Box<dyn TokioChildWrapper>,
+        stdout: ChildStdout,
+        stderr: ChildStderr,
+        stdin: ChildStdin,
+        cleanup: Option<Vec<String>>,
+    ) -> Self {
+        let (commands, receiver) = mpsc::unbounded_channel();
+        let busy = Arc::new(AtomicBool::new(false));
+        let runtime = SessionRuntime {
+            process,
+            stdout: BufReader::new(stdout),
+            stdin,
+            _stderr: stderr,
+            commands: receiver,
+            busy: busy.clone(),
+            counter: 0,
+        };
+        tokio::spawn(runtime.run());
+        Self {
+            commands,
+            busy,
+            cleanup,
+        }
+    }
+
+    pub(super) fn cleanup(&self) -> Option<&Vec<String>> {
+        self.cleanup.as_ref()
+    }
+
+    /// The shell is running, it exits when a command terminates it or the session is reset
+    pub(super) fn is_alive(&self) -> bool {
+        !self.commands.is_closed()
+    }
+
+    /// A command is running in the session
+    pub(super) fn is_busy(&self) -> bool {
+        self.busy.load(Ordering::SeqCst)
+    }
+
+    pub(super) fn execute(&self, command: SessionCommand) -> Result<()> {
+        self.busy.store(true, Ordering::SeqCst);
+        self.commands
+            .send(command)
+            .map_err(|_| anyhow::anyhow!("Shell session is closed"))
+    }
+}
+
+impl SessionRuntime {
+    async fn run(mut self) {
+        // stderr is merged into stdout to keep the order of the output
+        if self.stdin.write_all(b"exec 2>&1\n").await.is_ok() {
+            while let Some(command) = self.commands.recv().await {
+                if !self.run_command(command).await {
+                    break;
+                }
+            }
+        }
+        tracing::debug!("Shell session is closed");
+        self.process.start_kill().ok();
+        // commands sent while the shell was exiting are never run
+        self.commands.close();
+        while let Ok(command) = self.commands.try_recv() {
+            self.finish(&command, None);
+        }
+    }
+
+    /// Runs the command until the sentinel, returns false if the shell exited
+    async fn run_command(&mut self, mut command: SessionCommand) -> bool {
+        self.counter += 1;
+        let sentinel = sentinel(self.counter);
+        let script = command_script(&command.command, command.cwd.as_deref(), &sentinel);
+        if let Err(e) = self.write(script.as_bytes()).await {
+            tracing::error!(error = ?e, "Error writing command to shell session");
+            self.finish(&command, None);
+            return false;
+        }
+        // the output may be not valid UTF-8, so lines are read as bytes. The partial line
+        // is kept in the buffer when another branch completes first.
+        let mut buf = Vec::new();
+        loop {
+            tokio::select! {
+                read = self.stdout.read_until(b'\n', &mut buf) => match read {
+                    Ok(n) if n > 0 => {
+                        let text = String::from_utf8_lossy(&buf).into_owned();
+                        buf.clear();
+                        let line = text.strip_suffix('\n').unwrap_or(&text);
+                        if let Some((output, exit_code)) = parse_sentinel(line, &sentinel) {
+                            if !output.is_empty() {
+                                command.sender.send(ProcessOutput::Output(format!("{output}\n"))).ok();
+                            }
+                            self.finish(&command, exit_code);
+                            return true;
+                        }
+                        command.sender.send(ProcessOutput::Output(format!("{line}\n"))).ok();
+                    }
+                    // the command exited the shell
+                    Ok(_) => {
+                        let status = Box::into_pin(self.process.wait()).await.ok();
+                        tracing::debug!("Shell session exited with status: {:?}", status);
+                        self.finish(&command, status.and_then(|s| s.code()));
+                        return false;
+                    }
+                    Err(e) => {
+                        tracing::error!(error = ?e, "Error reading shell session output");
+                        self.process.start_kill().ok();
+                        let status = Box::into_pin(self.process.wait()).await.ok();
+                        self.finish(&command, status.and_then(|s| s.code()));
+                        return false;
+                    }
+                },
+                Some(data) = command.input_signal.recv() => {
+                    tracing::trace!("Writing data to shell session: {:?}", data);
+                    if let Err(e) = self.write(&data).await {
+                        tracing::error!(error = ?e, "Error writing data to shell session");
+                    }
+                }
+                _ = &mut command.terminate_signal => {
+                    tracing::debug!("Receive terminal_signal");
+                    let mut exit_code = None;
+                    if self.process.start_kill().is_ok() {
+                        if let Ok(status) = Box::into_pin(self.process.wait()).await {
+                            exit_code = status.code();
+                        }
+                    }
+                    self.finish(&command, exit_code);
+                    return false;
+                }
+            }
+        }
+    }
+
+    /// Reports the exit code, the session accepts the next command after it
+    fn finish(&self, command: &SessionCommand, exit_code: Option<i32>) {
+        self.busy.store(false, Ordering::SeqCst);
+        command.sender.send(ProcessOutput::Exited(exit_code)).ok();
+    }
+
+    async fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.stdin.write_all(data).await?;
+        self.stdin.flush().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_script() {
+        let sentinel = sentinel(1);
+        assert_eq!(
+            command_script("echo 'a b'", None, &sentinel),
+            format!("eval 'echo '\\''a b'\\'''; printf '%s %d\\n' {sentinel} $?\n")
+        );
+        assert_eq!(
+            command_script("ls", Some("/work/src"), &sentinel),
+            format!("cd '/work/src' && eval 'ls'; printf '%s %d\\n' {sentinel} $?\n")
+        );
+        assert_eq!(
+            parse_sentinel(&format!("{sentinel} 2"), &sentinel),
+            Some(("", Some(2)))
+        );
+        // the last line of the output without a newline
+        assert_eq!(
+            parse_sentinel(&format!("Continue? {sentinel} 0"), &sentinel),
+            Some(("Continue? ", Some(0)))
+        );
+        assert_eq!(parse_sentinel("output", &sentinel), None);
+    }
+}
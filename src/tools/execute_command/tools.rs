@@ -1,6 +1,7 @@
 // Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use indoc::formatdoc;
 use rig::completion::ToolDefinition;
@@ -11,15 +12,27 @@ use tokio::sync::{mpsc, RwLock};
 
 use crate::agent::event::AgentCommandStatus;
 use crate::agent::AgentOutputEvent;
-use crate::tools::{workspace_to_string, AgentToolError};
+use crate::tools::{resolve_write_path, workspace_to_string, AgentToolError};
 
 use super::ProcessRegistry;
 
-const COMMAND_TIMEOUT: u64 = 300; // 30 secs
+/// Interval of polling the command output while waiting for the command
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Upper bound of the time to wait for the command, longer commands continue in the background
+const MAX_TIMEOUT_SECS: u64 = 600;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExecuteCommandToolArgs {
     pub command: String,
+    /// Directory to run the command in, relative to the workspace
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// Seconds to wait for the command to complete
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// Maximal number of characters of the output returned
+    #[serde(default)]
+    pub max_output: Option<usize>,
 }
 
 pub struct ExecuteCommandTool {
@@ -31,6 +44,8 @@ pub struct ExecuteCommandTool {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GetCommandResultToolArgs {
     pub command_id: usize,
+    #[serde(default)]
+    pub max_output: Option<usize>,
 }
 
 pub struct GetCommandResultTool {
@@ -46,6 +61,13 @@ pub struct TerminateCommandTool {
     process_registry: Arc<RwLock<ProcessRegistry>>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResetShellToolArgs {}
+
+pub struct ResetShellTool {
+    process_registry: Arc<RwLock<ProcessRegistry>>,
+}
+
 impl ExecuteCommandTool {
     pub fn new(
         workspace: PathBuf,
@@ -72,6 +94,12 @@ impl TerminateCommandTool {
     }
 }
 
+impl ResetShellTool {
+    pub fn new(process_registry: Arc<RwLock<ProcessRegistry>>) -> Self {
+        Self { process_registry }
+    }
+}
+
 impl Tool for ExecuteCommandTool {
     const NAME: &'static str = "execute_command";
 
@@ -80,6 +108,22 @@ impl Tool for ExecuteCommandTool {
     type Output = String;
 
     async fn definition(&self, _prompt: String) -> ToolDefinition {
+        let (timeout_secs, max_output, persistent_shell) = {
+            let process_registry = self.process_registry.read().await;
+            let config = process_registry.config();
+            (
+                config.timeout_secs,
+                config.max_output,
+                config.persistent_shell,
+            )
+        };
+        let session = if persistent_shell {
+            "Commands are run one at a time in a persistent shell session, so the working directory, \
+             environment variables and shell functions are kept between commands, \
+             use reset_shell to start a fresh session.\n"
+        } else {
+            ""
+        };
         ToolDefinition {
             name: self.name(),
             description: formatdoc! {"\
@@ -91,7 +135,11 @@ impl Tool for ExecuteCommandTool {
                 Returns the command ID, exit status, and command output upon completion.\
                 For running commands, returns the ID, partial output, and a \"Command is run\" indicator.\
                 If the command is still running, it will return the ID and the output of the last command.\
-                Commands will be executed in the current working directory: {workspace_dir}",
+                Large outputs are truncated to the beginning and the end, the full output is saved to a file \
+                that can be read with read_file.\
+                {session}\
+                Commands will be executed in the current working directory: {workspace_dir} \
+                or in the directory set by `cwd`",
                 workspace_dir = workspace_to_string(&self.workspace)}.to_string(),
             parameters: json!({
                 "type": "object",
@@ -101,6 +149,20 @@ impl Tool for ExecuteCommandTool {
                         "description": "The CLI command to execute. This should be valid for the current operating system.\
                                         Ensure the command is properly formatted and does not contain any harmful instructions.",
                     },
+                    "cwd": {
+                        "type": "string",
+                        "description": "Directory to run the command in, relative to the workspace (default is the workspace), \
+                                        it must be inside the workspace",
+                    },
+                    "timeout_secs": {
+                        "type": "integer",
+                        "description": format!("Seconds to wait for the command to complete (default {}, max {}), \
+                                                the command continues in the background after the timeout", timeout_secs, MAX_TIMEOUT_SECS),
+                    },
+                    "max_output": {
+                        "type": "integer",
+                        "description": format!("Maximal number of characters of the output to return (default {})", max_output),
+                    },
                 },
                 "required": ["command"]
             })
@@ -109,46 +171,81 @@ impl Tool for ExecuteCommandTool {
     }
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        tracing::info!("Executing command '{}'", args.command);
+        let (timeout_secs, max_output) = {
+            let process_registry = self.process_registry.read().await;
+            tracing::info!(
+                "Executing command '{}'",
+                process_registry.redact(&args.command)
+            );
+            let config = process_registry.config();
+            (
+                args.timeout_secs
+                    .unwrap_or(config.timeout_secs)
+                    .min(MAX_TIMEOUT_SECS),
+                args.max_output.unwrap_or(config.max_output),
+            )
+        };
+        let cwd = match args.cwd.as_deref() {
+            Some(cwd) => {
+                let path = resolve_write_path(&self.workspace, cwd)?;
+                if !path.is_dir() {
+                    return Err(AgentToolError::Other(anyhow::anyhow!(
+                        "Directory '{}' doesn't exist",
+                        cwd
+                    )));
+                }
+                Some(workspace_to_string(&path))
+            }
+            None => None,
+        };
         let command_id = self
             .process_registry
             .write()
             .await
-            .execute_command(&args.command, &workspace_to_string(&self.workspace))
+            .execute_command(
+                &args.command,
+                &workspace_to_string(&self.workspace),
+                cwd.as_deref(),
+            )
             .await?;
-        let mut command_output = String::new();
-        for _ in 0..COMMAND_TIMEOUT {
+        let deadline = Instant::now() + Duration::from_secs(timeout_secs);
+        loop {
             self.process_registry.write().await.poll();
-            if let Some((exit_status, output)) =
-                self.process_registry.read().await.get_process(command_id)
-            {
+            let process_registry = self.process_registry.read().await;
+            if let Some((exit_status, output)) = process_registry.get_process(command_id) {
                 self.sender
                     .send(AgentOutputEvent::CommandStatus(vec![AgentCommandStatus {
                         command_id,
                         command: Some(args.command.clone()),
                         output: output.to_string(),
+                        screen: process_registry.screen(command_id),
                         is_active: exit_status.is_none(),
                     }]))
                     .ok();
+                let output = process_registry
+                    .get_truncated_output(command_id, max_output)
+                    .unwrap_or_default();
                 if let Some(exit_status) = exit_status {
                     return Ok(format!(
                         "Command ID: {}\nExit Status: Exited({})\nOutput:\n{}",
                         command_id, exit_status, output
                     ));
                 }
-                command_output = output.to_string();
+                if Instant::now() >= deadline {
+                    return Ok(format!(
+                        "Command ID: {}\nCommand is run, it's still running after {} seconds\nOutput:\n{}",
+                        command_id, timeout_secs, output
+                    ));
+                }
             } else {
                 return Err(AgentToolError::Other(anyhow::anyhow!(
                     "Command '{}' not found",
                     args.command
                 )));
             }
-            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            drop(process_registry);
+            tokio::time::sleep(POLL_INTERVAL).await;
         }
-        Ok(format!(
-            "Command ID: {}\nCommand is run\nOutput:\n{}",
-            command_id, command_output
-        ))
     }
 }
 
@@ -174,6 +271,10 @@ impl Tool for GetCommandResultTool {
                         "type": "number",
                         "description": "The identifier of the command returned by the `execute_command` tool",
                     },
+                    "max_output": {
+                        "type": "integer",
+                        "description": "Maximal number of characters of the output to return",
+                    },
                 },
                 "required": ["command_id"]
             })
@@ -183,12 +284,14 @@ impl Tool for GetCommandResultTool {
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
         tracing::info!("Get command result '{}'", args.command_id);
-        if let Some((exit_status, output)) = self
-            .process_registry
-            .read()
-            .await
-            .get_process(args.command_id)
-        {
+        let process_registry = self.process_registry.read().await;
+        let max_output = args
+            .max_output
+            .unwrap_or(process_registry.config().max_output);
+        if let (Some((exit_status, _)), Some(output)) = (
+            process_registry.get_process(args.command_id),
+            process_registry.get_truncated_output(args.command_id, max_output),
+        ) {
             if let Some(exit_status) = exit_status {
                 Ok(format!(
                     "Command ID: {}\nExit Status: Exited({})\nOutput:\n{}",
@@ -246,3 +349,34 @@ impl Tool for TerminateCommandTool {
         ))
     }
 }
+
+impl Tool for ResetShellTool {
+    const NAME: &'static str = "reset_shell";
+
+    type Error = AgentToolError;
+    type Args = ResetShellToolArgs;
+    type Output = String;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name(),
+            description: formatdoc! {"\
+                Terminates the persistent shell session used by `execute_command` including the running command. \
+                The next command starts a fresh session in the workspace directory without the changed \
+                working directory, environment variables and shell functions.\
+                ## Example usage:
+                When the shell session is broken, e.g. a command is stuck or the environment is misconfigured.\
+            "}.to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {},
+            })
+        }
+    }
+
+    async fn call(&self, _args: Self::Args) -> Result<Self::Output, Self::Error> {
+        tracing::info!("Reset shell session");
+        self.process_registry.write().await.reset_shell();
+        Ok("Shell session is reset, the next command starts a fresh session.".to_string())
+    }
+}
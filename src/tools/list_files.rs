@@ -9,7 +9,7 @@ use serde_json::json;
 
 use crate::tools::workspace_to_string;
 
-use super::{normalize_path, AgentToolError};
+use super::{resolve_read_path, AgentToolError};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ListFilesToolArgs {
@@ -19,11 +19,16 @@ pub struct ListFilesToolArgs {
 
 pub struct ListFilesTool {
     pub workspace: PathBuf,
+    /// Directories outside of the workspace allowed for reading
+    pub read_only_roots: Vec<PathBuf>,
 }
 
 impl ListFilesTool {
-    pub fn new(workspace: PathBuf) -> Self {
-        Self { workspace }
+    pub fn new(workspace: PathBuf, read_only_roots: Vec<PathBuf>) -> Self {
+        Self {
+            workspace,
+            read_only_roots,
+        }
     }
 }
 
@@ -62,7 +67,7 @@ impl Tool for ListFilesTool {
     }
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        let path = normalize_path(&self.workspace, &args.path);
+        let path = resolve_read_path(&self.workspace, &self.read_only_roots, &args.path)?;
         let max_depth = args.max_depth.unwrap_or(1);
         let mut files: Vec<String> = Vec::default();
         for entry in ignore::WalkBuilder::new(path.clone())
@@ -20,9 +20,14 @@ use crate::{
     tools::memory::{voyageai_embedding::VoyageAIEmbeddingModel, Entity, MemoryManager},
 };
 
+#[cfg(test)]
+use crate::tools::memory::stub_embedding::StubEmbeddingModel;
+
 pub enum MemoryEmbeddingModel {
     Fastembed(rig_fastembed::EmbeddingModel),
     VoyageAI(VoyageAIEmbeddingModel),
+    #[cfg(test)]
+    Stub(StubEmbeddingModel),
 }
 
 #[derive(Serialize, Deserialize, Default)]
@@ -35,6 +40,50 @@ fn to_texts(entity: &Entity) -> String {
 }
 
 impl MemoryEmbeddingModel {
+    pub fn new(provider: &EmbeddingProvider) -> Self {
+        match provider {
+            EmbeddingProvider::Fastembed => {
+                let client = rig_fastembed::Client::new();
+                let model = client.embedding_model(&rig_fastembed::FastembedModel::AllMiniLML6V2);
+                Self::Fastembed(model)
+            }
+            EmbeddingProvider::VoyageAi {
+                api_key,
+                model,
+                dimensions,
+            } => Self::VoyageAI(VoyageAIEmbeddingModel::new(
+                api_key.clone(),
+                model.clone(),
+                *dimensions,
+            )),
+        }
+    }
+
+    /// Model embedding hashed words, so tests work offline without downloading a model
+    #[cfg(test)]
+    pub fn stub() -> Self {
+        Self::Stub(StubEmbeddingModel)
+    }
+
+    /// Embeds the texts in batches, VoyageAI model embeds one text per request
+    pub async fn embed_texts(
+        &self,
+        texts: Vec<String>,
+    ) -> color_eyre::Result<Vec<embeddings::Embedding>> {
+        match self {
+            Self::Fastembed(model) => Ok(model.embed_texts(texts).await?),
+            Self::VoyageAI(model) => {
+                let mut embeddings = Vec::with_capacity(texts.len());
+                for text in texts {
+                    embeddings.push(model.embed_text(&text).await?);
+                }
+                Ok(embeddings)
+            }
+            #[cfg(test)]
+            Self::Stub(model) => Ok(model.embed_texts(texts).await?),
+        }
+    }
+
     pub async fn embeddings(
         &self,
         document: &Entity,
@@ -49,6 +98,11 @@ impl MemoryEmbeddingModel {
                 let embedding = model.embed_text(&txt).await?;
                 Ok(OneOrMany::one(embedding))
             }
+            #[cfg(test)]
+            Self::Stub(model) => {
+                let embedding = model.embed_text(&txt).await?;
+                Ok(OneOrMany::one(embedding))
+            }
         }
     }
 
@@ -73,6 +127,14 @@ impl MemoryEmbeddingModel {
                     .await?;
                 Ok(res.into_iter().map(|(_, _, entity)| entity).collect())
             }
+            #[cfg(test)]
+            Self::Stub(model) => {
+                let res: Vec<(f64, String, Entity)> = vector_store
+                    .index(model.clone())
+                    .top_n(query, limit)
+                    .await?;
+                Ok(res.into_iter().map(|(_, _, entity)| entity).collect())
+            }
         }
     }
 }
@@ -94,32 +156,23 @@ impl MemoryIndexer {
         }
     }
 
+    /// Uses the given model instead of the configured provider
+    #[cfg(test)]
+    pub fn with_embedding_model(mut self, model: MemoryEmbeddingModel) -> Self {
+        self.embedding_model = Some(model);
+        self
+    }
+
     pub async fn init(&mut self, memory: Arc<RwLock<MemoryManager>>) -> color_eyre::Result<()> {
         let embedding_storage = if self.embedding_storage_path.exists() {
             serde_json::from_str(&std::fs::read_to_string(&self.embedding_storage_path)?)?
         } else {
             MemoryVectorStorage::default()
         };
-        match &self.embedding_provider {
-            EmbeddingProvider::Fastembed => {
-                let client = rig_fastembed::Client::new();
-                let model = client.embedding_model(&rig_fastembed::FastembedModel::AllMiniLML6V2);
-                self.embedding_model = Some(MemoryEmbeddingModel::Fastembed(model));
-            }
-            EmbeddingProvider::VoyageAi {
-                api_key,
-                model,
-                dimensions,
-            } => {
-                let model =
-                    VoyageAIEmbeddingModel::new(api_key.clone(), model.clone(), *dimensions);
-                self.embedding_model = Some(MemoryEmbeddingModel::VoyageAI(model));
-            }
-        }
+        let model = self
+            .embedding_model
+            .get_or_insert_with(|| MemoryEmbeddingModel::new(&self.embedding_provider));
         let documents = memory.read().await.entities().clone();
-        let Some(model) = self.embedding_model.as_ref() else {
-            return Ok(());
-        };
 
         for document in documents.iter() {
             if let Some((_, emb)) = embedding_storage
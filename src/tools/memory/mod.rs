@@ -20,6 +20,8 @@ use crate::tools::memory::indexer::MemoryIndexer;
 use super::AgentToolError;
 
 pub mod indexer;
+#[cfg(test)]
+mod stub_embedding;
 mod voyageai_embedding;
 
 const TOOLS_STR: &str = include_str!("tools.json");
@@ -0,0 +1,37 @@
+// Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use rig::embeddings::{self, embedding, Embedding, EmbeddingError};
+
+const STUB_DIMENSIONS: usize = 64;
+
+/// Embeds texts as counts of hashed words, so tests don't download a model
+#[derive(Debug, Clone, Default)]
+pub struct StubEmbeddingModel;
+
+impl embedding::EmbeddingModel for StubEmbeddingModel {
+    const MAX_DOCUMENTS: usize = 1024;
+
+    fn ndims(&self) -> usize {
+        STUB_DIMENSIONS
+    }
+
+    async fn embed_texts(
+        &self,
+        documents: impl IntoIterator<Item = String>,
+    ) -> Result<Vec<embeddings::Embedding>, EmbeddingError> {
+        Ok(documents
+            .into_iter()
+            .map(|document| {
+                let mut vec = vec![0.0; STUB_DIMENSIONS];
+                for word in document.split_whitespace() {
+                    let mut hasher = DefaultHasher::new();
+                    word.to_lowercase().hash(&mut hasher);
+                    vec[hasher.finish() as usize % STUB_DIMENSIONS] += 1.0;
+                }
+                Embedding { document, vec }
+            })
+            .collect())
+    }
+}
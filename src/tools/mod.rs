@@ -1,14 +1,21 @@
 // Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
-use std::path::Path;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Component, Path, PathBuf};
 
 use thiserror::Error;
 
+pub mod apply_patch;
 pub mod ask_followup_question;
 pub mod attempt_completion;
+pub mod code_nav;
+pub mod codebase_search;
+pub mod document;
 pub mod execute_command;
 pub mod list_files;
 pub mod memory;
 pub mod read_file;
+pub mod read_image;
 pub mod replace_in_file;
 pub mod search_files;
 pub mod web_fetch;
@@ -30,7 +37,37 @@ pub fn create_patch(original: &str, modified: &str) -> String {
         .to_string()
         .lines()
         .skip(2)
-        .collect::<String>()
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Writes the file via a temporary file in the same directory renamed over the target,
+/// so the file is never left partially written
+pub fn write_atomic(path: &Path, content: &str) -> std::io::Result<()> {
+    let (Some(dir), Some(file_name)) = (path.parent(), path.file_name()) else {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("Invalid file path: {}", path.display()),
+        ));
+    };
+    let tmp_path = dir.join(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+    let result = (|| {
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        if let Ok(metadata) = fs::metadata(path) {
+            fs::set_permissions(&tmp_path, metadata.permissions())?;
+        }
+        fs::rename(&tmp_path, path)
+    })();
+    if result.is_err() {
+        fs::remove_file(&tmp_path).ok();
+    }
+    result
 }
 
 #[inline]
@@ -38,12 +75,154 @@ pub fn workspace_to_string(workspace: &Path) -> String {
     workspace.to_str().unwrap().to_string().replace("\\", "/")
 }
 
-pub fn normalize_path(workspace: &Path, path: &str) -> String {
-    let path = path.to_string().replace("\\", "/");
-    let workspace = workspace_to_string(workspace);
-    if !path.starts_with(&workspace) {
-        format!("{}/{}", workspace, path)
+/// Resolves path of a file the agent is going to modify, the path must be inside the workspace
+pub fn resolve_write_path(workspace: &Path, path: &str) -> std::io::Result<PathBuf> {
+    resolve_path(workspace, &[], path)
+}
+
+/// Resolves path of a file the agent is going to read, the path must be inside the workspace
+/// or one of the read-only roots
+pub fn resolve_read_path(
+    workspace: &Path,
+    read_only_roots: &[PathBuf],
+    path: &str,
+) -> std::io::Result<PathBuf> {
+    resolve_path(workspace, read_only_roots, path)
+}
+
+fn resolve_path(
+    workspace: &Path,
+    allowed_roots: &[PathBuf],
+    path: &str,
+) -> std::io::Result<PathBuf> {
+    let workspace = canonicalize_path(workspace)?;
+    let requested = Path::new(&path.replace("\\", "/")).to_path_buf();
+    let full_path = if requested.is_absolute() {
+        requested
     } else {
-        path
+        workspace.join(requested)
+    };
+    let resolved = canonicalize_path(&full_path)?;
+    let allowed = resolved.starts_with(&workspace)
+        || allowed_roots.iter().any(|root| {
+            canonicalize_path(&workspace.join(root)).is_ok_and(|root| resolved.starts_with(root))
+        });
+    if allowed {
+        Ok(resolved)
+    } else {
+        Err(std::io::Error::new(
+            ErrorKind::PermissionDenied,
+            format!(
+                "Access denied: path '{}' is outside of the workspace {}",
+                path,
+                workspace_to_string(&workspace)
+            ),
+        ))
+    }
+}
+
+/// Removes `.` and `..` components lexically, so a missing directory followed by `..`
+/// can't hide a symlink behind it
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                result.pop();
+            }
+            Component::CurDir => {}
+            component => result.push(component),
+        }
+    }
+    result
+}
+
+/// Canonicalizes path resolving all symlinks, path may not exist yet (e.g. a new file),
+/// in this case the longest existing ancestor is canonicalized
+fn canonicalize_path(path: &Path) -> std::io::Result<PathBuf> {
+    let path = normalize_path(&std::path::absolute(path)?);
+    let mut existing = path.as_path();
+    let mut missing = Vec::new();
+    // `symlink_metadata` is used so dangling symlinks are not treated as missing files
+    while existing.symlink_metadata().is_err() {
+        let (Some(parent), Some(name)) = (existing.parent(), existing.file_name()) else {
+            break;
+        };
+        missing.push(name);
+        existing = parent;
+    }
+    let mut result = existing.canonicalize()?;
+    for name in missing.iter().rev() {
+        result.push(name);
+    }
+    Ok(result)
+}
+
+/// Empty directory of the test in the system temp directory, unique for the test process
+#[cfg(test)]
+pub(crate) fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("huly-coder-{}-{}", name, std::process::id()));
+    if dir.exists() {
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_path() {
+        let dir = test_dir("sandbox");
+        let workspace = dir.join("workspace");
+        let docs = dir.join("docs");
+        fs::create_dir_all(workspace.join("src")).unwrap();
+        fs::create_dir_all(&docs).unwrap();
+        fs::write(docs.join("guide.md"), "guide").unwrap();
+        let workspace_canonical = workspace.canonicalize().unwrap();
+
+        assert_eq!(
+            resolve_write_path(&workspace, "src/main.rs").unwrap(),
+            workspace_canonical.join("src/main.rs")
+        );
+        assert_eq!(
+            resolve_write_path(&workspace, "new/dir/../file.rs").unwrap(),
+            workspace_canonical.join("new/file.rs")
+        );
+        assert!(resolve_write_path(&workspace, "../docs/guide.md").is_err());
+        assert!(resolve_write_path(&workspace, "src/../../docs/guide.md").is_err());
+        assert!(resolve_write_path(&workspace, "/etc/passwd").is_err());
+        assert!(resolve_write_path(&workspace, "missing/../../../etc/passwd").is_err());
+
+        let read_only_roots = vec![docs.clone()];
+        assert!(resolve_read_path(&workspace, &read_only_roots, "../docs/guide.md").is_ok());
+        assert!(resolve_read_path(&workspace, &read_only_roots, "../guide.md").is_err());
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&docs, workspace.join("docs_link")).unwrap();
+            std::os::unix::fs::symlink(dir.join("missing"), workspace.join("dangling")).unwrap();
+            assert!(resolve_write_path(&workspace, "docs_link/guide.md").is_err());
+            assert!(resolve_write_path(&workspace, "dangling").is_err());
+            // the missing directory followed by `..` doesn't hide the symlink
+            assert!(resolve_write_path(&workspace, "x/../docs_link/pwn.txt").is_err());
+            assert!(resolve_write_path(&workspace, "x/y/../../docs_link/new/pwn.txt").is_err());
+            assert!(resolve_read_path(&workspace, &read_only_roots, "docs_link/guide.md").is_ok());
+        }
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = test_dir("atomic");
+        let path = dir.join("file.txt");
+        write_atomic(&path, "v1").unwrap();
+        write_atomic(&path, "v2").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "v2");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        assert!(write_atomic(&dir.join("missing/file.txt"), "v1").is_err());
+        fs::remove_dir_all(dir).ok();
     }
 }
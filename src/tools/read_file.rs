@@ -1,6 +1,7 @@
 // Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
 use std::fs;
-use std::path::PathBuf;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
 
 use indoc::formatdoc;
 use rig::completion::ToolDefinition;
@@ -8,22 +9,42 @@ use rig::tool::Tool;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-use crate::tools::{normalize_path, workspace_to_string};
+use crate::tools::{document, resolve_read_path, workspace_to_string};
 
 use super::AgentToolError;
 
+/// Maximal number of lines returned by one call
+const MAX_LINES: usize = 2000;
+/// Maximal size of the returned content in bytes
+const MAX_OUTPUT_SIZE: usize = 100 * 1024;
+/// Longer lines are cut, e.g. minified files
+const MAX_LINE_LENGTH: usize = 2000;
+/// Size of the file prefix checked for binary content
+const BINARY_CHECK_SIZE: usize = 8 * 1024;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReadFileToolArgs {
     pub path: String,
+    /// 1-based line to start reading from
+    #[serde(default)]
+    pub start_line: Option<usize>,
+    /// 1-based inclusive line to stop reading at
+    #[serde(default)]
+    pub end_line: Option<usize>,
 }
 
 pub struct ReadFileTool {
     pub workspace: PathBuf,
+    /// Directories outside of the workspace allowed for reading
+    pub read_only_roots: Vec<PathBuf>,
 }
 
 impl ReadFileTool {
-    pub fn new(workspace: PathBuf) -> Self {
-        Self { workspace }
+    pub fn new(workspace: PathBuf, read_only_roots: Vec<PathBuf>) -> Self {
+        Self {
+            workspace,
+            read_only_roots,
+        }
     }
 }
 
@@ -40,14 +61,25 @@ impl Tool for ReadFileTool {
             description: formatdoc! {"\
                 Request to read the contents of a file at the specified path. Use this when you need to examine the contents \
                 of an existing file you do not know the contents of, for example to analyze code, review text files, \
-                or extract information from configuration files. Automatically extracts raw text from PDF and DOCX files. \
-                May not be suitable for other types of binary files, as it returns the raw content as a string."}.to_string(),
+                or extract information from configuration files. Returns the content with line numbers in the format \
+                `line_number|line`, line numbers are not a part of the file content. At most {} lines are returned \
+                per call, use start_line and end_line to read large files in parts. Automatically extracts raw text \
+                from PDF, DOCX and ODT files, spreadsheets (XLSX, XLS, ODS) are returned as CSV. Other binary files \
+                can't be read.", MAX_LINES}.to_string(),
             parameters: json!({
                 "type": "object",
                 "properties": {
                     "path": {
                         "type": "string",
                         "description": format!("The path of the file to read (relative to the current working directory {})", workspace_to_string(&self.workspace))
+                    },
+                    "start_line": {
+                        "type": "integer",
+                        "description": "The 1-based line number to start reading from (optional, defaults to 1)"
+                    },
+                    "end_line": {
+                        "type": "integer",
+                        "description": "The 1-based line number to stop reading at, inclusive (optional, defaults to the end of file)"
                     }
                 },
                 "required": ["path"]
@@ -57,8 +89,144 @@ impl Tool for ReadFileTool {
     }
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        let path = normalize_path(&self.workspace, &args.path);
-        tracing::info!("Reading file {}", path);
-        Ok(fs::read_to_string(path)?)
+        let path = resolve_read_path(&self.workspace, &self.read_only_roots, &args.path)?;
+        tracing::info!("Reading file {}", path.display());
+        let content = match document::extract_text(&path) {
+            Some(content) => content?,
+            None => read_text(&path)?,
+        };
+        Ok(format_lines(&content, args.start_line, args.end_line))
+    }
+}
+
+/// Returns true if the data looks like a binary file, e.g. contains NUL bytes
+fn is_binary(data: &[u8]) -> bool {
+    data[..data.len().min(BINARY_CHECK_SIZE)].contains(&0)
+}
+
+fn decode_utf16(data: &[u8], from_bytes: fn([u8; 2]) -> u16) -> String {
+    let units = data
+        .chunks_exact(2)
+        .map(|chunk| from_bytes([chunk[0], chunk[1]]))
+        .collect::<Vec<_>>();
+    String::from_utf16_lossy(&units)
+}
+
+/// Reads the text file, invalid UTF-8 sequences are replaced with U+FFFD
+pub fn read_text(path: &Path) -> std::io::Result<String> {
+    let data = fs::read(path)?;
+    if let Some(data) = data.strip_prefix(&[0xFF, 0xFE]) {
+        return Ok(decode_utf16(data, u16::from_le_bytes));
+    }
+    if let Some(data) = data.strip_prefix(&[0xFE, 0xFF]) {
+        return Ok(decode_utf16(data, u16::from_be_bytes));
+    }
+    let data = data.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(&data[..]);
+    if is_binary(data) {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "File {} is a binary file ({} bytes) and can't be read as text",
+                path.display(),
+                data.len()
+            ),
+        ));
+    }
+    Ok(String::from_utf8_lossy(data).into_owned())
+}
+
+/// Formats the requested range of lines with line numbers,
+/// the output is limited by the number of lines and the size
+fn format_lines(content: &str, start_line: Option<usize>, end_line: Option<usize>) -> String {
+    let lines = content.lines().collect::<Vec<_>>();
+    let start = start_line.unwrap_or(1).max(1);
+    let end = end_line.unwrap_or(lines.len()).min(lines.len());
+    if lines.is_empty() {
+        return "The file is empty.".to_string();
+    }
+    if start > end {
+        return format!(
+            "The requested range {}-{} is outside of the file with {} lines.",
+            start,
+            end_line.unwrap_or(lines.len()),
+            lines.len()
+        );
+    }
+    let width = end.to_string().len();
+    let mut result = String::new();
+    let mut last_line = start - 1;
+    for (idx, line) in lines[start - 1..end].iter().enumerate() {
+        let line_number = start + idx;
+        let line = match line.char_indices().nth(MAX_LINE_LENGTH) {
+            Some((cut, _)) => format!("{}... (line truncated)", &line[..cut]),
+            None => line.to_string(),
+        };
+        let line = format!("{line_number:>width$}|{line}\n");
+        // the first line is always returned, so the reading can continue after it
+        if idx > 0 && (idx >= MAX_LINES || result.len() + line.len() > MAX_OUTPUT_SIZE) {
+            break;
+        }
+        result.push_str(&line);
+        last_line = line_number;
+    }
+    if last_line < end {
+        result.push_str(&format!(
+            "... truncated, {} more lines. Use start_line={} to continue reading.\n",
+            end - last_line,
+            last_line + 1
+        ));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::test_dir;
+
+    #[test]
+    fn test_format_lines() {
+        let content = (1..=12)
+            .map(|idx| format!("line {idx}\n"))
+            .collect::<String>();
+        assert_eq!(
+            format_lines(&content, Some(9), Some(11)),
+            " 9|line 9\n10|line 10\n11|line 11\n"
+        );
+        assert_eq!(format_lines(&content, Some(12), Some(20)), "12|line 12\n");
+        assert!(format_lines(&content, Some(13), None).starts_with("The requested range 13-12"));
+
+        let content = "line\n".repeat(MAX_LINES + 10);
+        let result = format_lines(&content, None, None);
+        assert_eq!(result.lines().count(), MAX_LINES + 1);
+        assert!(result.ends_with(&format!(
+            "... truncated, 10 more lines. Use start_line={} to continue reading.\n",
+            MAX_LINES + 1
+        )));
+
+        // the size limit is checked after long lines are truncated
+        let content = format!("{}\nnext\n", "a".repeat(200 * 1024));
+        let result = format_lines(&content, None, None);
+        assert!(result.starts_with(&format!(
+            "1|{}... (line truncated)\n",
+            "a".repeat(MAX_LINE_LENGTH)
+        )));
+        assert!(result.ends_with("2|next\n"));
+        let content = "a".repeat(200 * 1024);
+        assert_eq!(format_lines(&content, None, None).lines().count(), 1);
+    }
+
+    #[test]
+    fn test_read_text() {
+        let dir = test_dir("read");
+        let path = dir.join("file.txt");
+
+        fs::write(&path, b"caf\xe9\n").unwrap();
+        assert_eq!(read_text(&path).unwrap(), "caf\u{FFFD}\n");
+        fs::write(&path, b"\xFF\xFEh\0i\0").unwrap();
+        assert_eq!(read_text(&path).unwrap(), "hi");
+        fs::write(&path, b"\x7FELF\0\0\x01").unwrap();
+        assert_eq!(read_text(&path).unwrap_err().kind(), ErrorKind::InvalidData);
+        fs::remove_dir_all(dir).ok();
     }
 }
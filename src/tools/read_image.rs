@@ -0,0 +1,200 @@
+// Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+use std::fs;
+use std::io::{Cursor, ErrorKind};
+use std::path::PathBuf;
+
+use base64::Engine;
+use image::imageops::FilterType;
+use image::{GenericImageView, ImageFormat};
+use indoc::formatdoc;
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::tools::{resolve_read_path, workspace_to_string};
+
+/// Larger images are downscaled to fit into the square with this side
+const MAX_IMAGE_DIMENSION: u32 = 1568;
+/// Maximal size of the image file to read
+const MAX_IMAGE_FILE_SIZE: u64 = 20 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadImageToolArgs {
+    pub path: String,
+}
+
+/// Image passed to the model as an image content of the tool result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadImageToolOutput {
+    pub path: String,
+    /// MIME type of the encoded image
+    pub media_type: String,
+    /// Base64 encoded image
+    pub data: String,
+    pub width: u32,
+    pub height: u32,
+    /// Size of the original image if it was downscaled
+    pub original_size: Option<(u32, u32)>,
+}
+
+impl ReadImageToolOutput {
+    /// Text part of the tool result accompanying the image
+    pub fn description(&self) -> String {
+        match self.original_size {
+            Some((width, height)) => format!(
+                "Image {} ({}x{}, downscaled to {}x{})",
+                self.path, width, height, self.width, self.height
+            ),
+            None => format!("Image {} ({}x{})", self.path, self.width, self.height),
+        }
+    }
+}
+
+pub struct ReadImageTool {
+    pub workspace: PathBuf,
+    /// Directories outside of the workspace allowed for reading
+    pub read_only_roots: Vec<PathBuf>,
+}
+
+impl ReadImageTool {
+    pub fn new(workspace: PathBuf, read_only_roots: Vec<PathBuf>) -> Self {
+        Self {
+            workspace,
+            read_only_roots,
+        }
+    }
+}
+
+fn invalid_image(e: impl ToString) -> std::io::Error {
+    std::io::Error::new(ErrorKind::InvalidData, e.to_string())
+}
+
+impl Tool for ReadImageTool {
+    const NAME: &'static str = "read_image";
+
+    type Error = std::io::Error;
+    type Args = ReadImageToolArgs;
+    type Output = ReadImageToolOutput;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name(),
+            description: formatdoc! {"\
+                Request to view an image file (PNG, JPEG, GIF or WEBP) at the specified path, for example a screenshot, \
+                a design mockup or a diagram. Images larger than {}x{} pixels are downscaled.", MAX_IMAGE_DIMENSION, MAX_IMAGE_DIMENSION}.to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": format!("The path of the image to view (relative to the current working directory {})", workspace_to_string(&self.workspace))
+                    }
+                },
+                "required": ["path"]
+            })
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let path = resolve_read_path(&self.workspace, &self.read_only_roots, &args.path)?;
+        tracing::info!("Reading image {}", path.display());
+        let size = fs::metadata(&path)?.len();
+        if size > MAX_IMAGE_FILE_SIZE {
+            return Err(invalid_image(format!(
+                "Image {} is too large ({} bytes), the maximal size is {} bytes",
+                args.path, size, MAX_IMAGE_FILE_SIZE
+            )));
+        }
+        let data = fs::read(&path)?;
+        let format = image::guess_format(&data).map_err(invalid_image)?;
+        let media_type = match format {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Gif => "image/gif",
+            ImageFormat::WebP => "image/webp",
+            _ => {
+                return Err(invalid_image(format!(
+                    "Unsupported image format {format:?}, supported formats are PNG, JPEG, GIF and WEBP"
+                )))
+            }
+        };
+        let image = image::load_from_memory_with_format(&data, format).map_err(invalid_image)?;
+        let (width, height) = image.dimensions();
+        let engine = base64::engine::general_purpose::STANDARD;
+        if width.max(height) <= MAX_IMAGE_DIMENSION {
+            return Ok(ReadImageToolOutput {
+                path: args.path,
+                media_type: media_type.to_string(),
+                data: engine.encode(&data),
+                width,
+                height,
+                original_size: None,
+            });
+        }
+        let resized = image.resize(
+            MAX_IMAGE_DIMENSION,
+            MAX_IMAGE_DIMENSION,
+            FilterType::Lanczos3,
+        );
+        // photos stay in JPEG, other images are re-encoded as lossless PNG
+        let (output_format, media_type) = match format {
+            ImageFormat::Jpeg => (ImageFormat::Jpeg, "image/jpeg"),
+            _ => (ImageFormat::Png, "image/png"),
+        };
+        let mut buffer = Cursor::new(Vec::new());
+        resized
+            .write_to(&mut buffer, output_format)
+            .map_err(invalid_image)?;
+        Ok(ReadImageToolOutput {
+            path: args.path,
+            media_type: media_type.to_string(),
+            data: engine.encode(buffer.into_inner()),
+            width: resized.width(),
+            height: resized.height(),
+            original_size: Some((width, height)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, RgbaImage};
+
+    use super::*;
+    use crate::tools::test_dir;
+
+    #[tokio::test]
+    async fn test_read_image() {
+        let workspace = test_dir("image");
+        let tool = ReadImageTool::new(workspace.clone(), Vec::new());
+        let read = |path: &str| {
+            tool.call(ReadImageToolArgs {
+                path: path.to_string(),
+            })
+        };
+
+        DynamicImage::ImageRgba8(RgbaImage::new(100, 50))
+            .save(workspace.join("small.png"))
+            .unwrap();
+        let output = read("small.png").await.unwrap();
+        assert_eq!(output.media_type, "image/png");
+        assert_eq!((output.width, output.height), (100, 50));
+        assert_eq!(output.original_size, None);
+
+        DynamicImage::ImageRgba8(RgbaImage::new(3136, 1000))
+            .save(workspace.join("large.png"))
+            .unwrap();
+        let output = read("large.png").await.unwrap();
+        assert_eq!(output.media_type, "image/png");
+        assert_eq!((output.width, output.height), (1568, 500));
+        assert_eq!(
+            output.description(),
+            "Image large.png (3136x1000, downscaled to 1568x500)"
+        );
+
+        fs::write(workspace.join("text.png"), "not an image").unwrap();
+        assert!(read("text.png").await.is_err());
+        fs::remove_dir_all(workspace).ok();
+    }
+}
@@ -1,15 +1,17 @@
 // Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+use std::fmt::Display;
 use std::fs;
 use std::io::ErrorKind;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use indoc::{formatdoc, indoc};
+use itertools::Itertools;
 use rig::completion::ToolDefinition;
 use rig::tool::Tool;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-use crate::tools::{create_patch, normalize_path, workspace_to_string};
+use crate::tools::{create_patch, resolve_write_path, workspace_to_string, write_atomic};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReplaceInFileToolArgs {
@@ -63,10 +65,11 @@ impl Tool for ReplaceInFileTool {
                               1. SEARCH content must match the associated file section to find EXACTLY:
                                  * Match character-for-character including whitespace, indentation, line endings
                                  * Include all comments, docstrings, etc.
-                              2. SEARCH/REPLACE blocks will ONLY replace the first match occurrence.
+                              2. SEARCH content must match exactly one location in the file, otherwise the tool call fails.
                                  * Including multiple unique SEARCH/REPLACE blocks if you need to make multiple changes.
                                  * Include *just* enough lines in each SEARCH section to uniquely match each set of lines that need to change.
-                                 * When using multiple SEARCH/REPLACE blocks, list them in the order they appear in the file.
+                                 * Blocks are applied in order, each block is matched against the file with the previous blocks applied.
+                                 * If any block fails to match, no changes are made to the file.
                               3. Keep SEARCH/REPLACE blocks concise:
                                  * Break large SEARCH/REPLACE blocks into a series of smaller blocks that each change a small portion of the file.
                                  * Include just the changing lines, and a few surrounding lines if needed for uniqueness.
@@ -85,34 +88,437 @@ impl Tool for ReplaceInFileTool {
     }
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        let path = normalize_path(&self.workspace, &args.path);
-        tracing::info!("Replace in file '{}'", path);
+        let path = resolve_write_path(&self.workspace, &args.path)?;
+        tracing::info!("Replace in file '{}'", path.display());
         let replace_diffs = parse_replace_diff(&args.diff)?;
         let original_content = fs::read_to_string(path.clone())?;
-        let mut modified_content = original_content.clone();
-        for replace_diff in replace_diffs {
-            let search = &replace_diff.search;
-            let replace = &replace_diff.replace;
-            let start = original_content.find(search);
-            if let Some(start) = start {
-                let end = start + search.len();
-                modified_content.replace_range(start..end, replace);
-            } else {
-                return Err(std::io::Error::new(
-                    ErrorKind::NotFound,
-                    format!("Search string not found: {}", search),
-                ));
-            }
-        }
-        let diff = create_patch(&original_content, &modified_content);
-        fs::write(path, modified_content)?;
+        let result = apply_replace_diffs(&original_content, replace_diffs)?;
+        let diff = create_patch(&original_content, &result.content);
+        write_atomic(&path, &result.content)?;
         Ok(format!(
-            "The user made the following updates to your content:\n\n{}",
+            "{}The user made the following updates to your content:\n\n{}",
+            result.notes(),
             diff
         ))
     }
 }
 
+/// Computes the patch of the pending tool call without modifying the file,
+/// fails if any SEARCH block doesn't match the file
+pub fn preview_changes(workspace: &Path, args: &ReplaceInFileToolArgs) -> std::io::Result<String> {
+    let path = resolve_write_path(workspace, &args.path)?;
+    let original_content = fs::read_to_string(path)?;
+    let result = apply_replace_diffs(&original_content, parse_replace_diff(&args.diff)?)?;
+    Ok(create_patch(&original_content, &result.content))
+}
+
+/// Minimal similarity of a file region to the SEARCH block to be replaced
+const SIMILARITY_THRESHOLD: f64 = 0.9;
+/// Regions with lower similarity are not reported as the closest candidates
+const CANDIDATE_THRESHOLD: f64 = 0.5;
+/// Similarity matching is skipped for larger files and SEARCH blocks,
+/// since the edit distance is quadratic in the block length
+const MAX_SIMILARITY_LINES: usize = 20_000;
+const MAX_SIMILARITY_SEARCH_LEN: usize = 5_000;
+/// Similarity matching is skipped if too many regions are similar to the SEARCH block by the first or the last line
+const MAX_SIMILARITY_CANDIDATES: usize = 100;
+
+/// Strategies of matching SEARCH block to the file content, from the strictest one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MatchStrategy {
+    Exact,
+    /// Lines match ignoring trailing whitespaces
+    LineTrimmed,
+    /// Lines match ignoring common indentation of the block
+    IndentationNormalized,
+    /// Lines are similar enough ignoring whitespaces
+    Similarity,
+}
+
+impl Display for MatchStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Exact => write!(f, "exact"),
+            Self::LineTrimmed => write!(f, "line-trimmed"),
+            Self::IndentationNormalized => write!(f, "indentation-normalized"),
+            Self::Similarity => write!(f, "similarity"),
+        }
+    }
+}
+
+/// Region of the file content matched by SEARCH block
+#[derive(Debug)]
+struct BlockMatch {
+    start: usize,
+    end: usize,
+    /// Replacement adjusted to the matched region (e.g. reindented)
+    replace: String,
+    strategy: MatchStrategy,
+}
+
+#[derive(Debug)]
+struct ReplaceResult {
+    content: String,
+    /// Strategy used to match every SEARCH block
+    strategies: Vec<MatchStrategy>,
+}
+
+impl ReplaceResult {
+    /// Notes for the model about SEARCH blocks that didn't match exactly
+    fn notes(&self) -> String {
+        self.strategies
+            .iter()
+            .enumerate()
+            .filter(|(_, strategy)| **strategy != MatchStrategy::Exact)
+            .map(|(idx, strategy)| {
+                format!(
+                    "Note: SEARCH block {} didn't match exactly and was applied using {} matching.\n",
+                    idx + 1,
+                    strategy
+                )
+            })
+            .collect()
+    }
+}
+
+fn apply_replace_diffs(
+    original_content: &str,
+    replace_diffs: Vec<ReplaceDiffBlock>,
+) -> Result<ReplaceResult, std::io::Error> {
+    // blocks are parsed line by line, so CRLF files are matched with LF line endings
+    let is_crlf = original_content.contains("\r\n");
+    let mut modified_content = original_content.to_string();
+    let mut strategies = Vec::new();
+    let mut errors = Vec::new();
+    // every block is validated before anything is written, so a failed block never leaves a partial edit
+    for (idx, replace_diff) in replace_diffs.iter().enumerate() {
+        let normalized = is_crlf.then(|| normalize_line_endings(&modified_content));
+        let content = normalized
+            .as_ref()
+            .map_or(modified_content.as_str(), |(content, _)| content.as_str());
+        match find_block(content, replace_diff) {
+            Ok(block_match) => {
+                let (start, end) = match &normalized {
+                    Some((_, offsets)) => (offsets[block_match.start], offsets[block_match.end]),
+                    None => (block_match.start, block_match.end),
+                };
+                // line endings of other lines are kept, the replacement gets the ending of the region
+                let region = &modified_content[start..end];
+                let replace = if region.contains("\r\n") || (is_crlf && !region.contains('\n')) {
+                    block_match.replace.replace('\n', "\r\n")
+                } else {
+                    block_match.replace
+                };
+                modified_content.replace_range(start..end, &replace);
+                strategies.push(block_match.strategy);
+            }
+            Err(e) => errors.push(e.with_block_idx(idx)),
+        }
+    }
+    if let Some(first) = errors.first() {
+        return Err(std::io::Error::new(
+            first.kind,
+            format!(
+                "No changes were made, {} of {} SEARCH blocks failed:\n{}",
+                errors.len(),
+                replace_diffs.len(),
+                errors.iter().map(|e| e.message.as_str()).join("\n")
+            ),
+        ));
+    }
+    Ok(ReplaceResult {
+        content: modified_content,
+        strategies,
+    })
+}
+
+/// Replaces CRLF with LF, returns the offset in the original content for every byte
+/// of the normalized one and its end
+fn normalize_line_endings(content: &str) -> (String, Vec<usize>) {
+    let mut normalized = String::with_capacity(content.len());
+    let mut offsets = Vec::with_capacity(content.len() + 1);
+    let mut chars = content.char_indices().peekable();
+    while let Some((idx, ch)) = chars.next() {
+        if ch == '\r' && chars.peek().is_some_and(|(_, next)| *next == '\n') {
+            // the line break starts at CR, so it's replaced together with LF
+            chars.next();
+            normalized.push('\n');
+            offsets.push(idx);
+            continue;
+        }
+        normalized.push(ch);
+        offsets.extend(idx..idx + ch.len_utf8());
+    }
+    offsets.push(content.len());
+    (normalized, offsets)
+}
+
+#[derive(Debug)]
+struct BlockError {
+    kind: ErrorKind,
+    message: String,
+}
+
+impl BlockError {
+    fn not_found(message: String) -> Self {
+        Self {
+            kind: ErrorKind::NotFound,
+            message,
+        }
+    }
+
+    /// SEARCH block matches several regions of the file, the line numbers are 1-based
+    fn ambiguous(search: &str, lines: &[usize]) -> Self {
+        Self {
+            kind: ErrorKind::InvalidInput,
+            message: format!(
+                "Search string matches {} times (at lines {}), include more surrounding lines to make it unique: {}",
+                lines.len(),
+                lines.iter().join(", "),
+                search
+            ),
+        }
+    }
+
+    fn with_block_idx(self, idx: usize) -> Self {
+        Self {
+            kind: self.kind,
+            message: format!("SEARCH block {}: {}", idx + 1, self.message),
+        }
+    }
+}
+
+/// Byte ranges of content lines without line endings
+fn line_ranges(content: &str) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    for line in content.split_inclusive('\n') {
+        ranges.push((start, start + line.trim_end_matches('\n').len()));
+        start += line.len();
+    }
+    ranges
+}
+
+fn indentation(line: &str) -> &str {
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+/// Minimal indentation of non-blank lines
+fn common_indentation<'a>(lines: &[&'a str]) -> &'a str {
+    lines
+        .iter()
+        .copied()
+        .filter(|line| !line.trim().is_empty())
+        .map(indentation)
+        .min_by_key(|indent| indent.len())
+        .unwrap_or_default()
+}
+
+fn dedent<'a>(lines: &[&'a str]) -> Vec<&'a str> {
+    let indent = common_indentation(lines).len();
+    lines
+        .iter()
+        .copied()
+        .map(|line| {
+            let line = line.trim_end();
+            &line[indent.min(indentation(line).len())..]
+        })
+        .collect()
+}
+
+/// Moves replacement lines from the indentation of SEARCH block to the indentation of the matched region
+fn reindent(replace: &str, search_indent: &str, target_indent: &str) -> String {
+    if search_indent == target_indent {
+        return replace.to_string();
+    }
+    replace
+        .split_inclusive('\n')
+        .map(|line| {
+            if line.trim().is_empty() {
+                line.to_string()
+            } else {
+                let indent = indentation(line);
+                let strip = search_indent.len().min(indent.len());
+                format!("{}{}", target_indent, &line[strip..])
+            }
+        })
+        .collect()
+}
+
+/// The length difference alone gives the upper bound of similarity
+fn similar_length(len: usize, search_len: usize) -> bool {
+    let (min_len, max_len) = (len.min(search_len), len.max(search_len));
+    max_len == 0 || (min_len as f64 / max_len as f64) >= CANDIDATE_THRESHOLD
+}
+
+fn similar_line(line: &str, search_line: &str) -> bool {
+    let line = line.trim();
+    similar_length(line.len(), search_line.len())
+        && strsim::normalized_levenshtein(line, search_line) >= CANDIDATE_THRESHOLD
+}
+
+fn similarity(lines: &[&str], search: &str) -> f64 {
+    let text = lines.iter().map(|line| line.trim()).join("\n");
+    strsim::normalized_levenshtein(&text, search)
+}
+
+/// Windows worth comparing with the SEARCH block: a window has a similar length and the first
+/// or the last line similar to the block's one. Returns None if there are too many.
+fn similarity_candidates(lines: &[&str], search_lines: &[&str]) -> Option<Vec<usize>> {
+    let count = search_lines.len();
+    let search_len = search_lines
+        .iter()
+        .map(|line| line.trim().len() + 1)
+        .sum::<usize>();
+    let (first, last) = (search_lines[0].trim(), search_lines[count - 1].trim());
+    // lengths of trimmed lines with the line break, summed from the beginning of the file
+    let mut offsets = vec![0];
+    for line in lines {
+        offsets.push(offsets[offsets.len() - 1] + line.trim().len() + 1);
+    }
+    let candidates = (0..=lines.len() - count)
+        .filter(|idx| {
+            similar_length(offsets[*idx + count] - offsets[*idx], search_len)
+                && (similar_line(lines[*idx], first) || similar_line(lines[*idx + count - 1], last))
+        })
+        .collect::<Vec<_>>();
+    (candidates.len() <= MAX_SIMILARITY_CANDIDATES).then_some(candidates)
+}
+
+fn find_block(content: &str, block: &ReplaceDiffBlock) -> Result<BlockMatch, BlockError> {
+    let exact_match = |start: usize| BlockMatch {
+        start,
+        end: start + block.search.len(),
+        replace: block.replace.clone(),
+        strategy: MatchStrategy::Exact,
+    };
+    // empty SEARCH block inserts the replacement at the beginning of the file
+    if block.search.is_empty() {
+        return Ok(exact_match(0));
+    }
+    let exact_matches = content
+        .match_indices(&block.search)
+        .map(|(start, _)| start)
+        .collect::<Vec<_>>();
+    match exact_matches.as_slice() {
+        [start] => return Ok(exact_match(*start)),
+        [] => {}
+        starts => {
+            let lines = starts
+                .iter()
+                .map(|start| content[..*start].matches('\n').count() + 1)
+                .collect::<Vec<_>>();
+            return Err(BlockError::ambiguous(&block.search, &lines));
+        }
+    }
+    let not_found = BlockError::not_found(format!("Search string not found: {}", block.search));
+    let search_lines = block.search.lines().collect::<Vec<_>>();
+    if search_lines.iter().all(|line| line.trim().is_empty()) {
+        return Err(not_found);
+    }
+    let ranges = line_ranges(content);
+    let lines = ranges
+        .iter()
+        .map(|(start, end)| &content[*start..*end])
+        .collect::<Vec<_>>();
+    let count = search_lines.len();
+    if lines.len() < count {
+        return Err(not_found);
+    }
+    let search_indent = common_indentation(&search_lines);
+    let dedented_search = dedent(&search_lines);
+    let block_match = |idx: usize, strategy: MatchStrategy| {
+        let start = ranges[idx].0;
+        let mut end = ranges[idx + count - 1].1;
+        let mut replace = block.replace.clone();
+        // the line break after the region is replaced only if the SEARCH block ends with it
+        if block.search.ends_with('\n') {
+            if content[end..].starts_with('\n') {
+                end += 1;
+            } else if replace.ends_with('\n') {
+                // the region ends at the end of file without a line break
+                replace.pop();
+            }
+        }
+        if strategy != MatchStrategy::LineTrimmed {
+            let target_indent = common_indentation(&lines[idx..idx + count]);
+            replace = reindent(&replace, search_indent, target_indent);
+        }
+        BlockMatch {
+            start,
+            end,
+            replace,
+            strategy,
+        }
+    };
+    let windows = 0..=lines.len() - count;
+    let unique_match = |matches: Vec<usize>, strategy: MatchStrategy| match matches.as_slice() {
+        [] => None,
+        [idx] => Some(Ok(block_match(*idx, strategy))),
+        _ => {
+            let lines = matches.iter().map(|idx| idx + 1).collect::<Vec<_>>();
+            Some(Err(BlockError::ambiguous(&block.search, &lines)))
+        }
+    };
+
+    let trimmed_matches = windows
+        .clone()
+        .filter(|idx| {
+            lines[*idx..*idx + count]
+                .iter()
+                .zip(search_lines.iter())
+                .all(|(line, search)| line.trim_end() == search.trim_end())
+        })
+        .collect();
+    if let Some(result) = unique_match(trimmed_matches, MatchStrategy::LineTrimmed) {
+        return result;
+    }
+    let dedented_matches = windows
+        .filter(|idx| dedent(&lines[*idx..*idx + count]) == dedented_search)
+        .collect();
+    if let Some(result) = unique_match(dedented_matches, MatchStrategy::IndentationNormalized) {
+        return result;
+    }
+    if lines.len() > MAX_SIMILARITY_LINES || block.search.len() > MAX_SIMILARITY_SEARCH_LEN {
+        return Err(not_found);
+    }
+    let Some(candidates) = similarity_candidates(&lines, &search_lines) else {
+        return Err(not_found);
+    };
+    let search = search_lines.iter().map(|line| line.trim()).join("\n");
+    let scores = candidates
+        .into_iter()
+        .map(|idx| (idx, similarity(&lines[idx..idx + count], &search)))
+        .collect::<Vec<_>>();
+    let Some((best_idx, best_score)) = scores.iter().copied().max_by(|a, b| a.1.total_cmp(&b.1))
+    else {
+        return Err(not_found);
+    };
+    if best_score >= SIMILARITY_THRESHOLD {
+        // overlapping windows are similar to the best one as well, only distinct regions are ambiguous
+        let similar_matches = scores
+            .iter()
+            .filter(|(idx, score)| {
+                *score >= SIMILARITY_THRESHOLD
+                    && (*idx == best_idx || idx.abs_diff(best_idx) >= count)
+            })
+            .map(|(idx, _)| *idx)
+            .collect();
+        return unique_match(similar_matches, MatchStrategy::Similarity).unwrap_or(Err(not_found));
+    }
+    if best_score >= CANDIDATE_THRESHOLD {
+        return Err(BlockError::not_found(format!(
+            "{}\nThe closest match ({:.0}% similar) is at lines {}-{}:\n{}",
+            not_found.message,
+            best_score * 100.0,
+            best_idx + 1,
+            best_idx + count,
+            lines[best_idx..best_idx + count].join("\n")
+        )));
+    }
+    Err(not_found)
+}
+
 #[derive(Debug, Default, PartialEq, Eq)]
 struct ReplaceDiffBlock {
     pub search: String,
@@ -150,6 +556,7 @@ fn parse_replace_diff(diff: &str) -> Result<Vec<ReplaceDiffBlock>, std::io::Erro
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tools::test_dir;
 
     #[test]
     fn test_parse_replace() {
@@ -192,4 +599,140 @@ return (
             }
         );
     }
+
+    #[test]
+    fn test_preview_changes() {
+        let workspace = test_dir("preview");
+        fs::write(
+            workspace.join("main.rs"),
+            "fn main() {\n    println!(\"Hello\");\n}\n",
+        )
+        .unwrap();
+        let replace = "    println!(\"Hello, world!\");";
+        let args = |search: &str| ReplaceInFileToolArgs {
+            path: "main.rs".to_string(),
+            diff: format!("<<<<<<< SEARCH\n{search}\n=======\n{replace}\n>>>>>>> REPLACE\n"),
+        };
+        let diff = preview_changes(&workspace, &args("    println!(\"Hello\");")).unwrap();
+        assert!(diff.contains("\n-    println!(\"Hello\");\n+    println!(\"Hello, world!\");\n"));
+        assert_eq!(
+            fs::read_to_string(workspace.join("main.rs")).unwrap(),
+            "fn main() {\n    println!(\"Hello\");\n}\n"
+        );
+        let err = preview_changes(&workspace, &args("    println!(\"Bye\");")).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        fs::remove_dir_all(workspace).ok();
+    }
+
+    fn replace(
+        content: &str,
+        search: &str,
+        replace: &str,
+    ) -> Result<ReplaceResult, std::io::Error> {
+        apply_replace_diffs(
+            content,
+            vec![ReplaceDiffBlock {
+                search: search.to_string(),
+                replace: replace.to_string(),
+            }],
+        )
+    }
+
+    #[test]
+    fn test_match_strategies() {
+        let content = "fn main() {\n    let a = 1;  \n    let b = 2;\n}\n";
+
+        let result = replace(content, "    let a = 1;  \n", "    let a = 3;\n").unwrap();
+        assert_eq!(result.strategies, vec![MatchStrategy::Exact]);
+
+        let result = replace(
+            content,
+            "    let a = 1;\n    let b = 2;\n",
+            "    let c = 3;\n",
+        )
+        .unwrap();
+        assert_eq!(result.strategies, vec![MatchStrategy::LineTrimmed]);
+        assert_eq!(result.content, "fn main() {\n    let c = 3;\n}\n");
+
+        let result = replace(
+            content,
+            "let a = 1;\nlet b = 2;\n",
+            "let c = 3;\nif c > 2 {\n    c = 2;\n}\n",
+        )
+        .unwrap();
+        assert_eq!(
+            result.strategies,
+            vec![MatchStrategy::IndentationNormalized]
+        );
+        assert_eq!(
+            result.content,
+            "fn main() {\n    let c = 3;\n    if c > 2 {\n        c = 2;\n    }\n}\n"
+        );
+
+        let result = replace(content, "    let a = 1;\n    let b = 22;\n}", "}").unwrap();
+        assert_eq!(result.strategies, vec![MatchStrategy::Similarity]);
+        assert_eq!(result.content, "fn main() {\n}\n");
+
+        let result = replace("a\r\nb\r\nc\r\n", "b\n", "d\ne\n").unwrap();
+        assert_eq!(result.strategies, vec![MatchStrategy::Exact]);
+        assert_eq!(result.content, "a\r\nd\r\ne\r\nc\r\n");
+
+        // line endings of mixed files are kept, the replaced region keeps its own
+        let result = replace("a\r\nb\nc\r\nd\n", "c\nd\n", "e\nf\n").unwrap();
+        assert_eq!(result.strategies, vec![MatchStrategy::Exact]);
+        assert_eq!(result.content, "a\r\nb\ne\r\nf\r\n");
+        let result = replace("a\r\nb\nc\r\n", "b\n", "x\ny\n").unwrap();
+        assert_eq!(result.content, "a\r\nx\ny\nc\r\n");
+        let result = replace("a\r\nb  \r\nc\n", "b\n", "d\n").unwrap();
+        assert_eq!(result.strategies, vec![MatchStrategy::LineTrimmed]);
+        assert_eq!(result.content, "a\r\nd\r\nc\n");
+
+        let err = replace(content, "    let a = 10;\n    let x = 20;\n", "").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("The closest match (87% similar) is at lines 2-3"));
+
+        // too many similar regions aren't compared with the SEARCH block
+        let content = "let x = 1;\n".repeat(MAX_SIMILARITY_CANDIDATES + 1);
+        let err = replace(&content, "let x = 2;\n", "").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_apply_multiple_blocks() {
+        let content = "let a = 1;\nlet b = 2;\nlet c = 3;\nlet b = 2;\n";
+        let block = |search: &str, replace: &str| ReplaceDiffBlock {
+            search: search.to_string(),
+            replace: replace.to_string(),
+        };
+
+        // the second block is matched after the first one changed the content length
+        let result = apply_replace_diffs(
+            content,
+            vec![
+                block("let a = 1;\n", "let a = 1;\nlet a2 = 10;\n"),
+                block("let c = 3;\n", "let c = 30;\n"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            result.content,
+            "let a = 1;\nlet a2 = 10;\nlet b = 2;\nlet c = 30;\nlet b = 2;\n"
+        );
+
+        let err = apply_replace_diffs(
+            content,
+            vec![
+                block("let a = 1;\n", "let a = 10;\n"),
+                block("let b = 2;\n", "let b = 20;\n"),
+                block("let d = 4;\n", ""),
+            ],
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        let message = err.to_string();
+        assert!(message.starts_with("No changes were made, 2 of 3 SEARCH blocks failed"));
+        assert!(message.contains("SEARCH block 2: Search string matches 2 times (at lines 2, 4)"));
+        assert!(message.contains("SEARCH block 3: Search string not found"));
+    }
 }
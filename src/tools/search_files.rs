@@ -1,22 +1,32 @@
 // Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
-use std::io::{Cursor, ErrorKind};
-use std::path::PathBuf;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
 
-use grep_printer::StandardBuilder;
-use grep_regex::RegexMatcher;
-use grep_searcher::{BinaryDetection, SearcherBuilder};
+use grep_regex::RegexMatcherBuilder;
+use grep_searcher::{
+    BinaryDetection, Searcher, SearcherBuilder, Sink, SinkContext, SinkContextKind, SinkMatch,
+};
+use ignore::overrides::OverrideBuilder;
+use ignore::WalkBuilder;
 use indoc::formatdoc;
 use rig::completion::ToolDefinition;
 use rig::tool::Tool;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-use crate::tools::{normalize_path, workspace_to_string};
+use crate::tools::{resolve_read_path, workspace_to_string};
 
 use super::AgentToolError;
 
+/// Default maximal number of matching lines returned by one search
+const DEFAULT_MAX_RESULTS: usize = 100;
+/// Upper bound of the number of context lines around every match
+const MAX_CONTEXT_LINES: usize = 10;
+
 pub struct SearchFilesTool {
     pub workspace: PathBuf,
+    /// Directories outside of the workspace allowed for reading
+    pub read_only_roots: Vec<PathBuf>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,11 +34,82 @@ pub struct SearchFilesToolArgs {
     pub path: String,
     pub regex: String,
     pub file_pattern: Option<String>,
+    /// Number of lines shown before and after every match
+    #[serde(default)]
+    pub context_lines: Option<usize>,
+    #[serde(default)]
+    pub case_insensitive: Option<bool>,
+    /// Search for the literal string instead of the regex
+    #[serde(default)]
+    pub fixed_strings: Option<bool>,
+    #[serde(default)]
+    pub max_results: Option<usize>,
+}
+
+/// Counts matching lines of a file and prints them with their context in one pass,
+/// lines are printed as `path:line:text` and context lines as `path-line-text`
+struct ResultSink<'a> {
+    path: &'a Path,
+    output: &'a mut String,
+    /// Number of matching lines which can still be shown
+    remaining: usize,
+    count: usize,
+    /// Number of the last printed line, groups of non-adjacent lines are separated by `--`
+    last_line: Option<u64>,
+}
+
+impl ResultSink<'_> {
+    fn print(&mut self, line_number: u64, separator: char, line: &[u8]) {
+        if self.last_line.is_some_and(|last| last + 1 < line_number) {
+            self.output.push_str("--\n");
+        }
+        let line = String::from_utf8_lossy(line);
+        self.output.push_str(&format!(
+            "{}{separator}{line_number}{separator}{}\n",
+            self.path.display(),
+            line.trim_end_matches(['\r', '\n'])
+        ));
+        self.last_line = Some(line_number);
+    }
+}
+
+impl Sink for ResultSink<'_> {
+    type Error = std::io::Error;
+
+    fn matched(&mut self, _searcher: &Searcher, mat: &SinkMatch<'_>) -> Result<bool, Self::Error> {
+        let start = mat.line_number().unwrap_or_default();
+        for (idx, line) in mat.lines().enumerate() {
+            self.count += 1;
+            if self.remaining > 0 {
+                self.remaining -= 1;
+                self.print(start + idx as u64, ':', line);
+            }
+        }
+        Ok(true)
+    }
+
+    fn context(
+        &mut self,
+        _searcher: &Searcher,
+        context: &SinkContext<'_>,
+    ) -> Result<bool, Self::Error> {
+        let line_number = context.line_number().unwrap_or_default();
+        // the context after the last shown match is still printed
+        let follows_shown = *context.kind() == SinkContextKind::After
+            && self.last_line.is_some_and(|last| last + 1 == line_number);
+        if self.remaining > 0 || follows_shown {
+            self.print(line_number, '-', context.bytes());
+        }
+        Ok(true)
+    }
 }
 
 impl SearchFilesTool {
-    pub fn new(workspace: PathBuf) -> Self {
-        Self { workspace }
+    pub fn new(workspace: PathBuf, read_only_roots: Vec<PathBuf>) -> Self {
+        Self {
+            workspace,
+            read_only_roots,
+        }
     }
 }
 
@@ -44,7 +125,9 @@ impl Tool for SearchFilesTool {
             name: self.name(),
             description: formatdoc! {"\
                 Request to perform a regex search across files in a specified directory, providing context-rich results. \
-                This tool searches for patterns or specific content across multiple files, displaying each match with encapsulating context."}.to_string(),
+                This tool searches for patterns or specific content across multiple files, displaying each match with encapsulating context. \
+                Files ignored by .gitignore are skipped. Results are limited to {} matching lines by default, \
+                the number of matches in every file is reported.", DEFAULT_MAX_RESULTS}.to_string(),
             parameters: json!({
                 "type": "object",
                 "properties": {
@@ -60,6 +143,22 @@ impl Tool for SearchFilesTool {
                     "file_pattern": {
                         "type": "string",
                         "description": "Glob pattern to filter files (e.g., '*.ts' for TypeScript files). If not provided, it will search all files (*)."
+                    },
+                    "context_lines": {
+                        "type": "integer",
+                        "description": format!("Number of lines to show before and after every match (optional, 0 by default, at most {})", MAX_CONTEXT_LINES)
+                    },
+                    "case_insensitive": {
+                        "type": "boolean",
+                        "description": "Search case insensitively (optional, false by default)"
+                    },
+                    "fixed_strings": {
+                        "type": "boolean",
+                        "description": "Treat the regex as a literal string (optional, false by default)"
+                    },
+                    "max_results": {
+                        "type": "integer",
+                        "description": format!("Maximal number of matching lines to show (optional, {} by default)", DEFAULT_MAX_RESULTS)
                     }
                 },
                 "required": ["path", "regex"]
@@ -69,54 +168,166 @@ impl Tool for SearchFilesTool {
     }
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        let path = normalize_path(&self.workspace, &args.path);
-        let matcher = RegexMatcher::new_line_matcher(&args.regex).map_err(|e| {
-            std::io::Error::new(ErrorKind::InvalidInput, format!("invalid regex: {}", e))
-        })?;
-        tracing::info!("Search for path '{}' and regex {}", path, args.regex);
+        let path = resolve_read_path(&self.workspace, &self.read_only_roots, &args.path)?;
+        let matcher = RegexMatcherBuilder::new()
+            .line_terminator(Some(b'\n'))
+            .case_insensitive(args.case_insensitive.unwrap_or_default())
+            .fixed_strings(args.fixed_strings.unwrap_or_default())
+            .build(&args.regex)
+            .map_err(|e| {
+                std::io::Error::new(ErrorKind::InvalidInput, format!("invalid regex: {}", e))
+            })?;
+        tracing::info!(
+            "Search for path '{}' and regex {}",
+            path.display(),
+            args.regex
+        );
+        let mut walk_builder = WalkBuilder::new(&path);
+        if let Some(file_pattern) = args.file_pattern.as_ref().filter(|p| !p.is_empty()) {
+            let overrides = OverrideBuilder::new(&path)
+                .add(file_pattern)
+                .and_then(|builder| builder.build())
+                .map_err(|e| {
+                    std::io::Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("invalid file pattern: {}", e),
+                    )
+                })?;
+            walk_builder.overrides(overrides);
+        }
+        let context_lines = args.context_lines.unwrap_or(0).min(MAX_CONTEXT_LINES);
         let mut searcher = SearcherBuilder::new()
             .binary_detection(BinaryDetection::quit(b'\x00'))
+            .before_context(context_lines)
+            .after_context(context_lines)
             .build();
+        let max_results = args.max_results.unwrap_or(DEFAULT_MAX_RESULTS);
 
-        let mut buffer = Vec::new();
-        let writer = Cursor::new(&mut buffer);
-        let mut printer = StandardBuilder::new().build_no_color(writer);
-
-        for entry in ignore::Walk::new(path).filter_map(|e| e.ok()) {
+        let mut res = String::new();
+        let mut file_counts = Vec::new();
+        let mut remaining = max_results;
+        for entry in walk_builder.build().filter_map(|e| e.ok()) {
             if !entry.file_type().is_some_and(|t| t.is_file()) {
                 continue;
             }
-            let _ = searcher.search_path(
-                &matcher,
-                entry.path(),
-                printer.sink_with_path(&matcher, entry.path()),
-            );
+            let mut sink = ResultSink {
+                path: entry.path(),
+                output: &mut res,
+                remaining,
+                count: 0,
+                last_line: None,
+            };
+            let _ = searcher.search_path(&matcher, entry.path(), &mut sink);
+            remaining = sink.remaining;
+            if sink.count > 0 {
+                file_counts.push((entry.path().to_path_buf(), sink.count));
+            }
         }
-        let res = String::from_utf8(buffer).unwrap();
-        if res.is_empty() {
-            Ok("No results found".to_string())
-        } else {
-            Ok(res)
+        if file_counts.is_empty() {
+            return Ok("No results found".to_string());
         }
+        let shown = max_results - remaining;
+        let total = file_counts.iter().map(|(_, count)| count).sum::<usize>();
+        if total > shown {
+            res.push_str(&format!(
+                "\n... {} more matching lines are not shown, refine the search or increase max_results\n",
+                total - shown
+            ));
+        }
+        res.push_str(&format!(
+            "\nFound {} matching lines in {} files:\n",
+            total,
+            file_counts.len()
+        ));
+        for (path, count) in file_counts {
+            res.push_str(&format!("{}: {}\n", path.display(), count));
+        }
+        Ok(res)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tools::test_dir;
 
     #[tokio::test]
     async fn test_search_files() {
-        let tool = SearchFilesTool::new(".".into());
+        let tool = SearchFilesTool::new(".".into(), Vec::new());
         let res = tool
             .call(SearchFilesToolArgs {
                 path: "src".to_string(),
                 regex: ".*Tool.*".to_string(),
                 file_pattern: None,
+                context_lines: None,
+                case_insensitive: None,
+                fixed_strings: None,
+                max_results: None,
             })
             .await
             .ok()
             .unwrap();
         assert!(!res.is_empty());
     }
+
+    #[tokio::test]
+    async fn test_search_options() {
+        let workspace = test_dir("search");
+        std::fs::create_dir_all(workspace.join("src")).unwrap();
+        std::fs::write(
+            workspace.join("src/a.rs"),
+            "fn a() {}\n// TODO: a.b\n// todo: a\n",
+        )
+        .unwrap();
+        std::fs::write(workspace.join("src/b.ts"), "// TODO: b\n").unwrap();
+        let tool = SearchFilesTool::new(workspace.clone(), Vec::new());
+        let args = |regex: &str| SearchFilesToolArgs {
+            path: "src".to_string(),
+            regex: regex.to_string(),
+            file_pattern: Some("*.rs".to_string()),
+            context_lines: None,
+            case_insensitive: None,
+            fixed_strings: None,
+            max_results: None,
+        };
+
+        let res = tool.call(args("TODO")).await.unwrap();
+        assert!(res.contains("Found 1 matching lines in 1 files"));
+        assert!(!res.contains("b.ts"));
+
+        let res = tool
+            .call(SearchFilesToolArgs {
+                case_insensitive: Some(true),
+                max_results: Some(1),
+                ..args("todo")
+            })
+            .await
+            .unwrap();
+        assert!(res.contains("1 more matching lines are not shown"));
+        assert!(res.contains("a.rs: 2"));
+
+        let res = tool
+            .call(SearchFilesToolArgs {
+                case_insensitive: Some(true),
+                context_lines: Some(1),
+                max_results: Some(1),
+                ..args("todo")
+            })
+            .await
+            .unwrap();
+        assert!(res.contains("a.rs-1-fn a() {}\n"));
+        assert!(res.contains("a.rs:2:// TODO: a.b\n"));
+        assert!(!res.contains("a.rs:3:"));
+        assert!(res.contains("1 more matching lines are not shown"));
+
+        let res = tool
+            .call(SearchFilesToolArgs {
+                fixed_strings: Some(true),
+                ..args("a()")
+            })
+            .await
+            .unwrap();
+        assert!(res.contains("Found 1 matching lines"));
+        std::fs::remove_dir_all(workspace).ok();
+    }
 }
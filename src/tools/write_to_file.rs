@@ -7,7 +7,7 @@ use serde_json::json;
 use std::fs;
 use std::path::{Path, PathBuf};
 
-use crate::tools::{create_patch, normalize_path, workspace_to_string};
+use crate::tools::{create_patch, resolve_write_path, workspace_to_string, write_atomic};
 
 use super::AgentToolError;
 
@@ -27,6 +27,17 @@ impl WriteToFileTool {
     }
 }
 
+/// Computes the patch of the pending tool call without writing the file
+pub fn preview_changes(workspace: &Path, args: &WriteToFileToolArgs) -> std::io::Result<String> {
+    let path = resolve_write_path(workspace, &args.path)?;
+    let original_content = if path.exists() {
+        fs::read_to_string(&path)?
+    } else {
+        String::new()
+    };
+    Ok(create_patch(&original_content, &args.content))
+}
+
 impl Tool for WriteToFileTool {
     const NAME: &'static str = "write_to_file";
 
@@ -63,11 +74,11 @@ impl Tool for WriteToFileTool {
     }
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        let path = normalize_path(&self.workspace, &args.path);
-        tracing::info!("Write to file '{}'", path);
+        let path = resolve_write_path(&self.workspace, &args.path)?;
+        tracing::info!("Write to file '{}'", path.display());
         let diff = create_patch("", &args.content);
-        fs::create_dir_all(Path::new(&path).parent().unwrap())?;
-        fs::write(path, args.content)?;
+        fs::create_dir_all(path.parent().unwrap())?;
+        write_atomic(&path, &args.content)?;
         Ok(format!(
             "The user made the following updates to your content:\n\n{}",
             diff
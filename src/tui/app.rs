@@ -5,10 +5,13 @@ use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 use std::{fs, vec};
 
+use crate::agent::checkpoint::CheckpointStore;
 use crate::agent::event::{AgentCommandStatus, AgentState, ConfirmToolResponse};
+use crate::agent::session::{SessionInfo, SessionStore};
 use crate::config::Config;
 
 use crate::providers::model_info::ModelInfo;
+use crate::tools::execute_command::tools::ExecuteCommandTool;
 use crate::{
     agent::{self, AgentControlEvent, AgentOutputEvent},
     tui::{
@@ -25,13 +28,14 @@ use ratatui::{
     widgets::ScrollbarState,
     DefaultTerminal,
 };
-use rig::message::{AssistantContent, Message, UserContent};
+use rig::message::{AssistantContent, Message, ToolCall, UserContent};
 use rig::tool::Tool;
 use tokio::sync::mpsc;
 use tui_textarea::TextArea;
 use tui_widget_list::ListState;
 
 use super::filetree::FileTreeState;
+use super::tool_info;
 
 #[derive(Debug, Clone, Eq, Hash, PartialEq)]
 #[repr(u8)]
@@ -83,6 +87,50 @@ pub struct TerminalState {
     pub scroll_position: u16,
 }
 
+#[derive(Debug, Default)]
+pub struct SessionPickerState {
+    pub sessions: Vec<SessionInfo>,
+    pub selected: usize,
+    pub scroll: usize,
+    /// Edited title when the selected session is renamed
+    pub rename: Option<String>,
+    /// Error message of the last operation
+    pub message: Option<String>,
+}
+
+/// Rollback to the selected message awaiting confirmation
+#[derive(Debug, Default)]
+pub struct RollbackConfirmState {
+    pub message_idx: usize,
+    pub truncate_history: bool,
+    /// Workspace files restored by the rollback
+    pub files: Vec<String>,
+    /// Number of messages removed from the history
+    pub removed_messages: usize,
+    /// Error of reading the checkpoints
+    pub error: Option<String>,
+}
+
+/// Pending changes of a file tool call awaiting approval
+#[derive(Debug, Default, Clone)]
+pub struct ToolPreviewState {
+    pub tool_call_id: String,
+    /// Unified diff of the changes
+    pub diff: String,
+    /// Warning if the changes can't be applied
+    pub warning: Option<String>,
+    pub scroll: u16,
+}
+
+/// Editor of the pending tool call arguments
+#[derive(Debug)]
+pub struct ToolEditorState<'a> {
+    pub tool_call: ToolCall,
+    pub textarea: TextArea<'a>,
+    /// Error of the last attempt to apply the edited arguments
+    pub error: Option<String>,
+}
+
 #[derive(Debug)]
 pub struct UiState<'a> {
     pub textarea: TextArea<'a>,
@@ -91,9 +139,17 @@ pub struct UiState<'a> {
     pub history_state: ListState,
     pub history_follow_last: bool,
     pub history_opened_state: HashSet<usize>,
+    /// Index of the message for every item of the history list
+    pub history_message_idx: Vec<usize>,
     pub throbber_state: throbber_widgets_tui::ThrobberState,
     pub widget_areas: HashMap<FocusedComponent, Rect>,
     pub terminal_state: TerminalState,
+    pub session_picker: Option<SessionPickerState>,
+    pub tool_preview: Option<ToolPreviewState>,
+    /// Id of the tool call with the preview being computed
+    pub tool_preview_request: Option<String>,
+    pub tool_editor: Option<ToolEditorState<'a>>,
+    pub rollback_confirm: Option<RollbackConfirmState>,
 }
 
 #[derive(Debug)]
@@ -107,6 +163,8 @@ pub struct App<'a> {
     pub model: ModelState,
     pub ui: UiState<'a>,
     pub autoreload_theme: bool,
+    pub sessions: SessionStore,
+    pub session_id: String,
 }
 
 impl UiState<'_> {
@@ -118,9 +176,15 @@ impl UiState<'_> {
             history_state: ListState::default(),
             history_follow_last: false,
             history_opened_state: HashSet::default(),
+            history_message_idx: Vec::default(),
             throbber_state: throbber_widgets_tui::ThrobberState::default(),
             widget_areas: HashMap::default(),
             terminal_state: TerminalState::default(),
+            session_picker: None,
+            tool_preview: None,
+            tool_preview_request: None,
+            tool_editor: None,
+            rollback_confirm: None,
         }
     }
 }
@@ -138,6 +202,21 @@ impl ModelState {
     }
 }
 
+/// Computes changes of the pending tool call in a blocking task and sends them to the app
+fn spawn_tool_preview(
+    sender: mpsc::UnboundedSender<UiEvent>,
+    workspace: PathBuf,
+    tool_call: ToolCall,
+) {
+    tokio::task::spawn_blocking(move || {
+        if let Some(preview) = tool_info::get_tool_call_preview(&workspace, &tool_call) {
+            sender
+                .send(UiEvent::App(AppEvent::ToolPreview(preview)))
+                .ok();
+        }
+    });
+}
+
 impl App<'_> {
     pub fn new(
         config: Config,
@@ -146,6 +225,7 @@ impl App<'_> {
         sender: mpsc::UnboundedSender<agent::AgentControlEvent>,
         receiver: mpsc::UnboundedReceiver<agent::AgentOutputEvent>,
         messages: Vec<Message>,
+        sessions: SessionStore,
         autoreload_theme: bool,
     ) -> Self {
         let theme = Theme::load(&config.appearance.theme).unwrap();
@@ -159,6 +239,8 @@ impl App<'_> {
             theme,
             model: ModelState::new(messages, model_info),
             autoreload_theme,
+            sessions,
+            session_id: String::new(),
         }
     }
 
@@ -268,6 +350,7 @@ impl App<'_> {
                                     Self::handle_tree_input(&mut self.ui.tree_state, &event);
                                 }
                                 FocusedComponent::History => {
+                                    self.handle_history_rollback(key_event);
                                     Self::handle_list_input(
                                         &mut self.ui.history_state,
                                         &mut self.ui.history_opened_state,
@@ -369,6 +452,16 @@ impl App<'_> {
                 },
                 UiEvent::App(app_event) => match app_event {
                     AppEvent::Quit => self.quit(),
+                    AppEvent::ToolPreview(preview) => {
+                        // the tool call may be already approved or denied
+                        if matches!(
+                            &self.model.agent_status.state,
+                            AgentState::ToolCall(tool_call, true) if tool_call.id == preview.tool_call_id
+                        ) {
+                            self.ui.tool_preview_request = None;
+                            self.ui.tool_preview = Some(preview);
+                        }
+                    }
                     AppEvent::Agent(evt) => match evt {
                         AgentOutputEvent::NewTask => {
                             self.model.messages.clear();
@@ -417,6 +510,43 @@ impl App<'_> {
                                 self.ui.focus = FocusedComponent::Input;
                             }
                             self.model.agent_status.state = state;
+                            self.ui.tool_preview = match &self.model.agent_status.state {
+                                AgentState::ToolCall(tool_call, true) => {
+                                    match self.ui.tool_preview.take() {
+                                        Some(preview) if preview.tool_call_id == tool_call.id => {
+                                            Some(preview)
+                                        }
+                                        _ => {
+                                            // matching SEARCH blocks of large files may be slow,
+                                            // so the preview doesn't block rendering
+                                            if self.ui.tool_preview_request.as_ref()
+                                                != Some(&tool_call.id)
+                                            {
+                                                self.ui.tool_preview_request =
+                                                    Some(tool_call.id.clone());
+                                                spawn_tool_preview(
+                                                    self.events.sender(),
+                                                    self.config.workspace.clone(),
+                                                    tool_call.clone(),
+                                                );
+                                            }
+                                            None
+                                        }
+                                    }
+                                }
+                                _ => None,
+                            };
+                            let is_editing = match &self.model.agent_status.state {
+                                AgentState::ToolCall(tool_call, true) => self
+                                    .ui
+                                    .tool_editor
+                                    .as_ref()
+                                    .is_some_and(|editor| editor.tool_call.id == tool_call.id),
+                                _ => false,
+                            };
+                            if !is_editing {
+                                self.ui.tool_editor = None;
+                            }
                             if current_input_tokens > 0 {
                                 self.model.agent_status.current_input_tokens = current_input_tokens;
                                 self.model.agent_status.current_completion_tokens =
@@ -432,6 +562,15 @@ impl App<'_> {
                             }
                             self.ui.tree_state.highlight_file(path);
                         }
+                        AgentOutputEvent::ReplaceMessages(messages) => {
+                            self.model.messages = messages;
+                            self.ui.history_state.select(None);
+                            self.ui.history_opened_state.clear();
+                            self.ui.history_follow_last = true;
+                        }
+                        AgentOutputEvent::SessionChanged(session_id) => {
+                            self.session_id = session_id;
+                        }
                     },
                 },
             }
@@ -452,6 +591,81 @@ impl App<'_> {
         false
     }
 
+    /// Asks to roll back workspace files (`u`) or files and history (`U`) to the selected message
+    fn handle_history_rollback(&mut self, key_event: KeyEvent) {
+        if key_event.kind != KeyEventKind::Press {
+            return;
+        }
+        let truncate_history = match key_event.code {
+            KeyCode::Char('u') => false,
+            KeyCode::Char('U') => true,
+            _ => return,
+        };
+        let Some(message_idx) = self
+            .ui
+            .history_state
+            .selected
+            .and_then(|selected| self.ui.history_message_idx.get(selected))
+            .copied()
+        else {
+            return;
+        };
+        let workspace = self
+            .config
+            .workspace
+            .canonicalize()
+            .unwrap_or_else(|_| self.config.workspace.clone());
+        let (files, error) =
+            match CheckpointStore::new(&self.sessions.session_dir(&self.session_id))
+                .rollback_paths(message_idx)
+            {
+                Ok(paths) => (
+                    paths
+                        .iter()
+                        .map(|path| {
+                            path.strip_prefix(&workspace)
+                                .unwrap_or(path)
+                                .to_string_lossy()
+                                .to_string()
+                        })
+                        .collect(),
+                    None,
+                ),
+                Err(e) => (Vec::new(), Some(e.to_string())),
+            };
+        let removed_messages = if truncate_history {
+            self.model.messages.len().saturating_sub(message_idx)
+        } else {
+            0
+        };
+        self.ui.rollback_confirm = Some(RollbackConfirmState {
+            message_idx,
+            truncate_history,
+            files,
+            removed_messages,
+            error,
+        });
+    }
+
+    fn handle_rollback_confirm_input(&mut self, key_event: KeyEvent) {
+        let Some(confirm) = self.ui.rollback_confirm.as_ref() else {
+            return;
+        };
+        match key_event.code {
+            KeyCode::Enter if confirm.error.is_none() => {
+                self.agent_sender
+                    .send(AgentControlEvent::Rollback(
+                        confirm.message_idx,
+                        confirm.truncate_history,
+                    ))
+                    .unwrap();
+                self.ui.rollback_confirm = None;
+            }
+            KeyCode::Esc => self.ui.rollback_confirm = None,
+            _ => {}
+        }
+    }
+
     fn handle_list_input(
         state: &mut ListState,
         opened_state: &mut HashSet<usize>,
@@ -530,11 +744,29 @@ impl App<'_> {
             return Ok(false);
         }
 
+        if self.ui.session_picker.is_some() {
+            self.handle_session_picker_input(key_event);
+            return Ok(true);
+        }
+
+        if self.ui.tool_editor.is_some() {
+            self.handle_tool_editor_input(key_event);
+            return Ok(true);
+        }
+
+        if self.ui.rollback_confirm.is_some() {
+            self.handle_rollback_confirm_input(key_event);
+            return Ok(true);
+        }
+
         if let AgentState::ToolCall(tool_call, true) = &self.model.agent_status.state {
             if tool_call.function.name
                 != crate::tools::ask_followup_question::AskFollowupQuestionTool::NAME
             {
                 match key_event.code {
+                    KeyCode::Char('e') | KeyCode::Char('E') => {
+                        self.ui.tool_editor = Some(Self::open_tool_editor(tool_call.clone()));
+                    }
                     KeyCode::Enter => self
                         .agent_sender
                         .send(AgentControlEvent::ConfirmTool(ConfirmToolResponse::Approve))
@@ -551,6 +783,16 @@ impl App<'_> {
                             ConfirmToolResponse::AlwaysApprove,
                         ))
                         .unwrap(),
+                    KeyCode::Up | KeyCode::Down | KeyCode::PageUp | KeyCode::PageDown => {
+                        if let Some(preview) = self.ui.tool_preview.as_mut() {
+                            preview.scroll = match key_event.code {
+                                KeyCode::Up => preview.scroll.saturating_sub(1),
+                                KeyCode::Down => preview.scroll.saturating_add(1),
+                                KeyCode::PageUp => preview.scroll.saturating_sub(10),
+                                _ => preview.scroll.saturating_add(10),
+                            };
+                        }
+                    }
 
                     _ => {}
                 }
@@ -576,6 +818,18 @@ impl App<'_> {
             KeyCode::Char('e') if key_event.modifiers == KeyModifiers::CONTROL => {
                 self.export_history().unwrap();
             }
+            KeyCode::Char('o') if key_event.modifiers == KeyModifiers::CONTROL => {
+                let sessions = self.sessions.list();
+                let selected = sessions
+                    .iter()
+                    .position(|session| session.id == self.session_id)
+                    .unwrap_or_default();
+                self.ui.session_picker = Some(SessionPickerState {
+                    sessions,
+                    selected,
+                    ..Default::default()
+                });
+            }
             KeyCode::BackTab => {
                 let mut focus = self.ui.focus.clone() as u8;
                 if focus == 0 {
@@ -607,6 +861,153 @@ impl App<'_> {
         Ok(true)
     }
 
+    fn open_tool_editor<'b>(tool_call: ToolCall) -> ToolEditorState<'b> {
+        let args = &tool_call.function.arguments;
+        let text = match args.get("command").and_then(|command| command.as_str()) {
+            Some(command) if tool_call.function.name == ExecuteCommandTool::NAME => {
+                command.to_string()
+            }
+            _ => serde_json::to_string_pretty(args).unwrap_or_default(),
+        };
+        let mut textarea = TextArea::new(text.lines().map(str::to_string).collect::<Vec<_>>());
+        textarea.move_cursor(tui_textarea::CursorMove::Bottom);
+        textarea.move_cursor(tui_textarea::CursorMove::End);
+        ToolEditorState {
+            tool_call,
+            textarea,
+            error: None,
+        }
+    }
+
+    fn handle_tool_editor_input(&mut self, key_event: KeyEvent) {
+        let Some(editor) = self.ui.tool_editor.as_mut() else {
+            return;
+        };
+        match key_event.code {
+            KeyCode::Esc => self.ui.tool_editor = None,
+            KeyCode::Char('s') if key_event.modifiers == KeyModifiers::CONTROL => {
+                let text = editor.textarea.lines().join("\n");
+                let arguments = if editor.tool_call.function.name == ExecuteCommandTool::NAME {
+                    let mut arguments = editor.tool_call.function.arguments.clone();
+                    arguments["command"] = serde_json::Value::String(text.trim().to_string());
+                    Ok(arguments)
+                } else {
+                    serde_json::from_str::<serde_json::Value>(&text)
+                        .map_err(|e| format!("Invalid JSON: {e}"))
+                        .and_then(|arguments| {
+                            if arguments.is_object() {
+                                Ok(arguments)
+                            } else {
+                                Err("Arguments must be a JSON object".to_string())
+                            }
+                        })
+                };
+                match arguments {
+                    Ok(arguments) => {
+                        self.ui.tool_editor = None;
+                        self.agent_sender
+                            .send(AgentControlEvent::ConfirmTool(ConfirmToolResponse::Edit(
+                                arguments,
+                            )))
+                            .unwrap();
+                    }
+                    Err(e) => editor.error = Some(e),
+                }
+            }
+            _ => {
+                editor.error = None;
+                editor
+                    .textarea
+                    .input(crossterm::event::Event::Key(key_event));
+            }
+        }
+    }
+
+    fn handle_session_picker_input(&mut self, key_event: KeyEvent) {
+        let Some(picker) = self.ui.session_picker.as_mut() else {
+            return;
+        };
+        if let Some(title) = picker.rename.as_mut() {
+            match key_event.code {
+                KeyCode::Enter => {
+                    let title = picker.rename.take().unwrap_or_default();
+                    if let Some(session) = picker.sessions.get_mut(picker.selected) {
+                        match self.sessions.rename(&session.id, &title) {
+                            Ok(info) => *session = info,
+                            Err(e) => picker.message = Some(e.to_string()),
+                        }
+                    }
+                }
+                KeyCode::Esc => picker.rename = None,
+                KeyCode::Backspace => {
+                    title.pop();
+                }
+                KeyCode::Char(ch) => title.push(ch),
+                _ => {}
+            }
+            return;
+        }
+        picker.message = None;
+        let selected_id = picker
+            .sessions
+            .get(picker.selected)
+            .map(|session| session.id.clone());
+        match key_event.code {
+            KeyCode::Esc => self.ui.session_picker = None,
+            KeyCode::Char('o') if key_event.modifiers == KeyModifiers::CONTROL => {
+                self.ui.session_picker = None
+            }
+            KeyCode::Up => picker.selected = picker.selected.saturating_sub(1),
+            KeyCode::Down => {
+                if picker.selected + 1 < picker.sessions.len() {
+                    picker.selected += 1;
+                }
+            }
+            KeyCode::Enter => {
+                if let Some(session_id) = selected_id {
+                    self.agent_sender
+                        .send(AgentControlEvent::OpenSession(session_id))
+                        .unwrap();
+                    self.ui.session_picker = None;
+                    self.ui.focus = FocusedComponent::Input;
+                }
+            }
+            KeyCode::Char('f') => {
+                if let Some(session_id) = selected_id {
+                    match self.sessions.fork(&session_id) {
+                        Ok(info) => {
+                            self.agent_sender
+                                .send(AgentControlEvent::OpenSession(info.id))
+                                .unwrap();
+                            self.ui.session_picker = None;
+                            self.ui.focus = FocusedComponent::Input;
+                        }
+                        Err(e) => picker.message = Some(e.to_string()),
+                    }
+                }
+            }
+            KeyCode::Char('r') => {
+                if let Some(session) = picker.sessions.get(picker.selected) {
+                    picker.rename = Some(session.title.clone());
+                }
+            }
+            KeyCode::Char('d') => {
+                if let Some(session_id) = selected_id {
+                    if session_id == self.session_id {
+                        picker.message = Some("Current session can't be deleted".to_string());
+                    } else if let Err(e) = self.sessions.delete(&session_id) {
+                        picker.message = Some(e.to_string());
+                    } else {
+                        picker.sessions.remove(picker.selected);
+                        picker.selected =
+                            picker.selected.min(picker.sessions.len().saturating_sub(1));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
     pub fn current_task_text(&self) -> String {
         if let Some(Message::User { content }) = self.model.messages.first() {
             if let UserContent::Text(txt) = content.first() {
@@ -1,4 +1,5 @@
 // Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+use crate::tui::app::ToolPreviewState;
 use crate::AgentOutputEvent;
 use color_eyre::eyre::OptionExt;
 use futures::{FutureExt, StreamExt};
@@ -23,6 +24,8 @@ pub enum UiEvent {
 pub enum AppEvent {
     Quit,
     Agent(AgentOutputEvent),
+    /// Changes of the pending tool call computed in the background
+    ToolPreview(ToolPreviewState),
 }
 
 #[derive(Debug)]
@@ -49,6 +52,10 @@ impl UiEventMultiplexer {
     pub fn send(&mut self, app_event: AppEvent) {
         let _ = self.sender.send(UiEvent::App(app_event));
     }
+
+    pub fn sender(&self) -> mpsc::UnboundedSender<UiEvent> {
+        self.sender.clone()
+    }
 }
 
 struct UiEventTask {
@@ -1,12 +1,18 @@
 // Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
 
+use std::path::Path;
+
 use heck::ToTitleCase;
+use rig::message::ToolCall;
 use rig::tool::Tool;
 
+use crate::tools::apply_patch::ApplyPatchTool;
 use crate::tools::ask_followup_question::AskFollowupQuestionTool;
 use crate::tools::attempt_completion::AttemptCompletionTool;
+use crate::tools::code_nav::tools::{CodeOutlineTool, FindReferencesTool, FindSymbolTool};
+use crate::tools::codebase_search::CodebaseSearchTool;
 use crate::tools::execute_command::tools::{
-    ExecuteCommandTool, GetCommandResultTool, TerminateCommandTool,
+    ExecuteCommandTool, GetCommandResultTool, ResetShellTool, TerminateCommandTool,
 };
 use crate::tools::list_files::ListFilesTool;
 use crate::tools::memory::{
@@ -20,6 +26,8 @@ use crate::tools::search_files::SearchFilesTool;
 use crate::tools::web_fetch::WebFetchTool;
 use crate::tools::web_search::WebSearchTool;
 use crate::tools::write_to_file::WriteToFileTool;
+use crate::tools::{apply_patch, replace_in_file, write_to_file};
+use crate::tui::app::ToolPreviewState;
 
 fn array_info<'a>(name: &'a str, child_name: &'a str, args: &'a serde_json::Value) -> String {
     args.get(name)
@@ -48,6 +56,35 @@ fn array_info<'a>(name: &'a str, child_name: &'a str, args: &'a serde_json::Valu
         .unwrap_or_default()
 }
 
+/// Computes changes of a pending file tool call to show them in the approval prompt
+pub fn get_tool_call_preview(workspace: &Path, tool_call: &ToolCall) -> Option<ToolPreviewState> {
+    let args = tool_call.function.arguments.clone();
+    let result = match tool_call.function.name.as_str() {
+        WriteToFileTool::NAME => {
+            write_to_file::preview_changes(workspace, &serde_json::from_value(args).ok()?)
+        }
+        ReplaceInFileTool::NAME => {
+            replace_in_file::preview_changes(workspace, &serde_json::from_value(args).ok()?)
+        }
+        ApplyPatchTool::NAME => {
+            apply_patch::preview_changes(workspace, &serde_json::from_value(args).ok()?)
+        }
+        _ => return None,
+    };
+    Some(match result {
+        Ok(diff) => ToolPreviewState {
+            tool_call_id: tool_call.id.clone(),
+            diff,
+            ..Default::default()
+        },
+        Err(e) => ToolPreviewState {
+            tool_call_id: tool_call.id.clone(),
+            warning: Some(format!("The tool call will fail: {e}")),
+            ..Default::default()
+        },
+    })
+}
+
 pub fn get_tool_call_info(name: &str, args: &serde_json::Value) -> (String, String) {
     let title = name.to_string().to_title_case();
     let path = args
@@ -60,8 +97,44 @@ pub fn get_tool_call_info(name: &str, args: &serde_json::Value) -> (String, Stri
         .unwrap_or_default();
 
     let (icon, info) = match name {
+        ApplyPatchTool::NAME => (
+            "📁",
+            format!(
+                "Apply patch to {}",
+                apply_patch::patch_paths(
+                    args.get("patch")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or_default()
+                )
+                .join(", ")
+            ),
+        ),
         AskFollowupQuestionTool::NAME => ("🛠️", "Ask followup question".to_string()),
         AttemptCompletionTool::NAME => ("✅️", "Task completed".to_string()),
+        CodeOutlineTool::NAME => ("📁", format!("Outline code in {}", path)),
+        CodebaseSearchTool::NAME => (
+            "📁",
+            format!(
+                "Search codebase for '{}'",
+                args.get("query")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+            ),
+        ),
+        FindSymbolTool::NAME | FindReferencesTool::NAME => {
+            let symbol = args
+                .get("name")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default();
+            let action = match name {
+                FindSymbolTool::NAME => "Find symbol",
+                _ => "Find references of",
+            };
+            match path {
+                "" => ("📁", format!("{} '{}'", action, symbol)),
+                _ => ("📁", format!("{} '{}' in {}", action, symbol, path)),
+            }
+        }
         ExecuteCommandTool::NAME => (
             "🖥️️",
             format!(
@@ -89,8 +162,20 @@ pub fn get_tool_call_info(name: &str, args: &serde_json::Value) -> (String, Stri
                     .unwrap_or_default()
             ),
         ),
+        ResetShellTool::NAME => ("🖥️️", "Reset shell session".to_string()),
         ListFilesTool::NAME => ("📁", format!("List files in {}", path)),
-        ReadFileTool::NAME => ("📁", format!("Read file {}", path)),
+        ReadFileTool::NAME => {
+            let line = |name: &str| args.get(name).and_then(|v| v.as_u64());
+            let range = match (line("start_line"), line("end_line")) {
+                (None, None) => String::new(),
+                (start, end) => format!(
+                    ":{}-{}",
+                    start.unwrap_or(1),
+                    end.map(|end| end.to_string()).unwrap_or_default()
+                ),
+            };
+            ("📁", format!("Read file {}{}", path, range))
+        }
         ReplaceInFileTool::NAME => ("📁", format!("Replace in file {}", path)),
         SearchFilesTool::NAME => (
             "📁",
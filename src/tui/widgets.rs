@@ -1,10 +1,14 @@
 // Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
 pub mod filetree;
 mod message;
+mod rollback_confirm;
+mod session_picker;
 mod shortcuts;
 mod task_info;
 mod task_status;
 mod terminal;
+mod tool_editor;
+mod tool_preview;
 mod toolbar;
 
 use crate::agent::event::AgentState;
@@ -24,8 +28,12 @@ use ratatui::{
 };
 
 use rig::tool::Tool;
+use rollback_confirm::RollbackConfirmWidget;
+use session_picker::SessionPickerWidget;
 use shortcuts::ShortcutsWidget;
 use task_info::TaskInfoWidget;
+use tool_editor::ToolEditorWidget;
+use tool_preview::ToolPreviewWidget;
 use toolbar::ToolbarWidget;
 use tui_widget_list::{ListBuilder, ListView, ScrollAxis};
 
@@ -168,6 +176,7 @@ impl Widget for &mut App<'_> {
             .border_style(theme.border_style(matches!(self.ui.focus, FocusedComponent::History)));
 
         let mut messages: Vec<MessageWidget> = Vec::new();
+        self.ui.history_message_idx.clear();
         let mut virt_idx = 0;
         for (idx, message) in self.model.messages.iter().enumerate() {
             for item in create_messages(
@@ -179,6 +188,7 @@ impl Widget for &mut App<'_> {
                     || self.ui.history_opened_state.contains(&virt_idx),
             ) {
                 messages.push(item);
+                self.ui.history_message_idx.push(idx);
                 virt_idx += 1;
             }
         }
@@ -302,24 +312,62 @@ impl Widget for &mut App<'_> {
                 let tool_name = tool_call.function.name.clone();
                 let tool_args = tool_call.function.arguments.clone();
                 let (icon, info) = tool_info::get_tool_call_info(&tool_name, &tool_args);
-                let mut text = Text::default();
-                text.push_line("");
-                text.push_line("  Agent want execute tool:  ");
-                text.push_line("");
-                text.push_line(Line::default().spans(vec!["  ", &icon, &info, "   "]));
-                text.push_line("");
-                text.push_line(Line::styled(
-                    "  Enter - Approve | Esc - Deny | a - Always Approve  ",
-                    Style::default(),
-                ));
-                let popup = tui_popup::Popup::new(text)
-                    .title(format!(" Confirm tool execution: {}", tool_name))
-                    .style(Style::new().white().on_blue())
-                    .border_set(symbols::border::ROUNDED);
-                popup.render(area, buf);
+                let always_approve = if tool_name
+                    == crate::tools::execute_command::tools::ExecuteCommandTool::NAME
+                {
+                    tool_args
+                        .get("command")
+                        .and_then(|command| command.as_str())
+                        .and_then(crate::agent::permissions::command_prefix)
+                        .map(|prefix| format!("a - Always Approve `{prefix}`"))
+                        .unwrap_or_default()
+                } else {
+                    "a - Always Approve".to_string()
+                };
+                let shortcuts = if always_approve.is_empty() {
+                    "  Enter - Approve | e - Edit | Esc - Deny  ".to_string()
+                } else {
+                    format!("  Enter - Approve | e - Edit | Esc - Deny | {always_approve}  ")
+                };
+                if let Some(preview) = self.ui.tool_preview.as_mut() {
+                    ToolPreviewWidget.render(
+                        area,
+                        buf,
+                        &theme,
+                        &tool_name,
+                        Line::default().spans(vec![icon, info]),
+                        &shortcuts,
+                        preview,
+                    );
+                } else {
+                    let mut text = Text::default();
+                    text.push_line("");
+                    text.push_line("  Agent want execute tool:  ");
+                    text.push_line("");
+                    text.push_line(Line::default().spans(vec!["  ", &icon, &info, "   "]));
+                    text.push_line("");
+                    text.push_line(Line::styled(shortcuts, Style::default()));
+                    let popup = tui_popup::Popup::new(text)
+                        .title(format!(" Confirm tool execution: {}", tool_name))
+                        .style(Style::new().white().on_blue())
+                        .border_set(symbols::border::ROUNDED);
+                    popup.render(area, buf);
+                }
             }
         }
 
+        if let Some(tool_editor) = self.ui.tool_editor.as_mut() {
+            ToolEditorWidget.render(area, buf, &theme, tool_editor);
+        }
+
+        if let Some(rollback_confirm) = self.ui.rollback_confirm.as_ref() {
+            RollbackConfirmWidget.render(area, buf, &theme, rollback_confirm);
+        }
+
+        if let Some(session_picker) = self.ui.session_picker.as_mut() {
+            SessionPickerWidget.render(area, buf, &theme, session_picker, &self.session_id);
+        }
+
         //#region: focus areas
         self.ui
             .widget_areas
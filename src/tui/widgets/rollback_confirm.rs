@@ -0,0 +1,83 @@
+// Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+
+use ratatui::{
+    prelude::*,
+    widgets::{Block, BorderType, Borders, Clear, Padding, Paragraph, Wrap},
+};
+
+use crate::tui::{app::RollbackConfirmState, Theme};
+
+/// Confirmation of the rollback with the files and messages it changes
+pub struct RollbackConfirmWidget;
+
+impl RollbackConfirmWidget {
+    pub fn render(self, area: Rect, buf: &mut Buffer, theme: &Theme, state: &RollbackConfirmState) {
+        let area = area.inner(Margin::new(area.width / 8, area.height / 6));
+        Clear.render(area, buf);
+        let title = if state.truncate_history {
+            " Rollback files and history "
+        } else {
+            " Rollback files "
+        };
+        let block = Block::bordered()
+            .borders(Borders::ALL)
+            .title(title)
+            .title_alignment(Alignment::Right)
+            .title_style(theme.text_style())
+            .padding(Padding::horizontal(1))
+            .border_type(BorderType::Rounded)
+            .border_style(theme.border_style(true))
+            .style(theme.text_style());
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(1),    // Affected files and messages
+                Constraint::Length(1), // Error or shortcuts
+            ])
+            .split(inner);
+
+        let mut lines = Vec::new();
+        if state.files.is_empty() {
+            lines.push(Line::raw(format!(
+                "No files were changed since message {}",
+                state.message_idx
+            )));
+        } else {
+            lines.push(Line::raw(format!(
+                "Files restored to the state before message {}:",
+                state.message_idx
+            )));
+            lines.extend(
+                state
+                    .files
+                    .iter()
+                    .map(|file| Line::styled(format!("  {file}"), theme.text_style())),
+            );
+        }
+        if state.truncate_history {
+            lines.push(Line::default());
+            lines.push(Line::raw(format!(
+                "{} messages are removed from the history",
+                state.removed_messages
+            )));
+        }
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .render(layout[0], buf);
+
+        let footer = if let Some(error) = &state.error {
+            Line::styled(error.clone(), theme.error_style())
+        } else {
+            Line::styled(
+                "Enter - Rollback | Esc - Cancel",
+                Style::default().fg(theme.inactive_text),
+            )
+        };
+        Paragraph::new(footer)
+            .alignment(Alignment::Right)
+            .render(layout[1], buf);
+    }
+}
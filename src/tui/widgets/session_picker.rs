@@ -0,0 +1,128 @@
+// Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+
+use ratatui::{
+    prelude::*,
+    widgets::{Block, BorderType, Borders, Clear, Padding, Paragraph},
+};
+
+use crate::tui::{app::SessionPickerState, Theme};
+
+pub struct SessionPickerWidget;
+
+impl SessionPickerWidget {
+    pub fn render(
+        self,
+        area: Rect,
+        buf: &mut Buffer,
+        theme: &Theme,
+        state: &mut SessionPickerState,
+        current_session_id: &str,
+    ) {
+        let area = area.inner(Margin::new(area.width / 8, area.height / 6));
+        Clear.render(area, buf);
+        let block = Block::bordered()
+            .borders(Borders::ALL)
+            .title(" Sessions ")
+            .title_alignment(Alignment::Right)
+            .title_style(theme.text_style())
+            .padding(Padding::horizontal(1))
+            .border_type(BorderType::Rounded)
+            .border_style(theme.border_style(true))
+            .style(theme.text_style());
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(1),    // Sessions list
+                Constraint::Length(1), // Rename input or shortcuts
+            ])
+            .split(inner);
+
+        let visible = layout[0].height as usize;
+        if state.selected < state.scroll {
+            state.scroll = state.selected;
+        } else if visible > 0 && state.selected >= state.scroll + visible {
+            state.scroll = state.selected + 1 - visible;
+        }
+
+        let lines = if state.sessions.is_empty() {
+            vec![Line::styled(
+                "No saved sessions",
+                Style::default().fg(theme.inactive_text),
+            )]
+        } else {
+            state
+                .sessions
+                .iter()
+                .enumerate()
+                .skip(state.scroll)
+                .take(visible)
+                .map(|(idx, session)| {
+                    let marker = if session.id == current_session_id {
+                        "● "
+                    } else {
+                        "  "
+                    };
+                    let style = if idx == state.selected {
+                        Style::default()
+                            .fg(theme.highlight_text)
+                            .bg(theme.background_highlight)
+                    } else {
+                        theme.text_style()
+                    };
+                    Line::default()
+                        .spans([
+                            Span::styled(marker, Style::default().fg(theme.success)),
+                            Span::styled(
+                                format!(
+                                    "{} ",
+                                    session
+                                        .updated_at
+                                        .with_timezone(&chrono::Local)
+                                        .format("%Y-%m-%d %H:%M")
+                                ),
+                                Style::default().fg(theme.inactive_text),
+                            ),
+                            Span::styled(
+                                format!(
+                                    "{:>7} ${:<7}",
+                                    format_num::format_num!(
+                                        ".2s",
+                                        (session.input_tokens + session.completion_tokens) as f64
+                                    ),
+                                    format_num::format_num!(".2f", session.cost)
+                                ),
+                                Style::default().fg(theme.inactive_text),
+                            ),
+                            Span::raw(if session.title.is_empty() {
+                                session.id.clone()
+                            } else {
+                                session.title.clone()
+                            }),
+                        ])
+                        .style(style)
+                })
+                .collect::<Vec<_>>()
+        };
+        Paragraph::new(lines).render(layout[0], buf);
+
+        let footer = if let Some(title) = &state.rename {
+            Line::default().spans([
+                Span::styled("Title: ", Style::default().fg(theme.highlight_text)),
+                Span::raw(format!("{title}█")),
+            ])
+        } else if let Some(message) = &state.message {
+            Line::styled(message.clone(), theme.error_style())
+        } else {
+            Line::styled(
+                "Enter - Open | f - Fork | r - Rename | d - Delete | Esc - Close",
+                Style::default().fg(theme.inactive_text),
+            )
+        };
+        Paragraph::new(footer)
+            .alignment(Alignment::Right)
+            .render(layout[1], buf);
+    }
+}
@@ -18,6 +18,7 @@ impl ShortcutsWidget {
             .style(Style::default().bg(theme.background));
         let shortcuts = [
             ("^n", "New Task"),
+            ("^o", "Sessions"),
             ("^p", "Pause/Resume Task"),
             ("⇥", "Change Focus"),
             #[cfg(target_os = "macos")]
@@ -26,6 +27,7 @@ impl ShortcutsWidget {
             ("Alt+[1-4]", "Focus Panel"),
             ("↑↓", "Navigate"),
             ("Enter", "Select"),
+            ("u/U", "Rollback Files/History"),
             ("^e", "Export History"),
             ("^w", "Quit"),
         ];
@@ -5,7 +5,7 @@ use crate::tui::app::TerminalState;
 use crate::tui::Theme;
 use ratatui::buffer::Buffer;
 use ratatui::layout::{Alignment, Margin, Rect};
-use ratatui::style::{Color, Style};
+use ratatui::style::{Color, Modifier, Style};
 use ratatui::text::{Line, Span};
 use ratatui::widgets::{
     Block, BorderType, Borders, Padding, Paragraph, Scrollbar, ScrollbarOrientation,
@@ -14,6 +14,63 @@ use ratatui::widgets::{
 
 pub struct TerminalWidget;
 
+fn screen_color(color: vt100::Color) -> Option<Color> {
+    match color {
+        vt100::Color::Default => None,
+        vt100::Color::Idx(idx) => Some(Color::Indexed(idx)),
+        vt100::Color::Rgb(r, g, b) => Some(Color::Rgb(r, g, b)),
+    }
+}
+
+/// Lines of the emulated terminal screen with colors, trailing empty rows are skipped
+fn screen_lines(screen: &vt100::Screen, theme: &Theme) -> Vec<Line<'static>> {
+    let (rows, cols) = screen.size();
+    let mut lines = (0..rows)
+        .map(|row| {
+            let mut spans: Vec<Span<'static>> = Vec::new();
+            for col in 0..cols {
+                let Some(cell) = screen.cell(row, col) else {
+                    continue;
+                };
+                if cell.is_wide_continuation() {
+                    continue;
+                }
+                let mut style = theme.text_style();
+                if let Some(fg) = screen_color(cell.fgcolor()) {
+                    style = style.fg(fg);
+                }
+                if let Some(bg) = screen_color(cell.bgcolor()) {
+                    style = style.bg(bg);
+                }
+                if cell.bold() {
+                    style = style.add_modifier(Modifier::BOLD);
+                }
+                if cell.inverse() {
+                    style = style.add_modifier(Modifier::REVERSED);
+                }
+                let contents = cell.contents();
+                let contents = if contents.is_empty() {
+                    " ".to_string()
+                } else {
+                    contents.to_string()
+                };
+                match spans.last_mut() {
+                    Some(span) if span.style == style => span.content.to_mut().push_str(&contents),
+                    _ => spans.push(Span::styled(contents, style)),
+                }
+            }
+            Line::from(spans)
+        })
+        .collect::<Vec<_>>();
+    while lines
+        .last()
+        .is_some_and(|line| line.spans.iter().all(|span| span.content.trim().is_empty()))
+    {
+        lines.pop();
+    }
+    lines
+}
+
 impl TerminalWidget {
     #[allow(clippy::too_many_arguments)]
     pub fn render(
@@ -75,7 +132,10 @@ impl TerminalWidget {
                 format!("> {}", state.command.clone().unwrap_or_default()),
                 theme.text_style(),
             ));
-            if !state.output.is_empty() {
+            // the screen of the running command is shown live, the whole output after exit
+            if let Some(screen) = state.screen.as_ref().filter(|_| state.is_active) {
+                terminal_lines.extend(screen_lines(screen, theme));
+            } else if !state.output.is_empty() {
                 let output = state.output.replace("\\n", "\n");
                 output.lines().for_each(|line| {
                     terminal_lines.push(Line::styled(
@@ -0,0 +1,56 @@
+// Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+
+use ratatui::{
+    prelude::*,
+    widgets::{Block, BorderType, Borders, Clear, Padding, Paragraph},
+};
+
+use crate::tui::{app::ToolEditorState, Theme};
+
+/// Editor of the pending tool call arguments, the command is edited as plain text
+pub struct ToolEditorWidget;
+
+impl ToolEditorWidget {
+    pub fn render(self, area: Rect, buf: &mut Buffer, theme: &Theme, state: &mut ToolEditorState) {
+        let area = area.inner(Margin::new(area.width / 8, area.height / 6));
+        Clear.render(area, buf);
+        let block = Block::bordered()
+            .borders(Borders::ALL)
+            .title(format!(
+                " Edit tool call: {} ",
+                state.tool_call.function.name
+            ))
+            .title_alignment(Alignment::Right)
+            .title_style(theme.text_style())
+            .padding(Padding::horizontal(1))
+            .border_type(BorderType::Rounded)
+            .border_style(theme.border_style(true))
+            .style(theme.text_style());
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(1),    // Arguments
+                Constraint::Length(1), // Error or shortcuts
+            ])
+            .split(inner);
+
+        state.textarea.set_style(theme.text_style());
+        state.textarea.set_cursor_line_style(theme.text_style());
+        state.textarea.render(layout[0], buf);
+
+        let footer = if let Some(error) = &state.error {
+            Line::styled(error.clone(), theme.error_style())
+        } else {
+            Line::styled(
+                "^s - Approve edited | Esc - Cancel",
+                Style::default().fg(theme.inactive_text),
+            )
+        };
+        Paragraph::new(footer)
+            .alignment(Alignment::Right)
+            .render(layout[1], buf);
+    }
+}
@@ -0,0 +1,100 @@
+// Copyright © 2025 Huly Labs. Use of this source code is governed by the MIT license.
+
+use ratatui::{
+    prelude::*,
+    widgets::{Block, BorderType, Borders, Clear, Padding, Paragraph, Wrap},
+};
+
+use crate::tui::{app::ToolPreviewState, Theme};
+
+/// Approval prompt of a file tool call with the diff of pending changes
+pub struct ToolPreviewWidget;
+
+impl ToolPreviewWidget {
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        self,
+        area: Rect,
+        buf: &mut Buffer,
+        theme: &Theme,
+        tool_name: &str,
+        tool_info: Line,
+        shortcuts: &str,
+        state: &mut ToolPreviewState,
+    ) {
+        let area = area.inner(Margin::new(area.width / 8, area.height / 8));
+        Clear.render(area, buf);
+        let block = Block::bordered()
+            .borders(Borders::ALL)
+            .title(format!(" Confirm tool execution: {tool_name} "))
+            .title_alignment(Alignment::Right)
+            .title_style(theme.text_style())
+            .padding(Padding::horizontal(1))
+            .border_type(BorderType::Rounded)
+            .border_style(theme.border_style(true))
+            .style(theme.text_style());
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let warning_height = state
+            .warning
+            .as_ref()
+            .map(|warning| {
+                textwrap::wrap(warning, inner.width.max(1) as usize)
+                    .len()
+                    .min(5) as u16
+            })
+            .unwrap_or(0);
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(1),              // Tool info
+                Constraint::Length(warning_height), // Warning
+                Constraint::Min(1),                 // Diff
+                Constraint::Length(1),              // Shortcuts
+            ])
+            .split(inner);
+
+        Paragraph::new(tool_info).render(layout[0], buf);
+        if let Some(warning) = &state.warning {
+            Paragraph::new(warning.as_str())
+                .style(theme.error_style())
+                .wrap(Wrap { trim: false })
+                .render(layout[1], buf);
+        }
+
+        let lines = state
+            .diff
+            .lines()
+            .map(|line| {
+                let style = if line.starts_with('+') {
+                    Style::default().fg(theme.success)
+                } else if line.starts_with('-') {
+                    Style::default().fg(theme.error)
+                } else if line.starts_with("@@") {
+                    Style::default().fg(theme.highlight_text)
+                } else {
+                    theme.text_style()
+                };
+                Line::styled(line.to_string(), style)
+            })
+            .collect::<Vec<_>>();
+        let max_scroll = (lines.len() as u16).saturating_sub(layout[2].height);
+        state.scroll = state.scroll.min(max_scroll);
+        Paragraph::new(lines)
+            .scroll((state.scroll, 0))
+            .render(layout[2], buf);
+
+        let shortcuts = if max_scroll > 0 {
+            format!("↑↓ - Scroll | {}", shortcuts.trim())
+        } else {
+            shortcuts.trim().to_string()
+        };
+        Paragraph::new(Line::styled(
+            shortcuts,
+            Style::default().fg(theme.inactive_text),
+        ))
+        .alignment(Alignment::Right)
+        .render(layout[3], buf);
+    }
+}